@@ -4,7 +4,8 @@ set -ex
 TARGET="$HOME/splitgraph.github.io/"
 
 echo "Creating a production build"
-trunk build --release --public-url socrata-roulette/
+# With the SQL parse check, so that broken queries never reach the Splitgraph embed
+trunk build --release --features sql-check --public-url socrata-roulette/
 DIST="$PWD/dist"
 
 echo "Copying"
@@ -12,7 +12,9 @@ pub fn dataset_info(DatasetInfoProps { state }: &DatasetInfoProps) -> Html {
         <div class={classes!("w-full", "h-full", "my-8")}>
             {
                 match state {
-                    ComponentQueryState::None | ComponentQueryState::GeneratingQuery => html! {},
+                    ComponentQueryState::None
+                    | ComponentQueryState::GeneratingQuery
+                    | ComponentQueryState::Failed(_) => html! {},
                     ComponentQueryState::Ready(q) => {
                         let domain_url = format!("https://{:}", q.dataset_domain);
                         let splitgraph_url = format!("https://www.splitgraph.com/{:}/{:}", q.sg_namespace, q.sg_repository);
@@ -22,13 +24,33 @@ pub fn dataset_info(DatasetInfoProps { state }: &DatasetInfoProps) -> Html {
 
                         html! {
                             <>
-                            <h3 class={classes!("text-2xl", "font-bold", "mt-0", "mb-8", "text-slate-200")}>{ q.dataset_name.clone() }</h3>
+                            {
+                                match &q.title {
+                                    Some(title) => html! {
+                                        <>
+                                        <h2 class={classes!("text-3xl", "font-bold", "mt-0", "mb-2", "text-slate-100")}>{ title.clone() }</h2>
+                                        <h3 class={classes!("text-xl", "mt-0", "mb-8", "text-slate-300")}>{ q.dataset_name.clone() }</h3>
+                                        </>
+                                    },
+                                    None => html! {
+                                        <h3 class={classes!("text-2xl", "font-bold", "mt-0", "mb-8", "text-slate-200")}>{ q.dataset_name.clone() }</h3>
+                                    },
+                                }
+                            }
 
                             <div class={classes!("grid", "grid-cols-1", "sm:grid-cols-3", "gap-4", "text-center")}>
                                 <a href={ splitgraph_url } target={ "_blank" } class={classes!("bg-slate-300", "hover:bg-slate-400", "py-2", "px-4", "rounded")}>{ "View on Splitgraph" }</a>
                                 <a href={ domain_url } target={ "_blank" } class={classes!("bg-slate-300", "hover:bg-slate-400", "py-2", "px-4", "rounded")}>{ q.dataset_domain.clone() }</a>
                                 <a href={ dataset_url } target={ "_blank" } class={classes!("bg-slate-300", "hover:bg-slate-400", "py-2", "px-4", "rounded")}>{ socrata_button_text }</a>
                             </div>
+                            {
+                                match q.seed {
+                                    Some(seed) => html! {
+                                        <p class={classes!("text-sm", "text-gray-500", "mt-4")}>{ "Seed: " }<a class={classes!("text-blue-500", "hover:underline")} href={ format!("?seed={:}", seed) }>{ seed }</a></p>
+                                    },
+                                    None => html! {},
+                                }
+                            }
                             </>
                         }
                     }
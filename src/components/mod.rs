@@ -1,5 +1,8 @@
 pub mod dataset_info;
+pub mod parsed_query;
 pub mod query_button;
 pub mod query_embed;
+pub mod query_explanation;
 pub mod query_state;
+pub mod query_tweaks;
 pub mod random_query;
@@ -0,0 +1,50 @@
+use crate::components::query_state::{ComponentQueryState, QueryState};
+use crate::ddn::{get_dataset, SplitgraphDDNSyntax};
+use crate::query_gen::parser::parse_query;
+use crate::query_gen::query::Query;
+use std::rc::Rc;
+use yew::prelude::*;
+
+/// The query in the URL, parsed back into a `Query`. That needs its dataset, so this is None
+/// until the dataset has been fetched (and stays None if the query doesn't parse).
+#[hook]
+pub fn use_parsed_query(state: &ComponentQueryState) -> Option<Rc<Query<SplitgraphDDNSyntax>>> {
+    let current = match state {
+        ComponentQueryState::Ready(q) => Some((**q).clone()),
+        ComponentQueryState::None
+        | ComponentQueryState::GeneratingQuery
+        | ComponentQueryState::Failed(_) => None,
+    };
+    // Remember which query we parsed, so that we don't show an outdated one while the next
+    // dataset is loading
+    let parsed = use_state(|| None::<(QueryState, Rc<Query<SplitgraphDDNSyntax>>)>);
+
+    {
+        let parsed = parsed.clone();
+        use_effect_with_deps(
+            move |current: &Option<QueryState>| {
+                if let Some(q) = current.clone() {
+                    wasm_bindgen_futures::spawn_local(async move {
+                        let dataset = match get_dataset(&q.dataset_domain, &q.dataset_id).await {
+                            Some(dataset) => dataset,
+                            None => return,
+                        };
+                        let syntax = SplitgraphDDNSyntax {
+                            repository: q.splitgraph_info(),
+                        };
+                        if let Ok(query) = parse_query(&q.query, &dataset, syntax) {
+                            parsed.set(Some((q, Rc::new(query))));
+                        }
+                    });
+                }
+                || ()
+            },
+            current.clone(),
+        );
+    }
+
+    match (&*parsed, &current) {
+        (Some((q, query)), Some(current)) if q == current => Some(query.clone()),
+        _ => None,
+    }
+}
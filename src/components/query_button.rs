@@ -18,7 +18,7 @@ pub fn random_query_button(
         ComponentQueryState::GeneratingQuery => html! {
             <button class={classes!("bg-slate-300", "hover:bg-slate-400", "font-bold", "py-2", "px-4", "rounded", "opacity-50", "cursor-not-allowed")}>{ "Generating..." }</button>
         },
-        ComponentQueryState::Ready(_) => html! {
+        ComponentQueryState::Ready(_) | ComponentQueryState::Failed(_) => html! {
             <button class={classes!("bg-slate-300", "hover:bg-slate-400", "font-bold", "py-2", "px-4", "rounded")} {onclick}>{ "Another!" }</button>
         },
     }
@@ -21,6 +21,12 @@ pub fn splitgraph_embed_query(
                     ComponentQueryState::GeneratingQuery => html! {
                         <span class={classes!("inline-block", "align-middle", "font-bold")}>{"Please wait..."}</span>
                     },
+                    ComponentQueryState::Failed(error) => html! {
+                        <div class={classes!("text-center", "p-4")}>
+                            <p class={classes!("font-bold")}>{"Couldn't generate a valid query, please try another one"}</p>
+                            <p class={classes!("text-sm", "text-gray-500", "mt-4")}>{ error.clone() }</p>
+                        </div>
+                    },
                     ComponentQueryState::Ready(q) => {
                         let iframe_target = format!(
                             "https://www.splitgraph.com/embed/workspace/ddn?layout=hsplit&query={:}",
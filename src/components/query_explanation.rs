@@ -0,0 +1,49 @@
+use crate::ddn::SplitgraphDDNSyntax;
+use crate::query_gen::explain::explain_query;
+use crate::query_gen::query::Query;
+use std::rc::Rc;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct QueryExplanationProps {
+    // None until the query in the URL has been parsed
+    pub query: Option<Rc<Query<SplitgraphDDNSyntax>>>,
+}
+
+#[function_component(QueryExplanation)]
+pub fn query_explanation(QueryExplanationProps { query }: &QueryExplanationProps) -> Html {
+    let explanation = match query {
+        Some(query) => explain_query(query),
+        // Still loading the dataset, or not a query we can parse
+        None => return html! {},
+    };
+
+    html! {
+        <details class={classes!("w-full", "my-8", "rounded-sm", "bg-slate-800", "text-slate-200", "p-4")}>
+            <summary class={classes!("cursor-pointer", "font-bold")}>{ "Explain this query" }</summary>
+            {
+                for explanation.iter().map(|clause| html! {
+                    <div class={classes!("mt-4")}>
+                        <p class={classes!("font-bold")}>{ clause.summary.clone() }</p>
+                        <ul class={classes!("list-disc", "ml-6")}>
+                            {
+                                for clause.items.iter().map(|item| html! {
+                                    <li>
+                                        { item.text.clone() }
+                                        {
+                                            for item.columns.iter().map(|column| html! {
+                                                <span class={classes!("ml-2", "px-2", "rounded", "text-sm", "bg-slate-600", "text-slate-300")}>
+                                                    { format!("{:}: {:}", column.human_name, column.data_type) }
+                                                </span>
+                                            })
+                                        }
+                                    </li>
+                                })
+                            }
+                        </ul>
+                    </div>
+                })
+            }
+        </details>
+    }
+}
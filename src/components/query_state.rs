@@ -1,4 +1,5 @@
 use crate::ddn::SplitgraphInfo;
+use crate::query_gen::title::query_title;
 use crate::{Dataset, Query, Syntax};
 use serde_derive::{Deserialize, Serialize};
 
@@ -10,13 +11,28 @@ pub struct QueryState {
     pub dataset_domain: String,
     pub sg_namespace: String,
     pub sg_repository: String,
+    // Older URLs don't have the seed in them
+    #[serde(default)]
+    pub seed: Option<u64>,
+    // Same for the title
+    #[serde(default)]
+    pub title: Option<String>,
 }
 
 impl QueryState {
+    pub fn splitgraph_info(&self) -> SplitgraphInfo {
+        SplitgraphInfo {
+            namespace: self.sg_namespace.clone(),
+            repository: self.sg_repository.clone(),
+        }
+    }
+
     pub fn from_query_dataset<T: Syntax>(
         query: &Query<T>,
         dataset: &Dataset,
         splitgraph: &SplitgraphInfo,
+        // Tweaked queries can't be reproduced from a seed
+        seed: Option<u64>,
     ) -> Self {
         Self {
             query: query.to_sql(),
@@ -25,20 +41,31 @@ impl QueryState {
             dataset_domain: dataset.domain.clone(),
             sg_namespace: splitgraph.namespace.clone(),
             sg_repository: splitgraph.repository.clone(),
+            seed,
+            title: Some(query_title(query)),
         }
     }
 }
 
+/// A URL with just a seed in it (`?seed=1234`), to get the query that seed generates
+#[derive(Deserialize)]
+pub struct SeedState {
+    pub seed: u64,
+}
+
 #[derive(Clone, Eq, PartialEq)]
 pub enum QueryGenerationState {
     None,
     GeneratingQuery,
     Ready,
+    // What went wrong
+    Failed(String),
 }
 
 #[derive(Clone, Eq, PartialEq)]
 pub enum ComponentQueryState {
     None,
     GeneratingQuery,
-    Ready(QueryState),
+    Ready(Box<QueryState>),
+    Failed(String),
 }
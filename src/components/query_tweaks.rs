@@ -0,0 +1,48 @@
+use crate::components::query_state::ComponentQueryState;
+use crate::ddn::SplitgraphDDNSyntax;
+use crate::query_gen::mutate::Mutation;
+use crate::query_gen::query::Query;
+use std::rc::Rc;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct QueryTweaksProps {
+    pub state: ComponentQueryState,
+    // None until the query in the URL has been parsed
+    pub query: Option<Rc<Query<SplitgraphDDNSyntax>>>,
+    pub on_tweak: Callback<Mutation>,
+}
+
+#[function_component(QueryTweaks)]
+pub fn query_tweaks(
+    QueryTweaksProps {
+        state,
+        query,
+        on_tweak,
+    }: &QueryTweaksProps,
+) -> Html {
+    // Only offer the tweaks that can change the query (none until we've parsed it)
+    let applies = |mutation: &Mutation| query.as_ref().is_some_and(|q| mutation.applies_to(q));
+
+    match state {
+        ComponentQueryState::None
+        | ComponentQueryState::GeneratingQuery
+        | ComponentQueryState::Failed(_) => html! {},
+        ComponentQueryState::Ready(_) => html! {
+            <div class={classes!("w-full", "my-8")}>
+                <h4 class={classes!("text-xl", "font-bold", "mt-0", "mb-4", "text-slate-200")}>{ "More like this" }</h4>
+                <div class={classes!("grid", "grid-cols-2", "sm:grid-cols-3", "gap-4", "text-center")}>
+                    {
+                        for Mutation::ALL.iter().map(|mutation| {
+                            let mutation = *mutation;
+                            let onclick = on_tweak.reform(move |_: MouseEvent| mutation);
+                            html! {
+                                <button onclick={onclick} disabled={ !applies(&mutation) } class={classes!("bg-slate-300", "hover:bg-slate-400", "disabled:opacity-50", "disabled:hover:bg-slate-300", "py-2", "px-4", "rounded")}>{ mutation.label() }</button>
+                            }
+                        })
+                    }
+                </div>
+            </div>
+        },
+    }
+}
@@ -3,19 +3,30 @@ use yew_router::history::HistoryResult;
 use yew_router::prelude::use_location;
 
 use crate::components::dataset_info::DatasetInfo;
+use crate::components::parsed_query::use_parsed_query;
 use crate::components::query_button::RandomQueryButton;
 use crate::components::query_embed::SplitgraphEmbedQuery;
+use crate::components::query_explanation::QueryExplanation;
 use crate::components::query_state::ComponentQueryState;
+use crate::components::query_tweaks::QueryTweaks;
+use crate::query_gen::mutate::Mutation;
 use crate::{QueryGenerationState, QueryState};
 
 #[derive(Properties, PartialEq)]
 pub struct RandomQueryProps {
     pub onclick: Callback<MouseEvent>,
+    pub on_tweak: Callback<Mutation>,
     pub state: QueryGenerationState,
 }
 
 #[function_component(RandomQuery)]
-pub fn random_query(RandomQueryProps { onclick, state }: &RandomQueryProps) -> Html {
+pub fn random_query(
+    RandomQueryProps {
+        onclick,
+        on_tweak,
+        state,
+    }: &RandomQueryProps,
+) -> Html {
     // Compute the component state based on the state that was passed to us and the
     // current location (that may contain the query)
     let location = use_location().unwrap();
@@ -25,18 +36,23 @@ pub fn random_query(RandomQueryProps { onclick, state }: &RandomQueryProps) -> H
         // On first load, if we managed to deserialize the location, pass the query to the children
         (QueryGenerationState::None, Ok(qs)) |
         // Same if we actually managed to generate the query
-        (QueryGenerationState::Ready, Ok(qs))  => ComponentQueryState::Ready(qs),
+        (QueryGenerationState::Ready, Ok(qs))  => ComponentQueryState::Ready(Box::new(qs)),
         (QueryGenerationState::GeneratingQuery, _) => ComponentQueryState::GeneratingQuery,
+        (QueryGenerationState::Failed(error), _) => ComponentQueryState::Failed(error.clone()),
         (_, Err(_)) => ComponentQueryState::None,
     };
+    // Parsed once here, since both the explanation and the tweaks need it
+    let query = use_parsed_query(&component_state);
 
     html! {
         <div class={classes!("my-4")}>
             <div class={classes!("text-center")}>
                 <RandomQueryButton onclick={onclick} state={ component_state.clone() } />
             </div>
-            <SplitgraphEmbedQuery state={ component_state.clone() } />
             <DatasetInfo state={ component_state.clone() } />
+            <SplitgraphEmbedQuery state={ component_state.clone() } />
+            <QueryExplanation query={ query.clone() } />
+            <QueryTweaks state={ component_state.clone() } query={ query } on_tweak={ on_tweak } />
         </div>
     }
 }
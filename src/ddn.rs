@@ -1,7 +1,16 @@
-use crate::query_gen::query::{build_dimensions, build_measures, random_query, Query, Syntax};
+use crate::query_gen::cost::estimate_relative_cost;
+use crate::query_gen::mutate::{mutate, Mutation};
+use crate::query_gen::parser::parse_query;
+use crate::query_gen::query::{
+    build_dimensions, build_measures, random_query, random_top_n_per_group_query,
+    DimensionTransform, MeasureType, Query, RandomQueryParams, SqlDialect, Syntax,
+};
+use crate::query_gen::scoring::DefaultScorer;
+use crate::query_gen::shapes::{default_shapes, random_shaped_query, ShapeContext};
 use crate::query_gen::socrata::{parse_dataset, Column, Dataset, RawDatasetResource};
 use gloo_net::http::Request;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
 
@@ -41,6 +50,13 @@ struct GQLGetSplitgraphRepoVariables {
     name: String,
 }
 
+// Queries that cost more than this per row of the dataset (see `estimate_relative_cost`) tend
+// to time out on Socrata
+const COST_BUDGET: f64 = 10.0;
+
+// How many times to try a mutation before giving up on it
+const TWEAK_ATTEMPTS: usize = 5;
+
 const SOCRATA_REPO_QUERY: &str = r#"
 query getSocrataRepo($id: String!, $domain: String!, $name: String!) {
     socrataExternalRepositories (datasets:
@@ -50,7 +66,7 @@ query getSocrataRepo($id: String!, $domain: String!, $name: String!) {
     }
 }"#;
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, PartialEq, Eq)]
 pub struct SplitgraphInfo {
     pub namespace: String,
     pub repository: String,
@@ -62,11 +78,42 @@ struct GQLGetSplitgraphRepoData {
     socrata_external_repositories: Vec<SplitgraphInfo>,
 }
 
-pub async fn get_random_dataset() -> Dataset {
-    let mut rng = rand::thread_rng();
-    let cache_bust: u32 = rng.gen();
+pub async fn get_random_dataset<R: Rng>(rng: &mut R) -> Option<Dataset> {
+    // Shuffle the catalog by hashing every dataset with a salt from the seed instead of
+    // using ORDER BY random(), so that the same seed gives us the same dataset (as long as
+    // the catalog doesn't change)
+    let salt: u64 = rng.gen();
+    let request = DDNGetDatasetRequest {
+        sql: format!("SELECT * FROM \"splitgraph/socrata\".datasets ORDER BY md5(domain || '/' || (resource->>'id') || '/{salt}') LIMIT 1"),
+    };
+
+    let result = Request::post("https://data.splitgraph.com/sql/query/ddn")
+        .json(&request)
+        .ok()?
+        .send()
+        .await
+        .ok()?;
+
+    let parsed_response = result.json::<DDNGetDatasetResponse>().await.ok()?;
+    if !parsed_response.success {
+        return None;
+    }
+
+    parsed_response
+        .rows
+        .first()
+        .map(|row| parse_dataset(&row.domain, &row.resource))
+}
 
-    let request = DDNGetDatasetRequest { sql: format!("SELECT * FROM \"splitgraph/socrata\".datasets WHERE {cache_bust} = {cache_bust} ORDER BY random() DESC LIMIT 1")};
+/// Fetch a specific dataset from the catalog
+pub async fn get_dataset(domain: &str, socrata_id: &str) -> Option<Dataset> {
+    let request = DDNGetDatasetRequest {
+        sql: format!(
+            "SELECT * FROM \"splitgraph/socrata\".datasets WHERE domain = '{:}' AND resource->>'id' = '{:}'",
+            domain.replace('\'', "''"),
+            socrata_id.replace('\'', "''")
+        ),
+    };
 
     let result = Request::post("https://data.splitgraph.com/sql/query/ddn")
         .json(&request)
@@ -78,9 +125,10 @@ pub async fn get_random_dataset() -> Dataset {
     let parsed_response = result.json::<DDNGetDatasetResponse>().await.unwrap();
     assert!(parsed_response.success);
 
-    let row = parsed_response.rows.first().unwrap();
-
-    parse_dataset(&row.domain, &row.resource)
+    parsed_response
+        .rows
+        .first()
+        .map(|row| parse_dataset(&row.domain, &row.resource))
 }
 
 pub async fn get_dataset_namespace_repository(dataset: &Dataset) -> SplitgraphInfo {
@@ -114,8 +162,9 @@ pub async fn get_dataset_namespace_repository(dataset: &Dataset) -> SplitgraphIn
         .clone()
 }
 
+#[derive(Clone, PartialEq, Eq)]
 pub struct SplitgraphDDNSyntax {
-    repository: SplitgraphInfo,
+    pub(crate) repository: SplitgraphInfo,
 }
 
 pub fn slugify_table(table: &str) -> String {
@@ -142,6 +191,11 @@ pub fn slugify_table(table: &str) -> String {
 }
 
 impl Syntax for SplitgraphDDNSyntax {
+    fn get_sql_dialect(&self) -> Option<SqlDialect> {
+        // The DDN speaks the PostgreSQL wire protocol and dialect
+        Some(SqlDialect::PostgreSql)
+    }
+
     fn get_dataset_sql(self: &SplitgraphDDNSyntax, dataset: &Dataset) -> String {
         format!(
             "\"{:}/{:}\".\"{:}\"",
@@ -153,37 +207,154 @@ impl Syntax for SplitgraphDDNSyntax {
 
     fn get_column_sql(self: &SplitgraphDDNSyntax, column: &Column) -> String {
         // TODO: fetch real Splitgraph columns and map them
-        format!("\"{:}\"", column.pg_name.replace('\"', "\"\""))
+        self.get_identifier_sql(&column.pg_name)
+    }
+
+    fn can_represent_identifier(self: &SplitgraphDDNSyntax, identifier: &str) -> bool {
+        // We quote everything
+        !identifier.is_empty()
+    }
+
+    fn get_identifier_sql(self: &SplitgraphDDNSyntax, identifier: &str) -> String {
+        format!("\"{:}\"", identifier.replace('\"', "\"\""))
+    }
+
+    fn supports_measure_type(self: &SplitgraphDDNSyntax, measure_type: &MeasureType) -> bool {
+        // Without the real Splitgraph columns, we can't tell if geo columns are PostGIS
+        // geometries
+        !matches!(
+            measure_type,
+            MeasureType::Extent | MeasureType::Centroid | MeasureType::CountWithinRadius { .. }
+        )
+    }
+
+    fn supports_dimension_transform(
+        self: &SplitgraphDDNSyntax,
+        transform: &DimensionTransform,
+    ) -> bool {
+        !matches!(transform, DimensionTransform::SnapToGrid { .. })
     }
 }
 
+/// Generate a random query on a random dataset. The same seed will produce the same dataset
+/// and query, as long as the Splitgraph catalog stays the same. Fails if the query still has
+/// problems (e.g. SQL that doesn't parse, with the `sql-check` feature), so that it doesn't
+/// get sent to Splitgraph.
 pub async fn get_random_query_on_random_data(
-) -> (Query<SplitgraphDDNSyntax>, Dataset, SplitgraphInfo) {
-    let dataset = get_random_dataset().await;
+    seed: u64,
+) -> Result<(Query<SplitgraphDDNSyntax>, Dataset, SplitgraphInfo), String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let dataset = get_random_dataset(&mut rng)
+        .await
+        .ok_or_else(|| "Couldn't get a random dataset from the catalog".to_string())?;
 
     let namespace_repository = get_dataset_namespace_repository(&dataset).await;
 
     let measures = build_measures(&dataset);
     let dimensions = build_dimensions(&dataset);
+    let syntax = SplitgraphDDNSyntax {
+        repository: namespace_repository.clone(),
+    };
 
-    let query = random_query(
-        &dataset,
-        &measures,
-        &dimensions,
-        1..3,
-        1..4,
-        0..3,
-        SplitgraphDDNSyntax {
-            repository: namespace_repository.clone(),
-        },
-    );
+    // Sometimes, get the top N items per group instead of a flat aggregation
+    let top_n_query = if rng.gen_ratio(1, 5) {
+        random_top_n_per_group_query(
+            &mut rng,
+            &dataset,
+            &measures,
+            &dimensions,
+            &DefaultScorer {},
+            syntax.clone(),
+        )
+        .filter(|q| estimate_relative_cost(q) <= COST_BUDGET)
+    } else {
+        None
+    };
+
+    // Or one of the well-known query shapes (a trend, a histogram...), if the dataset has
+    // the columns for any of them
+    let shaped_query = if top_n_query.is_none() && rng.gen_ratio(1, 3) {
+        random_shaped_query(
+            &mut rng,
+            &default_shapes(),
+            &ShapeContext {
+                dataset: &dataset,
+                measures: &measures,
+                dimensions: &dimensions,
+                scorer: &DefaultScorer {},
+            },
+            syntax.clone(),
+        )
+        .filter(|q| estimate_relative_cost(q) <= COST_BUDGET)
+    } else {
+        None
+    };
+
+    let query = top_n_query.or(shaped_query).unwrap_or_else(|| {
+        random_query(
+            &mut rng,
+            &dataset,
+            &measures,
+            &dimensions,
+            &RandomQueryParams {
+                no_dimensions: 1..3,
+                no_measures: 1..4,
+                no_order_bys: 0..3,
+                no_havings: 0..2,
+                no_window_measures: 0..2,
+                no_filters: 0..3,
+                max_cost: Some(COST_BUDGET),
+                scorer: Box::new(DefaultScorer {}),
+            },
+            syntax,
+        )
+    });
+
+    let diagnostics = query.validate();
+    if !diagnostics.is_empty() {
+        return Err(format!("{diagnostics:?}"));
+    }
 
-    (query, dataset, namespace_repository)
+    Ok((query, dataset, namespace_repository))
+}
+
+/// Apply a mutation to a query we generated earlier (and only have the SQL of), so that
+/// the user can get something similar to it instead of a completely new query
+pub async fn tweak_query(
+    sql: &str,
+    domain: &str,
+    socrata_id: &str,
+    splitgraph: &SplitgraphInfo,
+    mutation: Mutation,
+    seed: u64,
+) -> Option<(Query<SplitgraphDDNSyntax>, Dataset)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let dataset = get_dataset(domain, socrata_id).await?;
+    let syntax = SplitgraphDDNSyntax {
+        repository: splitgraph.clone(),
+    };
+
+    let query = parse_query(sql, &dataset, syntax).ok()?;
+
+    // Mutations are random, so a failed one (e.g. a filter that makes the query too
+    // expensive) can work out on the next try
+    let tweaked = (0..TWEAK_ATTEMPTS).find_map(|_| {
+        mutate(&mut rng, &query, mutation, &DefaultScorer {})
+            .filter(|q| estimate_relative_cost(q) <= COST_BUDGET)
+    })?;
+    Some((tweaked, dataset))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::ddn::slugify_table;
+    use crate::ddn::{slugify_table, SplitgraphDDNSyntax, SplitgraphInfo};
+    use crate::query_gen::parser::parse_query;
+    use crate::query_gen::query::{build_dimensions, build_measures, random_query};
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn test_slugify_table() {
@@ -193,4 +364,38 @@ mod tests {
         );
         assert_eq!(slugify_table("Some Table"), "some_table");
     }
+
+    #[test]
+    fn test_parse_query_round_trip() {
+        let dataset = edmonton_dataset();
+
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+        let syntax = SplitgraphDDNSyntax {
+            repository: SplitgraphInfo {
+                namespace: "edmonton".to_string(),
+                repository: "u7r4-acwa".to_string(),
+            },
+        };
+
+        // Make sure we can read our own quoted identifiers
+        for seed in 0..50 {
+            let query = random_query(
+                &mut StdRng::seed_from_u64(seed),
+                &dataset,
+                &measures,
+                &dimensions,
+                &default_params(),
+                syntax.clone(),
+            );
+            let sql = query.to_sql();
+
+            assert_eq!(
+                parse_query(&sql, &dataset, syntax.clone())
+                    .unwrap()
+                    .to_sql(),
+                sql
+            );
+        }
+    }
 }
@@ -3,13 +3,14 @@ mod ddn;
 mod query_gen;
 
 use crate::components::random_query::RandomQuery;
-use crate::ddn::get_random_query_on_random_data;
+use crate::ddn::{get_random_query_on_random_data, tweak_query};
 use crate::query_gen::socrata::Dataset;
 use std::collections::HashMap;
 
 use crate::components::query_state::QueryState;
 use crate::query_gen::query::{Query, Syntax};
-use components::query_state::QueryGenerationState;
+use components::query_state::{QueryGenerationState, SeedState};
+use rand::Rng;
 use yew::prelude::*;
 use yew_router::prelude::*;
 use yew_router::AnyRoute;
@@ -18,29 +19,105 @@ use yew_router::AnyRoute;
 fn main_app() -> Html {
     let query_gen_state = use_state(|| QueryGenerationState::None);
 
+    // Generate a query from a seed and put it in the URL
+    let generate = {
+        let query_gen_state = query_gen_state.clone();
+        let navigator = use_navigator().unwrap();
+        Callback::from(move |seed: u64| {
+            let query_gen_state = query_gen_state.clone();
+            let navigator = navigator.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                query_gen_state.set(QueryGenerationState::GeneratingQuery);
+                match get_random_query_on_random_data(seed).await {
+                    Ok((query, dataset, splitgraph)) => {
+                        let query_state = QueryState::from_query_dataset(
+                            &query,
+                            &dataset,
+                            &splitgraph,
+                            Some(seed),
+                        );
+                        let params: HashMap<&str, &str> = HashMap::new();
+                        let route = AnyRoute::from_path("", &params).unwrap();
+                        navigator.push_with_query(&route, &query_state).unwrap();
+
+                        query_gen_state.set(QueryGenerationState::Ready)
+                    }
+                    Err(error) => query_gen_state.set(QueryGenerationState::Failed(format!(
+                        "Seed {seed}: {error}"
+                    ))),
+                }
+            });
+        })
+    };
+
     let onclick = {
+        let generate = generate.clone();
+        Callback::from(move |_| generate.emit(rand::thread_rng().gen()))
+    };
+
+    // Reproduce a query from just its seed (`?seed=1234`, e.g. from the seed under a query)
+    {
+        let location = use_location().unwrap();
+        use_effect_with_deps(
+            move |_| {
+                if location.query::<QueryState>().is_err() {
+                    if let Ok(SeedState { seed }) = location.query::<SeedState>() {
+                        generate.emit(seed);
+                    }
+                }
+                || ()
+            },
+            (),
+        );
+    }
+
+    // Change the current query a little instead of generating a new one
+    let on_tweak = {
         let query_gen_state = query_gen_state.clone();
         let navigator = use_navigator().unwrap();
-        Callback::from(move |_| {
+        let current: Option<QueryState> = use_location().unwrap().query().ok();
+        Callback::from(move |mutation| {
+            let current = match &current {
+                Some(c) => c.clone(),
+                None => return,
+            };
             let query_gen_state = query_gen_state.clone();
             let navigator = navigator.clone();
             wasm_bindgen_futures::spawn_local(async move {
                 query_gen_state.set(QueryGenerationState::GeneratingQuery);
-                let (query, dataset, splitgraph) = get_random_query_on_random_data().await;
+                let seed: u64 = rand::thread_rng().gen();
+                let splitgraph = current.splitgraph_info();
+                let tweaked = tweak_query(
+                    &current.query,
+                    &current.dataset_domain,
+                    &current.dataset_id,
+                    &splitgraph,
+                    mutation,
+                    seed,
+                )
+                .await;
 
-                let query_state = QueryState::from_query_dataset(&query, &dataset, &splitgraph);
-                let params: HashMap<&str, &str> = HashMap::new();
-                let route = AnyRoute::from_path("", &params).unwrap();
-                navigator.push_with_query(&route, &query_state).unwrap();
+                match tweaked {
+                    Some((query, dataset)) => {
+                        let query_state =
+                            QueryState::from_query_dataset(&query, &dataset, &splitgraph, None);
+                        let params: HashMap<&str, &str> = HashMap::new();
+                        let route = AnyRoute::from_path("", &params).unwrap();
+                        navigator.push_with_query(&route, &query_state).unwrap();
 
-                query_gen_state.set(QueryGenerationState::Ready)
+                        query_gen_state.set(QueryGenerationState::Ready)
+                    }
+                    None => query_gen_state.set(QueryGenerationState::Failed(format!(
+                        "Couldn't apply \"{:}\" to this query",
+                        mutation.label()
+                    ))),
+                }
             });
         })
     };
 
     // TODO: some error handling
     // TODO: factor some styles out
-    // TODO: add a way to generate a nice query name
     // TODO: easier way to copy the URL
     //       (grab more stuff out of the Socrata result, incl. the real original URL)
 
@@ -51,7 +128,7 @@ fn main_app() -> Html {
                     <h1 class={classes!("text-5xl", "font-bold", "mt-0", "mb-6", "text-slate-200")}>{ "Socrata Roulette" }</h1>
                     <h3 class={classes!("text-2xl", "font-bold", "mt-0", "mb-8", "text-slate-200")}>{ "Run a random SQL query on a random open government dataset" }</h3>
                 </section>
-                <RandomQuery onclick={onclick} state={ (*query_gen_state).clone() } />
+                <RandomQuery onclick={onclick} on_tweak={on_tweak} state={ (*query_gen_state).clone() } />
             </div>
             <footer class={classes!("mt-auto", "border-t", "shadow", "md:flex", "md:items-center", "md:justify-between", "p-2", "md:p-6", "bg-slate-800", "border-slate-600")}>
                 <div class={classes!("container", "mx-auto", "max-w-3xl")}>
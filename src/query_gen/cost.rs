@@ -0,0 +1,468 @@
+use crate::query_gen::query::{
+    random_filter, Binning, ComparisonOperator, DatePart, DateTruncUnit, Dimension,
+    DimensionTransform, Filter, MeasureType, Query, QuerySource, Syntax,
+};
+use crate::query_gen::socrata::DataType;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+// Rows we assume a dataset has if the catalog doesn't say
+const DEFAULT_ROW_COUNT: f64 = 100_000.0;
+// Distinct values we assume a text column has. Most of the time, these are categories,
+// but sometimes they're free text and every row is different.
+const DEFAULT_TEXT_CARDINALITY: f64 = 1_000.0;
+// Distinct values we assume a number or a timestamp has
+const DEFAULT_CONTINUOUS_CARDINALITY: f64 = 10_000.0;
+// Default bucket count for binnings that don't have a fixed one
+const DEFAULT_BIN_CARDINALITY: f64 = 20.0;
+// Grid cells we assume the points of a dataset fall into
+const DEFAULT_GRID_CARDINALITY: f64 = 1_000.0;
+// What `reduce_cost` lowers the LIMIT of a sorted query to: a top 10 is still worth looking at
+const REDUCED_LIMIT: usize = 10;
+// Filters that `reduce_cost` adds have to keep at most this share of rows
+const REDUCING_SELECTIVITY: f64 = 0.3;
+
+/// Rough number of groups a dimension splits the dataset into
+pub fn estimate_dimension_cardinality(dimension: &Dimension) -> f64 {
+    match &dimension.transform {
+        Some(DimensionTransform::DateTrunc(unit)) => match unit {
+            // Assume around 10 years of data
+            DateTruncUnit::Day => 3_650.0,
+            DateTruncUnit::Week => 520.0,
+            DateTruncUnit::Month => 120.0,
+            DateTruncUnit::Quarter => 40.0,
+            DateTruncUnit::Year => 10.0,
+        },
+        Some(DimensionTransform::Extract(part)) => match part {
+            DatePart::Year => 10.0,
+            DatePart::Month => 12.0,
+            DatePart::DayOfWeek => 7.0,
+            DatePart::Hour => 24.0,
+        },
+        // Plus the two buckets for out-of-range values
+        Some(DimensionTransform::Bin(Binning::Range { count, .. })) => *count as f64 + 2.0,
+        Some(DimensionTransform::Bin(_)) => DEFAULT_BIN_CARDINALITY,
+        Some(DimensionTransform::SnapToGrid { .. }) => DEFAULT_GRID_CARDINALITY,
+        None => match dimension.column.stats.cardinality {
+            // The statistics know better than we do
+            Some(cardinality) => cardinality as f64,
+            None => match dimension.column.data_type {
+                DataType::Checkbox => 2.0,
+                DataType::Text | DataType::Url => DEFAULT_TEXT_CARDINALITY,
+                _ => DEFAULT_CONTINUOUS_CARDINALITY,
+            },
+        },
+    }
+}
+
+/// Rough share of rows (0-1) that pass a filter
+pub fn estimate_filter_selectivity(filter: &Filter) -> f64 {
+    match filter {
+        Filter::Comparison { operator, .. } => match operator {
+            ComparisonOperator::Eq => 0.1,
+            ComparisonOperator::NotEq => 0.9,
+            _ => 0.5,
+        },
+        Filter::In { values, .. } => (0.1 * values.len() as f64).min(1.0),
+        Filter::Between { .. } => 0.3,
+        Filter::IsNull { negated, .. } => {
+            if *negated {
+                0.9
+            } else {
+                0.1
+            }
+        }
+        Filter::Like { .. } => 0.2,
+        Filter::And(filters) => filters.iter().map(estimate_filter_selectivity).product(),
+        Filter::Or(filters) => filters
+            .iter()
+            .map(estimate_filter_selectivity)
+            .sum::<f64>()
+            .min(1.0),
+    }
+}
+
+// Relative per-row cost of computing an aggregate
+fn measure_weight(measure_type: &MeasureType) -> f64 {
+    match measure_type {
+        MeasureType::Count => 0.0,
+        // These need to keep all values around and sort them
+        MeasureType::CountDistinct | MeasureType::Median | MeasureType::Percentile(_) => 3.0,
+        // Collects all geometries before finding the center
+        MeasureType::Centroid => 3.0,
+        // Distance on the spheroid for every row
+        MeasureType::CountWithinRadius { .. } => 2.0,
+        _ => 1.0,
+    }
+}
+
+// Sorting to keep the first `keep` rows only needs a heap of that size (top-N sort)
+fn sort_cost(rows: f64, keep: f64) -> f64 {
+    rows * (rows.min(keep) + 1.0).log2()
+}
+
+fn estimate_dataset_rows<T: Syntax>(query: &Query<T>) -> f64 {
+    query
+        .dataset
+        .row_count
+        .map(|r| r as f64)
+        .unwrap_or(DEFAULT_ROW_COUNT)
+}
+
+fn estimate_input_rows<T: Syntax>(query: &Query<T>) -> f64 {
+    match &query.source {
+        QuerySource::Dataset => estimate_dataset_rows(query),
+        QuerySource::CommonTableExpression(name) => query
+            .ctes
+            .iter()
+            .find(|c| &c.name == name)
+            .map(|c| estimate_output_rows(&c.query))
+            .unwrap_or(DEFAULT_ROW_COUNT),
+    }
+}
+
+fn estimate_filtered_rows<T: Syntax>(query: &Query<T>) -> f64 {
+    estimate_input_rows(query)
+        * query
+            .filter
+            .as_ref()
+            .map(estimate_filter_selectivity)
+            .unwrap_or(1.0)
+}
+
+// Rows that come out of the aggregation (or just the filter), before the LIMIT
+fn estimate_groups<T: Syntax>(query: &Query<T>) -> f64 {
+    let rows = estimate_filtered_rows(query);
+
+    if query.dimensions.is_empty() && query.measures.is_empty() {
+        return rows;
+    }
+
+    query
+        .dimensions
+        .iter()
+        .map(estimate_dimension_cardinality)
+        .product::<f64>()
+        .min(rows)
+        .max(1.0)
+}
+
+/// Rough number of rows a query returns
+pub fn estimate_output_rows<T: Syntax>(query: &Query<T>) -> f64 {
+    let groups = estimate_groups(query);
+
+    match query.limit {
+        Some(l) => groups.min(l as f64),
+        None => groups,
+    }
+}
+
+/// Make a query cheaper until it fits into a budget (see `estimate_relative_cost`): first
+/// only return the top few rows, then filter on a random column, and if that's still not
+/// enough, drop the dimensions that split the data the most.
+pub fn reduce_cost<R: Rng, T: Syntax>(rng: &mut R, query: &mut Query<T>, max_cost: f64) {
+    if estimate_relative_cost(query) > max_cost
+        && !query.order_by.is_empty()
+        && !matches!(query.limit, Some(l) if l <= REDUCED_LIMIT)
+    {
+        query.limit = Some(REDUCED_LIMIT);
+    }
+
+    if estimate_relative_cost(query) > max_cost {
+        let mut columns: Vec<_> = query.dataset.columns.iter().collect();
+        columns.shuffle(rng);
+        let filter = columns
+            .into_iter()
+            .filter_map(|c| random_filter(rng, c))
+            .find(|f| estimate_filter_selectivity(f) <= REDUCING_SELECTIVITY);
+        if let Some(filter) = filter {
+            query.add_filter(filter);
+        }
+    }
+
+    while estimate_relative_cost(query) > max_cost {
+        let most_expensive = query
+            .dimensions
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| {
+                estimate_dimension_cardinality(a).total_cmp(&estimate_dimension_cardinality(b))
+            })
+            .map(|(i, _)| i);
+
+        match most_expensive {
+            Some(i) => query.remove_dimension(i),
+            None => return,
+        }
+    }
+}
+
+/// Rough cost of running a query, in row operations. This doesn't try to be accurate: it's
+/// meant to tell cheap queries from the ones that are likely to time out, based on how many
+/// rows go into the aggregation, how many groups come out of it and how much sorting there is.
+pub fn estimate_cost<T: Syntax>(query: &Query<T>) -> f64 {
+    let cte_cost: f64 = query.ctes.iter().map(|c| estimate_cost(&c.query)).sum();
+
+    let input_rows = estimate_input_rows(query);
+    let filtered_rows = estimate_filtered_rows(query);
+
+    // Every row gets read once (filters are cheap compared to aggregations), then every
+    // row that passes the filter gets hashed on every dimension and fed to every measure
+    let per_row_cost = 1.0
+        + query.dimensions.len() as f64
+        + query
+            .measures
+            .iter()
+            .map(|m| measure_weight(&m.type_))
+            .sum::<f64>();
+    let aggregation_cost = input_rows + filtered_rows * per_row_cost;
+
+    // Every window goes over all groups, and the ordered ones (as well as ORDER BY) sort them.
+    // ORDER BY only has to find the rows that make it under the LIMIT.
+    let groups = estimate_groups(query);
+    let window_sorts = query
+        .window_measures
+        .iter()
+        .filter(|w| !w.window.order_by.is_empty())
+        .count();
+    let window_cost = query.window_measures.len() as f64 * groups;
+    let window_sorting_cost = window_sorts as f64 * sort_cost(groups, groups);
+    let order_by_cost = if query.order_by.is_empty() {
+        0.0
+    } else {
+        sort_cost(groups, query.limit.map_or(groups, |l| l as f64))
+    };
+
+    cte_cost + aggregation_cost + window_cost + window_sorting_cost + order_by_cost
+}
+
+/// Cost of a query (see `estimate_cost`) per row of its dataset. Every query has to read the
+/// whole dataset, which Socrata does all the time: what makes a query time out is how much
+/// work it does on top of that. Compare this against a budget, so that a large dataset alone
+/// doesn't put a query over it. Datasets smaller than `DEFAULT_ROW_COUNT` count as that big,
+/// since queries on them are cheap anyway. A plain COUNT(*) costs at most 2.
+pub fn estimate_relative_cost<T: Syntax>(query: &Query<T>) -> f64 {
+    estimate_cost(query) / estimate_dataset_rows(query).max(DEFAULT_ROW_COUNT)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::cost::{
+        estimate_cost, estimate_dimension_cardinality, estimate_filter_selectivity,
+        estimate_output_rows, estimate_relative_cost, reduce_cost,
+    };
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, random_query, ComparisonOperator, DatePart,
+        DateTruncUnit, DefaultSyntax, Dimension, DimensionTransform, Filter, Literal, Measure,
+        MeasureType, OrderBy, OrderByDirection, OrderByItem, Query, RandomQueryParams,
+    };
+    use crate::query_gen::socrata::{Column, ColumnFormat, ColumnStats, DataType, Dataset};
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn get_columns() -> (Column, Column, Column) {
+        let column = |pg_name: &str, data_type: DataType| Column {
+            human_name: pg_name.to_string(),
+            pg_name: pg_name.to_string(),
+            data_type,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        (
+            column("status", DataType::Text),
+            column("issued", DataType::CalendarDate),
+            column("cost", DataType::Number),
+        )
+    }
+
+    fn get_query(row_count: Option<u64>) -> Query<DefaultSyntax> {
+        let (status, issued, cost) = get_columns();
+        let total_cost = Measure {
+            type_: MeasureType::Sum,
+            column: Some(cost.clone()),
+        };
+
+        Query::new(
+            Dataset {
+                domain: "data.edmonton.ca".to_string(),
+                socrata_id: "u7r4-acwa".to_string(),
+                name: "Building Permits".to_string(),
+                row_count,
+                columns: vec![status.clone(), issued.clone(), cost],
+            },
+            vec![total_cost.clone()],
+            vec![
+                Dimension {
+                    column: status,
+                    transform: None,
+                    alias: None,
+                },
+                Dimension {
+                    column: issued,
+                    transform: Some(DimensionTransform::Extract(DatePart::Month)),
+                    alias: None,
+                },
+            ],
+            vec![OrderBy {
+                item: OrderByItem::Measure(total_cost),
+                direction: OrderByDirection::Desc,
+            }],
+            Some(100),
+            DefaultSyntax {},
+        )
+    }
+
+    #[test]
+    fn test_estimate_dimension_cardinality() {
+        let (status, issued, _) = get_columns();
+        let dimension = |column: &Column, transform| Dimension {
+            column: column.clone(),
+            transform,
+            alias: None,
+        };
+
+        assert_eq!(
+            estimate_dimension_cardinality(&dimension(&issued, None)),
+            10_000.0
+        );
+        assert_eq!(
+            estimate_dimension_cardinality(&dimension(
+                &issued,
+                Some(DimensionTransform::DateTrunc(DateTruncUnit::Month))
+            )),
+            120.0
+        );
+        assert_eq!(
+            estimate_dimension_cardinality(&dimension(
+                &issued,
+                Some(DimensionTransform::Extract(DatePart::DayOfWeek))
+            )),
+            7.0
+        );
+        assert_eq!(
+            estimate_dimension_cardinality(&dimension(&status, None)),
+            1_000.0
+        );
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let query = get_query(None);
+        // 1000 statuses x 12 months, capped at the default 100000 rows
+        assert_eq!(estimate_output_rows(&query), 100.0);
+        let cost = estimate_cost(&query);
+
+        // Filters reduce the number of rows we aggregate
+        let mut filtered = get_query(None);
+        filtered.filter = Some(Filter::Comparison {
+            column: filtered.dimensions[0].column.clone(),
+            operator: ComparisonOperator::Eq,
+            value: Literal::Text("Issued".to_string()),
+        });
+        assert!(estimate_cost(&filtered) < cost);
+
+        // Fewer dimensions mean fewer groups to hash and sort
+        let mut fewer_dimensions = get_query(None);
+        fewer_dimensions.remove_dimension(0);
+        assert!(estimate_cost(&fewer_dimensions) < cost);
+
+        // Use the row count if we know it
+        assert!(estimate_cost(&get_query(Some(1_000))) < cost);
+        assert!(estimate_cost(&get_query(Some(10_000_000))) > cost);
+
+        // Only the work on top of reading the dataset grows with it
+        let relative_cost = estimate_relative_cost(&query);
+        assert!(estimate_relative_cost(&get_query(Some(10_000_000))) <= relative_cost);
+        assert!(estimate_relative_cost(&get_query(Some(1_000))) < relative_cost);
+
+        // Getting fewer rows out of the sort is cheaper
+        let mut top_10 = get_query(None);
+        top_10.limit = Some(10);
+        assert!(estimate_cost(&top_10) < cost);
+    }
+
+    #[test]
+    fn test_reduce_cost() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // Only getting the top 10 is enough for a small overshoot
+        let mut query = get_query(None);
+        let max_cost = estimate_relative_cost(&query) - 0.1;
+        reduce_cost(&mut rng, &mut query, max_cost);
+        assert_eq!(query.limit, Some(10));
+        assert_eq!(query.filter, None);
+        assert_eq!(query.dimensions.len(), 2);
+        assert!(estimate_relative_cost(&query) <= max_cost);
+
+        // Then filter the rows down
+        let mut query = get_query(None);
+        reduce_cost(&mut rng, &mut query, 3.0);
+        assert!(estimate_filter_selectivity(query.filter.as_ref().unwrap()) <= 0.3);
+        assert_eq!(query.dimensions.len(), 2);
+        assert!(estimate_relative_cost(&query) <= 3.0);
+        assert_eq!(query.validate(), vec![]);
+
+        // Then (here, with no columns to filter on) drop dimensions: the status has more
+        // distinct values than the month
+        let mut query = get_query(None);
+        query.dataset.columns.clear();
+        reduce_cost(&mut rng, &mut query, 4.5);
+        assert_eq!(query.filter, None);
+        assert_eq!(query.dimensions.len(), 1);
+        assert_eq!(query.dimensions[0].column.pg_name, "issued");
+        assert!(estimate_relative_cost(&query) <= 4.5);
+        assert_eq!(query.validate(), vec![]);
+    }
+
+    #[test]
+    fn test_random_query_cost_budget() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        for seed in 0..50 {
+            let query = random_query(
+                &mut StdRng::seed_from_u64(seed),
+                &dataset,
+                &measures,
+                &dimensions,
+                &RandomQueryParams {
+                    max_cost: Some(10.0),
+                    ..default_params()
+                },
+                DefaultSyntax {},
+            );
+
+            assert!(estimate_relative_cost(&query) <= 10.0);
+            assert_eq!(query.validate(), vec![]);
+        }
+    }
+
+    #[test]
+    fn test_random_query_cost_budget_large_dataset() {
+        let mut dataset = edmonton_dataset();
+        dataset.row_count = Some(5_000_000);
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        // Scanning more rows doesn't eat into the budget, so we still get to group on things
+        for seed in 0..100 {
+            let query = random_query(
+                &mut StdRng::seed_from_u64(seed),
+                &dataset,
+                &measures,
+                &dimensions,
+                &RandomQueryParams {
+                    max_cost: Some(10.0),
+                    ..default_params()
+                },
+                DefaultSyntax {},
+            );
+
+            assert!(estimate_relative_cost(&query) <= 10.0);
+            assert!(!query.dimensions.is_empty());
+            assert_eq!(query.validate(), vec![]);
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use crate::query_gen::query::{
+    Binning, DatePart, DateTruncUnit, DimensionTransform, Literal, MeasureType, SqlDialect, Syntax,
+};
+use crate::query_gen::socrata::{Column, Dataset};
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{:}\"", identifier.replace('\"', "\"\""))
+}
+
+/// Plain PostgreSQL, with the dataset loaded into a table named after its Socrata ID. Needs
+/// PostGIS (with geometries in SRID 4326) for the geospatial measures and dimensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct PostgresSyntax {}
+
+impl Syntax for PostgresSyntax {
+    fn get_sql_dialect(&self) -> Option<SqlDialect> {
+        Some(SqlDialect::PostgreSql)
+    }
+
+    fn get_dataset_sql(&self, dataset: &Dataset) -> String {
+        quote_identifier(&dataset.socrata_id)
+    }
+
+    fn get_column_sql(&self, column: &Column) -> String {
+        quote_identifier(&column.pg_name)
+    }
+
+    fn get_identifier_sql(&self, identifier: &str) -> String {
+        quote_identifier(identifier)
+    }
+
+    fn can_represent_identifier(&self, identifier: &str) -> bool {
+        !identifier.is_empty()
+    }
+}
+
+/// SQLite, with the dataset loaded into a table named after its Socrata ID. Needs the math
+/// functions (enabled by default in the CLI since 3.35) for binning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct SqliteSyntax {}
+
+impl Syntax for SqliteSyntax {
+    fn get_sql_dialect(&self) -> Option<SqlDialect> {
+        Some(SqlDialect::SQLite)
+    }
+
+    fn get_dataset_sql(&self, dataset: &Dataset) -> String {
+        quote_identifier(&dataset.socrata_id)
+    }
+
+    fn get_column_sql(&self, column: &Column) -> String {
+        quote_identifier(&column.pg_name)
+    }
+
+    fn get_identifier_sql(&self, identifier: &str) -> String {
+        quote_identifier(identifier)
+    }
+
+    fn can_represent_identifier(&self, identifier: &str) -> bool {
+        !identifier.is_empty()
+    }
+
+    fn get_literal_sql(&self, literal: &Literal) -> String {
+        match literal {
+            Literal::Boolean(true) => "1".to_string(),
+            Literal::Boolean(false) => "0".to_string(),
+            Literal::Number(n) => n.clone(),
+            Literal::Text(s) | Literal::Date(s) => format!("'{:}'", s.replace('\'', "''")),
+        }
+    }
+
+    fn supports_measure_type(&self, measure_type: &MeasureType) -> bool {
+        // These need extensions
+        !matches!(
+            measure_type,
+            MeasureType::Median
+                | MeasureType::Percentile(_)
+                | MeasureType::StddevSamp
+                | MeasureType::VarSamp
+                | MeasureType::Extent
+                | MeasureType::Centroid
+                | MeasureType::CountWithinRadius { .. }
+        )
+    }
+
+    fn supports_dimension_transform(&self, transform: &DimensionTransform) -> bool {
+        // So does this (SpatiaLite)
+        !matches!(transform, DimensionTransform::SnapToGrid { .. })
+    }
+
+    fn get_date_trunc_sql(&self, unit: &DateTruncUnit, expression: &str) -> String {
+        match unit {
+            DateTruncUnit::Day => format!("date({expression})"),
+            // Go back to the last Monday, like Postgres does
+            DateTruncUnit::Week => format!("date({expression}, '-6 days', 'weekday 1')"),
+            DateTruncUnit::Month => format!("date({expression}, 'start of month')"),
+            DateTruncUnit::Quarter => format!(
+                "date({expression}, 'start of month', '-' || ((CAST(strftime('%m', {expression}) AS INTEGER) - 1) % 3) || ' months')"
+            ),
+            DateTruncUnit::Year => format!("date({expression}, 'start of year')"),
+        }
+    }
+
+    fn get_extract_sql(&self, part: &DatePart, expression: &str) -> String {
+        let format = match part {
+            DatePart::Year => "%Y",
+            DatePart::Month => "%m",
+            // 0 is Sunday, same as DOW in Postgres
+            DatePart::DayOfWeek => "%w",
+            DatePart::Hour => "%H",
+        };
+        format!("CAST(strftime('{format}', {expression}) AS INTEGER)")
+    }
+
+    fn get_bin_sql(&self, binning: &Binning, expression: &str) -> String {
+        // Avoid integer division, which rounds towards zero
+        let expression = format!("CAST({expression} AS REAL)");
+
+        match binning {
+            Binning::Fixed { width } => format!("floor({expression} / {width}) * {width}"),
+            // No width_bucket(), so do the same thing by hand, with everything out of the
+            // range going into the 0th and the (count + 1)th bucket
+            Binning::Range { low, high, count } => format!(
+                "min(max(floor(({expression} - {low}) / ({high} - {low}) * {count}) + 1, 0), {:})",
+                count + 1
+            ),
+            Binning::Log => format!("floor(log10(max({expression}, 1)))"),
+        }
+    }
+}
+
+/// DuckDB, with the dataset loaded into a table named after its Socrata ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct DuckDbSyntax {}
+
+impl Syntax for DuckDbSyntax {
+    fn get_sql_dialect(&self) -> Option<SqlDialect> {
+        Some(SqlDialect::DuckDb)
+    }
+
+    fn get_dataset_sql(&self, dataset: &Dataset) -> String {
+        quote_identifier(&dataset.socrata_id)
+    }
+
+    fn get_column_sql(&self, column: &Column) -> String {
+        quote_identifier(&column.pg_name)
+    }
+
+    fn get_identifier_sql(&self, identifier: &str) -> String {
+        quote_identifier(identifier)
+    }
+
+    fn can_represent_identifier(&self, identifier: &str) -> bool {
+        !identifier.is_empty()
+    }
+
+    fn supports_measure_type(&self, measure_type: &MeasureType) -> bool {
+        // These need the spatial extension
+        !matches!(
+            measure_type,
+            MeasureType::Extent | MeasureType::Centroid | MeasureType::CountWithinRadius { .. }
+        )
+    }
+
+    fn supports_dimension_transform(&self, transform: &DimensionTransform) -> bool {
+        !matches!(transform, DimensionTransform::SnapToGrid { .. })
+    }
+
+    fn get_percentile_sql(&self, percentile: u8, expression: &str) -> String {
+        format!(
+            "quantile_cont({expression}, {:})",
+            percentile as f64 / 100.0
+        )
+    }
+
+    fn get_median_sql(&self, expression: &str) -> String {
+        format!("median({expression})")
+    }
+
+    fn get_bin_sql(&self, binning: &Binning, expression: &str) -> String {
+        match binning {
+            Binning::Fixed { width } => format!("floor({expression} / {width}) * {width}"),
+            // No width_bucket(), so do the same thing by hand
+            Binning::Range { low, high, count } => format!(
+                "least(greatest(floor(({expression} - {low}) / ({high} - {low}) * {count}) + 1, 0), {:})",
+                count + 1
+            ),
+            Binning::Log => format!("floor(log(greatest({expression}, 1)))"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::dialect::{DuckDbSyntax, PostgresSyntax, SqliteSyntax};
+    use crate::query_gen::parser::parse_query;
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, random_query, Binning, ComparisonOperator, DateTruncUnit,
+        DefaultSyntax, Diagnostic, Dimension, DimensionTransform, Filter, LimitStyle, Literal,
+        Measure, MeasureType, Query, Syntax,
+    };
+    use crate::query_gen::socrata::{Column, ColumnFormat, ColumnStats, DataType, Dataset};
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn get_query() -> Query<DefaultSyntax> {
+        let column = |pg_name: &str, data_type: DataType| Column {
+            human_name: pg_name.to_string(),
+            pg_name: pg_name.to_string(),
+            data_type,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let issued = column("issued", DataType::CalendarDate);
+        let cost = column("cost", DataType::Number);
+        let active = column("active", DataType::Checkbox);
+
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Building Permits".to_string(),
+            row_count: None,
+            columns: vec![issued.clone(), cost.clone(), active.clone()],
+        };
+
+        let mut query = Query::new(
+            dataset,
+            vec![Measure {
+                type_: MeasureType::Median,
+                column: Some(cost.clone()),
+            }],
+            vec![
+                Dimension {
+                    column: issued,
+                    transform: Some(DimensionTransform::DateTrunc(DateTruncUnit::Quarter)),
+                    alias: None,
+                },
+                Dimension {
+                    column: cost,
+                    transform: Some(DimensionTransform::Bin(Binning::Range {
+                        low: "0".to_string(),
+                        high: "100".to_string(),
+                        count: 10,
+                    })),
+                    alias: None,
+                },
+            ],
+            vec![],
+            Some(100),
+            DefaultSyntax {},
+        );
+        query.filter = Some(Filter::Comparison {
+            column: active,
+            operator: ComparisonOperator::Eq,
+            value: Literal::Boolean(true),
+        });
+
+        query
+    }
+
+    #[test]
+    fn test_emit_query_postgres() {
+        assert_eq!(
+            get_query().with_syntax(PostgresSyntax {}).to_sql(),
+            r#"SELECT
+  date_trunc('quarter', "issued") AS "quarter_issued",
+  width_bucket("cost", 0, 100, 10) AS "width_bucket_cost",
+  PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY "cost") AS "median_cost"
+FROM "u7r4-acwa"
+WHERE
+  "active" = TRUE
+GROUP BY
+  date_trunc('quarter', "issued"),
+  width_bucket("cost", 0, 100, 10)
+LIMIT 100"#
+        );
+    }
+
+    #[test]
+    fn test_emit_query_sqlite() {
+        let query = get_query().with_syntax(SqliteSyntax {});
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  date("issued", 'start of month', '-' || ((CAST(strftime('%m', "issued") AS INTEGER) - 1) % 3) || ' months') AS "quarter_issued",
+  min(max(floor((CAST("cost" AS REAL) - 0) / (100 - 0) * 10) + 1, 0), 11) AS "width_bucket_cost",
+  PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY "cost") AS "median_cost"
+FROM "u7r4-acwa"
+WHERE
+  "active" = 1
+GROUP BY
+  date("issued", 'start of month', '-' || ((CAST(strftime('%m', "issued") AS INTEGER) - 1) % 3) || ' months'),
+  min(max(floor((CAST("cost" AS REAL) - 0) / (100 - 0) * 10) + 1, 0), 11)
+LIMIT 100"#
+        );
+
+        // SQLite doesn't have a median
+        assert_eq!(
+            query.validate(),
+            vec![Diagnostic::UnsupportedMeasure(query.measures[0].clone())]
+        );
+    }
+
+    #[test]
+    fn test_emit_query_duckdb() {
+        assert_eq!(
+            get_query().with_syntax(DuckDbSyntax {}).to_sql(),
+            r#"SELECT
+  date_trunc('quarter', "issued") AS "quarter_issued",
+  least(greatest(floor(("cost" - 0) / (100 - 0) * 10) + 1, 0), 11) AS "width_bucket_cost",
+  median("cost") AS "median_cost"
+FROM "u7r4-acwa"
+WHERE
+  "active" = TRUE
+GROUP BY
+  date_trunc('quarter', "issued"),
+  least(greatest(floor(("cost" - 0) / (100 - 0) * 10) + 1, 0), 11)
+LIMIT 100"#
+        );
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct LimitStyleSyntax {
+        limit_style: LimitStyle,
+    }
+
+    impl Syntax for LimitStyleSyntax {
+        fn get_dataset_sql(&self, dataset: &Dataset) -> String {
+            dataset.socrata_id.clone()
+        }
+
+        fn get_column_sql(&self, column: &Column) -> String {
+            column.pg_name.clone()
+        }
+
+        fn get_identifier_sql(&self, identifier: &str) -> String {
+            identifier.to_string()
+        }
+
+        fn get_limit_style(&self) -> LimitStyle {
+            self.limit_style.clone()
+        }
+    }
+
+    #[test]
+    fn test_emit_query_limit_styles() {
+        let emit = |limit_style: LimitStyle| {
+            let mut query = get_query().with_syntax(LimitStyleSyntax { limit_style });
+            query.dimensions = vec![];
+            query.filter = None;
+            query.measures = vec![Measure {
+                type_: MeasureType::Count,
+                column: None,
+            }];
+
+            let sql = query.to_sql();
+            assert_eq!(
+                parse_query(&sql, &query.dataset, query.syntax().clone()).unwrap(),
+                query
+            );
+            sql
+        };
+
+        assert_eq!(
+            emit(LimitStyle::Limit),
+            "SELECT\n  COUNT(*) AS count\nFROM u7r4-acwa\nLIMIT 100"
+        );
+        assert_eq!(
+            emit(LimitStyle::Top),
+            "SELECT TOP 100\n  COUNT(*) AS count\nFROM u7r4-acwa"
+        );
+        assert_eq!(
+            emit(LimitStyle::FetchFirst),
+            "SELECT\n  COUNT(*) AS count\nFROM u7r4-acwa\nFETCH FIRST 100 ROWS ONLY"
+        );
+    }
+
+    fn assert_round_trip<T: Syntax + Clone + std::fmt::Debug + PartialEq>(syntax: T) {
+        let dataset = edmonton_dataset();
+
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        for seed in 0..50 {
+            let query = random_query(
+                &mut StdRng::seed_from_u64(seed),
+                &dataset,
+                &measures,
+                &dimensions,
+                &default_params(),
+                syntax.clone(),
+            );
+
+            assert_eq!(query.validate(), vec![]);
+            assert_eq!(
+                parse_query(&query.to_sql(), &dataset, syntax.clone()).unwrap(),
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_query_dialects() {
+        assert_round_trip(PostgresSyntax {});
+        assert_round_trip(SqliteSyntax {});
+        assert_round_trip(DuckDbSyntax {});
+
+        // Booleans and bins that are spelled differently in every dialect
+        let query = get_query().with_syntax(SqliteSyntax {});
+        assert_eq!(
+            parse_query(&query.to_sql(), &query.dataset, SqliteSyntax {}).unwrap(),
+            query
+        );
+        let query = get_query().with_syntax(DuckDbSyntax {});
+        assert_eq!(
+            parse_query(&query.to_sql(), &query.dataset, DuckDbSyntax {}).unwrap(),
+            query
+        );
+    }
+}
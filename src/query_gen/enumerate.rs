@@ -0,0 +1,273 @@
+use crate::query_gen::query::{
+    Dimension, Measure, OrderBy, OrderByDirection, OrderByItem, Query, Syntax,
+};
+use crate::query_gen::socrata::Dataset;
+use itertools::Itertools;
+use std::ops::Range;
+
+/// Bounds of the query space, with the same meaning as in `RandomQueryParams`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumerationParams {
+    pub no_dimensions: Range<usize>,
+    pub no_measures: Range<usize>,
+    pub no_order_bys: Range<usize>,
+}
+
+// Clamp a range to the number of items we actually have
+fn clamp(range: &Range<usize>, max: usize) -> Range<usize> {
+    range.start.min(max + 1)..range.end.min(max + 1)
+}
+
+// All ways to order by `no_order_bys` of the items, in any direction
+fn orderings(
+    items: Vec<OrderByItem>,
+    no_order_bys: Range<usize>,
+) -> impl Iterator<Item = Vec<OrderBy>> {
+    no_order_bys.flat_map(move |k| {
+        items
+            .clone()
+            .into_iter()
+            .permutations(k)
+            .flat_map(move |order| {
+                // Every combination of directions, one bit per item
+                (0..1usize << k).map(move |directions| {
+                    order
+                        .iter()
+                        .enumerate()
+                        .map(|(i, item)| OrderBy {
+                            item: item.clone(),
+                            direction: if directions & (1 << i) == 0 {
+                                OrderByDirection::Asc
+                            } else {
+                                OrderByDirection::Desc
+                            },
+                        })
+                        .collect_vec()
+                })
+            })
+    })
+}
+
+/// Every query made of some of the dimensions and measures and ordered by some of them,
+/// like the ones `random_query` generates (but without filters, HAVING conditions or window
+/// functions, which would make the space explode). Queries that select nothing are skipped.
+///
+/// The queries aren't validated, so pass in measures and dimensions that the syntax supports.
+#[allow(dead_code)]
+pub fn enumerate_queries<'a, T: Syntax + Clone + 'a>(
+    dataset: &'a Dataset,
+    measures: &'a [Measure],
+    dimensions: &'a [Dimension],
+    params: &EnumerationParams,
+    syntax: T,
+) -> impl Iterator<Item = Query<T>> + 'a {
+    let no_dimensions = clamp(&params.no_dimensions, dimensions.len());
+    let no_measures = clamp(&params.no_measures, measures.len());
+    let no_order_bys = params.no_order_bys.clone();
+
+    no_dimensions
+        .cartesian_product(no_measures)
+        .filter(|(d, m)| d + m > 0)
+        .flat_map(move |(d, m)| {
+            dimensions
+                .iter()
+                .cloned()
+                .combinations(d)
+                .cartesian_product(measures.iter().cloned().combinations(m).collect_vec())
+        })
+        .flat_map(move |(chosen_dimensions, chosen_measures)| {
+            let items: Vec<OrderByItem> = chosen_measures
+                .iter()
+                .cloned()
+                .map(OrderByItem::Measure)
+                .chain(
+                    chosen_dimensions
+                        .iter()
+                        .cloned()
+                        .map(OrderByItem::Dimension),
+                )
+                .collect();
+            let items_len = items.len();
+
+            orderings(items, clamp(&no_order_bys, items_len))
+                .map(move |order_by| (chosen_dimensions.clone(), chosen_measures.clone(), order_by))
+        })
+        .map(move |(chosen_dimensions, chosen_measures, order_by)| {
+            Query::new(
+                dataset.clone(),
+                chosen_measures,
+                chosen_dimensions,
+                order_by,
+                Some(100),
+                syntax.clone(),
+            )
+        })
+}
+
+fn binomial(n: usize, k: usize) -> u128 {
+    (0..k as u128).fold(1, |acc, i| acc.saturating_mul(n as u128 - i) / (i + 1))
+}
+
+// Number of ordered ways to pick k of n items
+fn permutations(n: usize, k: usize) -> u128 {
+    (0..k as u128).fold(1, |acc, i| acc.saturating_mul(n as u128 - i))
+}
+
+/// How many queries `enumerate_queries` would produce, without building them. Saturates at
+/// `u128::MAX`, which is more than anyone would iterate through anyway.
+#[allow(dead_code)]
+pub fn count_queries(
+    measures: &[Measure],
+    dimensions: &[Dimension],
+    params: &EnumerationParams,
+) -> u128 {
+    let mut total: u128 = 0;
+
+    for d in clamp(&params.no_dimensions, dimensions.len()) {
+        for m in clamp(&params.no_measures, measures.len()) {
+            if d + m == 0 {
+                continue;
+            }
+
+            let orderings = clamp(&params.no_order_bys, d + m)
+                .map(|k| permutations(d + m, k).saturating_mul(1 << k))
+                .fold(0u128, |acc, o| acc.saturating_add(o));
+
+            total = total.saturating_add(
+                binomial(dimensions.len(), d)
+                    .saturating_mul(binomial(measures.len(), m))
+                    .saturating_mul(orderings),
+            );
+        }
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::enumerate::{count_queries, enumerate_queries, EnumerationParams};
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, random_query, DefaultSyntax, RandomQueryParams,
+    };
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_count_queries() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        // 2 queries with one dimension and 2 with one measure, each unordered or ordered
+        // ASC/DESC (2 * 3 + 2 * 3), plus 4 with both: unordered, ordered by one of them in
+        // either direction or by both, in any order and directions (4 * (1 + 4 + 8))
+        let params = EnumerationParams {
+            no_dimensions: 0..2,
+            no_measures: 0..2,
+            no_order_bys: 0..3,
+        };
+        assert_eq!(count_queries(&measures[..2], &dimensions[..2], &params), 64);
+        assert_eq!(
+            enumerate_queries(
+                &dataset,
+                &measures[..2],
+                &dimensions[..2],
+                &params,
+                DefaultSyntax {}
+            )
+            .count(),
+            64
+        );
+
+        // Ranges past the number of items we have don't add anything
+        assert_eq!(
+            count_queries(
+                &measures[..2],
+                &dimensions[..2],
+                &EnumerationParams {
+                    no_dimensions: 0..10,
+                    no_measures: 0..10,
+                    no_order_bys: 0..3,
+                }
+            ),
+            count_queries(
+                &measures[..2],
+                &dimensions[..2],
+                &EnumerationParams {
+                    no_dimensions: 0..3,
+                    no_measures: 0..3,
+                    no_order_bys: 0..3,
+                }
+            ),
+        );
+
+        // Too big to enumerate, but we can still count it
+        assert!(
+            count_queries(
+                &measures,
+                &dimensions,
+                &EnumerationParams {
+                    no_dimensions: 1..3,
+                    no_measures: 1..4,
+                    no_order_bys: 0..3,
+                }
+            ) > 1_000_000_000
+        );
+    }
+
+    #[test]
+    fn test_enumerate_queries() {
+        let dataset = edmonton_dataset();
+        let measures = &build_measures(&dataset)[..6];
+        let dimensions = &build_dimensions(&dataset)[..6];
+        let params = EnumerationParams {
+            no_dimensions: 1..3,
+            no_measures: 1..3,
+            no_order_bys: 0..2,
+        };
+
+        let all_sql: Vec<String> =
+            enumerate_queries(&dataset, measures, dimensions, &params, DefaultSyntax {})
+                .map(|q| q.to_sql())
+                .collect();
+        assert_eq!(
+            all_sql.len() as u128,
+            count_queries(measures, dimensions, &params)
+        );
+
+        // No duplicates
+        let unique: HashSet<&String> = all_sql.iter().collect();
+        assert_eq!(unique.len(), all_sql.len());
+
+        // The random generator stays inside the space
+        for seed in 0..50 {
+            let mut query = random_query(
+                &mut StdRng::seed_from_u64(seed),
+                &dataset,
+                measures,
+                dimensions,
+                &RandomQueryParams {
+                    no_dimensions: params.no_dimensions.clone(),
+                    no_measures: params.no_measures.clone(),
+                    no_order_bys: params.no_order_bys.clone(),
+                    no_havings: 0..1,
+                    no_window_measures: 0..1,
+                    no_filters: 0..1,
+                    ..default_params()
+                },
+                DefaultSyntax {},
+            );
+            // The enumeration keeps the dimensions and measures in their original order
+            query
+                .dimensions
+                .sort_by_key(|d| dimensions.iter().position(|o| o == d));
+            query
+                .measures
+                .sort_by_key(|m| measures.iter().position(|o| o == m));
+            assert!(unique.contains(&query.to_sql()), "{:}", query.to_sql());
+        }
+    }
+}
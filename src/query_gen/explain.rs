@@ -0,0 +1,669 @@
+use crate::query_gen::query::{
+    filter_columns, is_temporal, Binning, ComparisonOperator, DatePart, DateTruncUnit, Dimension,
+    DimensionTransform, Filter, HavingCondition, Literal, Measure, MeasureType, OrderBy,
+    OrderByDirection, OrderByItem, Query, QuerySource, Syntax, Window, WindowFunction,
+    WindowMeasure,
+};
+use crate::query_gen::socrata::{Column, DataType};
+use crate::query_gen::title::{ordinal, query_title};
+use itertools::Itertools;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClauseKind {
+    With,
+    From,
+    Columns,
+    Where,
+    GroupBy,
+    Measures,
+    Windows,
+    Having,
+    OrderBy,
+    Limit,
+}
+
+/// A column the explanation refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDescription {
+    pub human_name: String,
+    // Readable name of the Socrata data type, e.g. "date" or "number"
+    pub data_type: String,
+}
+
+/// One thing a clause does, e.g. "the average of Latitude"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplanationItem {
+    pub text: String,
+    pub columns: Vec<ColumnDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClauseExplanation {
+    pub kind: ClauseKind,
+    // e.g. "Groups the rows by:"
+    pub summary: String,
+    pub items: Vec<ExplanationItem>,
+}
+
+pub fn data_type_name(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Checkbox => "yes/no",
+        DataType::Double | DataType::Number => "number",
+        DataType::FloatingTimestamp => "date and time",
+        DataType::CalendarDate => "date",
+        DataType::Money => "money",
+        DataType::Text => "text",
+        DataType::Url => "URL",
+        DataType::Line => "line",
+        DataType::Location => "location",
+        DataType::Multiline => "lines",
+        DataType::Multipoint => "points",
+        DataType::Multipolygon => "area",
+        DataType::Point => "point",
+        DataType::Other(other) => return other.to_lowercase(),
+    }
+    .to_string()
+}
+
+fn describe_column(column: &Column) -> ColumnDescription {
+    ColumnDescription {
+        human_name: column.human_name.clone(),
+        data_type: data_type_name(&column.data_type),
+    }
+}
+
+fn item(text: String, columns: Vec<&Column>) -> ExplanationItem {
+    let mut descriptions: Vec<ColumnDescription> = vec![];
+    for description in columns.into_iter().map(describe_column) {
+        if !descriptions.contains(&description) {
+            descriptions.push(description);
+        }
+    }
+    ExplanationItem {
+        text,
+        columns: descriptions,
+    }
+}
+
+fn literal_text(literal: &Literal) -> String {
+    match literal {
+        Literal::Number(n) => n.clone(),
+        Literal::Text(t) => format!("\"{t}\""),
+        Literal::Boolean(true) => "yes".to_string(),
+        Literal::Boolean(false) => "no".to_string(),
+        Literal::Date(d) => d.clone(),
+    }
+}
+
+fn operator_text(operator: &ComparisonOperator) -> &'static str {
+    match operator {
+        ComparisonOperator::Eq => "is",
+        ComparisonOperator::NotEq => "is not",
+        ComparisonOperator::Lt => "is less than",
+        ComparisonOperator::LtEq => "is at most",
+        ComparisonOperator::Gt => "is more than",
+        ComparisonOperator::GtEq => "is at least",
+    }
+}
+
+fn filter_text(filter: &Filter) -> String {
+    match filter {
+        Filter::Comparison {
+            column,
+            operator,
+            value,
+        } => format!(
+            "{:} {:} {:}",
+            column.human_name,
+            operator_text(operator),
+            literal_text(value)
+        ),
+        Filter::In { column, values } => format!(
+            "{:} is one of {:}",
+            column.human_name,
+            values.iter().map(literal_text).join(", ")
+        ),
+        Filter::Between { column, low, high } => format!(
+            "{:} is between {:} and {:}",
+            column.human_name,
+            literal_text(low),
+            literal_text(high)
+        ),
+        Filter::IsNull {
+            column,
+            negated: false,
+        } => format!("{:} is empty", column.human_name),
+        Filter::IsNull {
+            column,
+            negated: true,
+        } => format!("{:} is not empty", column.human_name),
+        Filter::Like { column, pattern } => format!(
+            "{:} matches the pattern \"{pattern}\" (% stands for any text)",
+            column.human_name
+        ),
+        Filter::And(filters) => format!("({:})", filters.iter().map(filter_text).join(" and ")),
+        Filter::Or(filters) => format!("({:})", filters.iter().map(filter_text).join(" or ")),
+    }
+}
+
+fn dimension_text(dimension: &Dimension) -> String {
+    let name = &dimension.column.human_name;
+
+    match &dimension.transform {
+        None => format!("each distinct {name}"),
+        Some(DimensionTransform::DateTrunc(unit)) => format!(
+            "the {:} of {name}",
+            match unit {
+                DateTruncUnit::Day => "day",
+                DateTruncUnit::Week => "week",
+                DateTruncUnit::Month => "month",
+                DateTruncUnit::Quarter => "quarter",
+                DateTruncUnit::Year => "year",
+            }
+        ),
+        Some(DimensionTransform::Extract(part)) => match part {
+            DatePart::Year => format!("the year of {name}"),
+            DatePart::Month => format!("the month of {name}, across all years"),
+            DatePart::DayOfWeek => format!("the day of the week of {name}"),
+            DatePart::Hour => format!("the hour of the day of {name}"),
+        },
+        Some(DimensionTransform::Bin(binning)) => match binning {
+            Binning::Fixed { width } => format!("{name}, in buckets of {width}"),
+            Binning::Range { low, high, count } => {
+                format!("{name}, split into {count} equal buckets between {low} and {high}")
+            }
+            Binning::Log => format!("the order of magnitude of {name}"),
+        },
+        Some(DimensionTransform::SnapToGrid { size }) => {
+            format!("{name}, snapped to a grid of squares {size} degrees wide")
+        }
+    }
+}
+
+fn measure_text(measure: &Measure) -> String {
+    let column = match &measure.column {
+        Some(c) => c,
+        None => return "the number of rows".to_string(),
+    };
+    let name = &column.human_name;
+    let is_time = is_temporal(&column.data_type);
+
+    match &measure.type_ {
+        MeasureType::Count => "the number of rows".to_string(),
+        MeasureType::CountDistinct => format!("the number of different values of {name}"),
+        MeasureType::CountNonNull => format!("the number of rows where {name} is not empty"),
+        MeasureType::Sum => format!("the sum of {name}"),
+        MeasureType::Average => format!("the average of {name}"),
+        MeasureType::Min if is_time => format!("the earliest {name}"),
+        MeasureType::Min => format!("the smallest {name}"),
+        MeasureType::Max if is_time => format!("the latest {name}"),
+        MeasureType::Max => format!("the largest {name}"),
+        MeasureType::Median => format!("the median of {name}"),
+        MeasureType::Percentile(p) => format!(
+            "the {:} percentile of {name} ({p}% of values are below it)",
+            ordinal(*p)
+        ),
+        MeasureType::StddevSamp => format!("the standard deviation of {name}"),
+        MeasureType::VarSamp => format!("the variance of {name}"),
+        MeasureType::Extent => format!("the smallest box that contains every {name}"),
+        MeasureType::Centroid => format!("the point in the middle of all {name} values"),
+        MeasureType::CountWithinRadius {
+            latitude,
+            longitude,
+            meters,
+        } => format!(
+            "the number of rows where {name} is within {meters} meters of the point at latitude {latitude}, longitude {longitude}"
+        ),
+    }
+}
+
+fn order_by_text(order_by: &OrderBy) -> String {
+    let descending = order_by.direction == OrderByDirection::Desc;
+
+    match &order_by.item {
+        OrderByItem::Measure(m) => format!(
+            "{:}, {:} first",
+            measure_text(m),
+            if descending { "highest" } else { "lowest" }
+        ),
+        OrderByItem::Dimension(d) => {
+            let direction = match (descending, &d.column.data_type) {
+                (true, t) if is_temporal(t) => "newest first",
+                (false, t) if is_temporal(t) => "oldest first",
+                (true, DataType::Text) => "Z to A",
+                (false, DataType::Text) => "A to Z",
+                (true, _) => "largest first",
+                (false, _) => "smallest first",
+            };
+            format!("{:}, {direction}", dimension_text(d))
+        }
+    }
+}
+
+fn order_by_columns(order_by: &OrderBy) -> Vec<&Column> {
+    match &order_by.item {
+        OrderByItem::Measure(m) => m.column.iter().collect(),
+        OrderByItem::Dimension(d) => vec![&d.column],
+    }
+}
+
+fn window_text(window: &Window) -> String {
+    let mut parts = vec![];
+    if !window.partition_by.is_empty() {
+        parts.push(format!(
+            "separately for {:}",
+            window.partition_by.iter().map(dimension_text).join(" and ")
+        ));
+    }
+    if !window.order_by.is_empty() {
+        parts.push(format!(
+            "going by {:}",
+            window.order_by.iter().map(order_by_text).join(", then ")
+        ));
+    }
+    parts.join(", ")
+}
+
+fn window_measure_item(window_measure: &WindowMeasure) -> ExplanationItem {
+    let window = &window_measure.window;
+    let what = match &window_measure.function {
+        WindowFunction::Rank => {
+            "the rank of each group (ties share a rank, leaving gaps)".to_string()
+        }
+        WindowFunction::DenseRank => {
+            "the rank of each group (ties share a rank, without gaps)".to_string()
+        }
+        WindowFunction::RowNumber => "the position of each group".to_string(),
+        WindowFunction::Sum(m) => format!("a running total of {:}", measure_text(m)),
+        WindowFunction::ShareOfTotal(m) => {
+            format!("each group's share of the total of {:}", measure_text(m))
+        }
+    };
+
+    let mut columns: Vec<&Column> = match &window_measure.function {
+        WindowFunction::Sum(m) | WindowFunction::ShareOfTotal(m) => m.column.iter().collect(),
+        _ => vec![],
+    };
+    columns.extend(window.partition_by.iter().map(|d| &d.column));
+    columns.extend(window.order_by.iter().flat_map(order_by_columns));
+
+    let how = window_text(window);
+    if how.is_empty() {
+        item(what, columns)
+    } else {
+        item(format!("{what}, {how}"), columns)
+    }
+}
+
+fn having_item(condition: &HavingCondition) -> ExplanationItem {
+    match condition {
+        HavingCondition::Comparison {
+            measure,
+            operator,
+            value,
+        } => item(
+            format!(
+                "{:} {:} {:}",
+                measure_text(measure),
+                operator_text(operator),
+                literal_text(value)
+            ),
+            measure.column.iter().collect(),
+        ),
+        HavingCondition::Between { measure, low, high } => item(
+            format!(
+                "{:} is between {:} and {:}",
+                measure_text(measure),
+                literal_text(low),
+                literal_text(high)
+            ),
+            measure.column.iter().collect(),
+        ),
+    }
+}
+
+fn clause(kind: ClauseKind, summary: &str, items: Vec<ExplanationItem>) -> ClauseExplanation {
+    ClauseExplanation {
+        kind,
+        summary: summary.to_string(),
+        items,
+    }
+}
+
+/// Describe what every clause of a query does, in the order the database runs them
+/// (roughly: source, filters, grouping, aggregates, sorting, limit)
+pub fn explain_query<T: Syntax>(query: &Query<T>) -> Vec<ClauseExplanation> {
+    let mut result = vec![];
+
+    if !query.ctes.is_empty() {
+        result.push(clause(
+            ClauseKind::With,
+            "First, prepares some intermediate results:",
+            query
+                .ctes
+                .iter()
+                .map(|cte| {
+                    let cte_query = &cte.query;
+                    let mut columns: Vec<&Column> = cte_query.columns.iter().collect();
+                    columns.extend(cte_query.dimensions.iter().map(|d| &d.column));
+                    columns.extend(cte_query.measures.iter().flat_map(|m| m.column.iter()));
+                    item(
+                        format!("\"{:}\": {:}", cte.name, query_title(cte_query)),
+                        columns,
+                    )
+                })
+                .collect(),
+        ));
+    }
+
+    result.push(clause(
+        ClauseKind::From,
+        "Reads rows from:",
+        vec![match &query.source {
+            QuerySource::Dataset => {
+                item(format!("the \"{:}\" dataset", query.dataset.name), vec![])
+            }
+            QuerySource::CommonTableExpression(name) => {
+                item(format!("the intermediate result \"{name}\""), vec![])
+            }
+        }],
+    ));
+
+    if let Some(filter) = &query.filter {
+        // Split the top-level AND into separate conditions
+        let filters = match filter {
+            Filter::And(filters) => filters.iter().collect(),
+            f => vec![f],
+        };
+        result.push(clause(
+            ClauseKind::Where,
+            "Only keeps the rows where:",
+            filters
+                .into_iter()
+                .map(|f| item(filter_text(f), filter_columns(f)))
+                .collect(),
+        ));
+    }
+
+    if !query.columns.is_empty() {
+        result.push(clause(
+            ClauseKind::Columns,
+            "Shows these columns as they are:",
+            query
+                .columns
+                .iter()
+                .map(|c| item(c.human_name.clone(), vec![c]))
+                .collect(),
+        ));
+    }
+
+    if !query.dimensions.is_empty() {
+        result.push(clause(
+            ClauseKind::GroupBy,
+            "Puts the rows into groups, one for every combination of:",
+            query
+                .dimensions
+                .iter()
+                .map(|d| item(dimension_text(d), vec![&d.column]))
+                .collect(),
+        ));
+    }
+
+    if !query.measures.is_empty() {
+        result.push(clause(
+            ClauseKind::Measures,
+            if query.dimensions.is_empty() {
+                "Calculates, over all the rows:"
+            } else {
+                "Calculates, for each group:"
+            },
+            query
+                .measures
+                .iter()
+                .map(|m| item(measure_text(m), m.column.iter().collect()))
+                .collect(),
+        ));
+    }
+
+    if !query.window_measures.is_empty() {
+        result.push(clause(
+            ClauseKind::Windows,
+            "Then compares the groups with each other, calculating:",
+            query
+                .window_measures
+                .iter()
+                .map(window_measure_item)
+                .collect(),
+        ));
+    }
+
+    if !query.having.is_empty() {
+        result.push(clause(
+            ClauseKind::Having,
+            "Only keeps the groups where:",
+            query.having.iter().map(having_item).collect(),
+        ));
+    }
+
+    if !query.order_by.is_empty() {
+        result.push(clause(
+            ClauseKind::OrderBy,
+            "Sorts the results by:",
+            query
+                .order_by
+                .iter()
+                .map(|o| item(order_by_text(o), order_by_columns(o)))
+                .collect(),
+        ));
+    }
+
+    if let Some(limit) = query.limit {
+        result.push(clause(
+            ClauseKind::Limit,
+            "Returns at most:",
+            vec![item(
+                if limit == 1 {
+                    "1 row".to_string()
+                } else {
+                    format!("{limit} rows")
+                },
+                vec![],
+            )],
+        ));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::explain::{explain_query, ClauseKind, ColumnDescription};
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, random_query, random_top_n_per_group_query,
+        ComparisonOperator, DateTruncUnit, DefaultSyntax, Dimension, DimensionTransform, Filter,
+        Literal, Measure, MeasureType, OrderBy, OrderByDirection, OrderByItem, Query,
+    };
+    use crate::query_gen::scoring::DefaultScorer;
+    use crate::query_gen::socrata::{Column, ColumnFormat, ColumnStats, DataType, Dataset};
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_explain_query() {
+        let status = Column {
+            human_name: "Status".to_string(),
+            pg_name: "status".to_string(),
+            data_type: DataType::Text,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let issued = Column {
+            human_name: "Issue Date".to_string(),
+            pg_name: "issue_date".to_string(),
+            data_type: DataType::CalendarDate,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Building Permits".to_string(),
+            row_count: None,
+            columns: vec![status.clone(), issued.clone()],
+        };
+        let count = Measure {
+            type_: MeasureType::Count,
+            column: None,
+        };
+        let month = Dimension {
+            column: issued.clone(),
+            transform: Some(DimensionTransform::DateTrunc(DateTruncUnit::Month)),
+            alias: None,
+        };
+
+        let mut query = Query::new(
+            dataset,
+            vec![
+                count.clone(),
+                Measure {
+                    type_: MeasureType::Min,
+                    column: Some(issued),
+                },
+            ],
+            vec![month.clone()],
+            vec![OrderBy {
+                item: OrderByItem::Dimension(month),
+                direction: OrderByDirection::Desc,
+            }],
+            Some(100),
+            DefaultSyntax {},
+        );
+        query.filter = Some(Filter::And(vec![
+            Filter::Comparison {
+                column: status.clone(),
+                operator: ComparisonOperator::NotEq,
+                value: Literal::Text("CANCELLED".to_string()),
+            },
+            Filter::Or(vec![
+                Filter::IsNull {
+                    column: status.clone(),
+                    negated: true,
+                },
+                Filter::In {
+                    column: status,
+                    values: vec![
+                        Literal::Text("A".to_string()),
+                        Literal::Text("B".to_string()),
+                    ],
+                },
+            ]),
+        ]));
+
+        let explanation = explain_query(&query);
+        assert_eq!(
+            explanation
+                .iter()
+                .map(|c| c.kind.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                ClauseKind::From,
+                ClauseKind::Where,
+                ClauseKind::GroupBy,
+                ClauseKind::Measures,
+                ClauseKind::OrderBy,
+                ClauseKind::Limit
+            ]
+        );
+
+        let texts = |kind: ClauseKind| {
+            explanation
+                .iter()
+                .find(|c| c.kind == kind)
+                .unwrap()
+                .items
+                .iter()
+                .map(|i| i.text.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            texts(ClauseKind::From),
+            vec!["the \"Building Permits\" dataset"]
+        );
+        assert_eq!(
+            texts(ClauseKind::Where),
+            vec![
+                "Status is not \"CANCELLED\"",
+                "(Status is not empty or Status is one of \"A\", \"B\")"
+            ]
+        );
+        assert_eq!(texts(ClauseKind::GroupBy), vec!["the month of Issue Date"]);
+        assert_eq!(
+            texts(ClauseKind::Measures),
+            vec!["the number of rows", "the earliest Issue Date"]
+        );
+        assert_eq!(
+            texts(ClauseKind::OrderBy),
+            vec!["the month of Issue Date, newest first"]
+        );
+        assert_eq!(texts(ClauseKind::Limit), vec!["100 rows"]);
+
+        // Columns are only listed once per item
+        assert_eq!(
+            explanation[1].items[1].columns,
+            vec![ColumnDescription {
+                human_name: "Status".to_string(),
+                data_type: "text".to_string()
+            }]
+        );
+        assert_eq!(
+            explanation[2].items[0].columns,
+            vec![ColumnDescription {
+                human_name: "Issue Date".to_string(),
+                data_type: "date".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_explain_random_queries() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let query = random_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &default_params(),
+                DefaultSyntax {},
+            );
+
+            let explanation = explain_query(&query);
+            let measure_clause = explanation
+                .iter()
+                .find(|c| c.kind == ClauseKind::Measures)
+                .unwrap();
+            assert_eq!(measure_clause.items.len(), query.measures.len());
+            assert!(explanation
+                .iter()
+                .all(|c| c.items.iter().all(|i| !i.text.is_empty())));
+
+            if let Some(top_n) = random_top_n_per_group_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &DefaultScorer {},
+                DefaultSyntax {},
+            ) {
+                let explanation = explain_query(&top_n);
+                assert_eq!(explanation[0].kind, ClauseKind::With);
+                assert_eq!(explanation[0].items.len(), 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use itertools::Itertools;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordCase {
+    Upper,
+    #[allow(dead_code)]
+    Lower,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommaStyle {
+    // a,\n  b
+    Trailing,
+    // a\n  , b
+    #[allow(dead_code)]
+    Leading,
+}
+
+/// Layout of the SQL emitted by `Query::to_sql_with`. The default is the one `Query::to_sql`
+/// uses: one clause item per line, indented by two spaces, with uppercase keywords.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    // Put the whole query on one line. Indentation, comma style and comments are ignored.
+    pub single_line: bool,
+    // Only applies to keywords: function names are spelled the way the Syntax spells them
+    pub keyword_case: KeywordCase,
+    pub indent_width: usize,
+    pub comma_style: CommaStyle,
+    // Add a `-- Human Name` comment to every SELECT item that comes from a column
+    pub column_comments: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            single_line: false,
+            keyword_case: KeywordCase::Upper,
+            indent_width: 2,
+            comma_style: CommaStyle::Trailing,
+            column_comments: false,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Everything on one line, e.g. to put into a URL
+    #[allow(dead_code)]
+    pub fn compact() -> Self {
+        Self {
+            single_line: true,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn indent(&self) -> String {
+        " ".repeat(self.indent_width)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ListSeparator {
+    Comma,
+    And,
+}
+
+/// Lay out a clause made of a keyword and a list of items, each with an optional comment
+pub(crate) fn format_clause(
+    keyword: &str,
+    items: &[(String, Option<String>)],
+    separator: ListSeparator,
+    options: &FormatOptions,
+) -> String {
+    if options.single_line {
+        return format!(
+            "{keyword} {:}",
+            items.iter().map(|(sql, _)| sql).join(match separator {
+                ListSeparator::Comma => ", ",
+                ListSeparator::And => " AND ",
+            })
+        );
+    }
+
+    let indent = options.indent();
+    let lines = items.iter().enumerate().map(|(i, (sql, comment))| {
+        let is_first = i == 0;
+        let is_last = i == items.len() - 1;
+
+        let mut line = indent.clone();
+        match (separator, options.comma_style) {
+            (ListSeparator::And, _) if !is_first => line.push_str("AND "),
+            (ListSeparator::Comma, CommaStyle::Leading) if !is_first => line.push_str(", "),
+            _ => {}
+        }
+        line.push_str(sql);
+        if let (ListSeparator::Comma, CommaStyle::Trailing, false) =
+            (separator, options.comma_style, is_last)
+        {
+            line.push(',');
+        }
+        if let (Some(comment), true) = (comment, options.column_comments) {
+            // A newline would end the comment early
+            line.push_str(format!(" -- {:}", comment.replace(['\r', '\n'], " ")).as_str());
+        }
+        line
+    });
+
+    format!("{keyword}\n{:}", lines.collect::<Vec<_>>().join("\n"))
+}
+
+// Keywords that the query emitter (as opposed to the Syntax's functions) produces
+const KEYWORDS: &[&str] = &[
+    "AND",
+    "AS",
+    "ASC",
+    "BETWEEN",
+    "BY",
+    "CURRENT",
+    "DESC",
+    "DISTINCT",
+    "FALSE",
+    "FETCH",
+    "FIRST",
+    "FOLLOWING",
+    "FROM",
+    "GROUP",
+    "HAVING",
+    "IN",
+    "IS",
+    "LIKE",
+    "LIMIT",
+    "NOT",
+    "NULL",
+    "ONLY",
+    "OR",
+    "ORDER",
+    "OVER",
+    "PARTITION",
+    "PRECEDING",
+    "RANGE",
+    "ROW",
+    "ROWS",
+    "SELECT",
+    "TOP",
+    "TRUE",
+    "UNBOUNDED",
+    "WHERE",
+    "WITH",
+    "WITHIN",
+];
+
+/// Change the case of the keywords in some emitted SQL, leaving strings, quoted identifiers
+/// and comments alone
+pub(crate) fn set_keyword_case(sql: &str, case: KeywordCase) -> String {
+    if case == KeywordCase::Upper {
+        // This is what we emit anyway
+        return sql.to_string();
+    }
+
+    let chars: Vec<char> = sql.chars().collect();
+    let mut result = String::with_capacity(sql.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\'' || c == '"' || c == '`' {
+            // Copy until the closing quote (doubled quotes just close and reopen)
+            result.push(c);
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                result.push(chars[i]);
+                i += 1;
+            }
+            if i < chars.len() {
+                result.push(c);
+                i += 1;
+            }
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                result.push(chars[i]);
+                i += 1;
+            }
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if KEYWORDS.contains(&word.as_str()) {
+                result.push_str(&word.to_lowercase());
+            } else {
+                result.push_str(&word);
+            }
+        } else {
+            result.push(c);
+            i += 1;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::format::{CommaStyle, FormatOptions, KeywordCase};
+    use crate::query_gen::parser::parse_query;
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, random_query, random_top_n_per_group_query,
+        ComparisonOperator, DefaultSyntax, Dimension, Filter, Literal, Measure, MeasureType,
+        OrderBy, OrderByDirection, OrderByItem, Query,
+    };
+    use crate::query_gen::scoring::DefaultScorer;
+    use crate::query_gen::socrata::{Column, ColumnFormat, ColumnStats, DataType, Dataset};
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn get_query() -> Query<DefaultSyntax> {
+        let status = Column {
+            human_name: "Status".to_string(),
+            pg_name: "status".to_string(),
+            data_type: DataType::Text,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let cost = Column {
+            human_name: "Estimated Cost".to_string(),
+            pg_name: "cost".to_string(),
+            data_type: DataType::Number,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4_acwa".to_string(),
+            name: "Building Permits".to_string(),
+            row_count: None,
+            columns: vec![status.clone(), cost.clone()],
+        };
+        let total_cost = Measure {
+            type_: MeasureType::Sum,
+            column: Some(cost.clone()),
+        };
+
+        let mut query = Query::new(
+            dataset,
+            vec![
+                Measure {
+                    type_: MeasureType::Count,
+                    column: None,
+                },
+                total_cost.clone(),
+            ],
+            vec![Dimension {
+                column: status.clone(),
+                transform: None,
+                alias: None,
+            }],
+            vec![OrderBy {
+                item: OrderByItem::Measure(total_cost),
+                direction: OrderByDirection::Desc,
+            }],
+            Some(10),
+            DefaultSyntax {},
+        );
+        query.filter = Some(Filter::And(vec![
+            Filter::IsNull {
+                column: cost,
+                negated: true,
+            },
+            Filter::Comparison {
+                column: status,
+                operator: ComparisonOperator::NotEq,
+                value: Literal::Text("NOT ISSUED".to_string()),
+            },
+        ]));
+
+        query
+    }
+
+    #[test]
+    fn test_format_default() {
+        let query = get_query();
+        assert_eq!(query.to_sql_with(&FormatOptions::default()), query.to_sql());
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  status,
+  COUNT(*) AS count,
+  SUM(cost) AS sum_cost
+FROM u7r4_acwa
+WHERE
+  cost IS NOT NULL
+  AND status <> 'NOT ISSUED'
+GROUP BY
+  status
+ORDER BY
+  SUM(cost) DESC
+LIMIT 10"#
+        );
+    }
+
+    #[test]
+    fn test_format_single_line() {
+        assert_eq!(
+            get_query().to_sql_with(&FormatOptions {
+                keyword_case: KeywordCase::Lower,
+                // Ignored on a single line
+                column_comments: true,
+                ..FormatOptions::compact()
+            }),
+            "select status, COUNT(*) as count, SUM(cost) as sum_cost from u7r4_acwa \
+             where cost is not null and status <> 'NOT ISSUED' group by status \
+             order by SUM(cost) desc limit 10"
+        );
+    }
+
+    #[test]
+    fn test_format_options() {
+        assert_eq!(
+            get_query().to_sql_with(&FormatOptions {
+                keyword_case: KeywordCase::Lower,
+                indent_width: 4,
+                comma_style: CommaStyle::Leading,
+                column_comments: true,
+                ..Default::default()
+            }),
+            r#"select
+    status -- Status
+    , COUNT(*) as count
+    , SUM(cost) as sum_cost -- Estimated Cost
+from u7r4_acwa
+where
+    cost is not null
+    and status <> 'NOT ISSUED'
+group by
+    status
+order by
+    SUM(cost) desc
+limit 10"#
+        );
+
+        assert_eq!(
+            get_query().to_sql_with(&FormatOptions {
+                column_comments: true,
+                ..Default::default()
+            }),
+            r#"SELECT
+  status, -- Status
+  COUNT(*) AS count,
+  SUM(cost) AS sum_cost -- Estimated Cost
+FROM u7r4_acwa
+WHERE
+  cost IS NOT NULL
+  AND status <> 'NOT ISSUED'
+GROUP BY
+  status
+ORDER BY
+  SUM(cost) DESC
+LIMIT 10"#
+        );
+    }
+
+    #[test]
+    fn test_format_parse_round_trip() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        let all_options = [
+            FormatOptions::compact(),
+            FormatOptions {
+                keyword_case: KeywordCase::Lower,
+                indent_width: 4,
+                comma_style: CommaStyle::Leading,
+                column_comments: true,
+                ..Default::default()
+            },
+        ];
+
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let query = random_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &default_params(),
+                DefaultSyntax {},
+            );
+            let top_n = random_top_n_per_group_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &DefaultScorer {},
+                DefaultSyntax {},
+            );
+
+            for options in &all_options {
+                assert_eq!(
+                    parse_query(&query.to_sql_with(options), &dataset, DefaultSyntax {}).unwrap(),
+                    query
+                );
+                if let Some(top_n) = &top_n {
+                    assert_eq!(
+                        parse_query(&top_n.to_sql_with(options), &dataset, DefaultSyntax {})
+                            .unwrap(),
+                        *top_n
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,2 +1,17 @@
+pub mod cost;
+pub mod dialect;
+pub mod enumerate;
+pub mod explain;
+pub mod format;
+pub mod mutate;
+pub mod parser;
 pub mod query;
+pub mod scoring;
+pub mod shapes;
 pub mod socrata;
+pub mod soql;
+#[cfg(feature = "sql-check")]
+pub mod sql_check;
+#[cfg(test)]
+pub mod test_util;
+pub mod title;
@@ -0,0 +1,392 @@
+use crate::query_gen::query::{
+    build_dimensions, build_measures, random_filter, Dimension, Filter, HavingCondition, Measure,
+    OrderBy, OrderByDirection, OrderByItem, Query, QuerySource, Syntax, WindowFunction,
+};
+use crate::query_gen::scoring::{choose_weighted, ColumnScorer};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// A small change to an existing query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutation {
+    AddDimension,
+    RemoveDimension,
+    SwapMeasure,
+    FlipOrder,
+    ChangeLimit,
+    AddFilter,
+}
+
+impl Mutation {
+    pub const ALL: [Mutation; 6] = [
+        Mutation::AddDimension,
+        Mutation::RemoveDimension,
+        Mutation::SwapMeasure,
+        Mutation::FlipOrder,
+        Mutation::ChangeLimit,
+        Mutation::AddFilter,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Mutation::AddDimension => "Group by something else too",
+            Mutation::RemoveDimension => "Group by less",
+            Mutation::SwapMeasure => "Calculate something else",
+            Mutation::FlipOrder => "Flip the order",
+            Mutation::ChangeLimit => "Change the number of rows",
+            Mutation::AddFilter => "Add a filter",
+        }
+    }
+
+    /// Whether the mutation can do anything to the query, e.g. there's no order to flip in a
+    /// query without an ORDER BY. It can still fail on a given try, e.g. if the changed query
+    /// doesn't validate.
+    pub fn applies_to<T: Syntax>(&self, query: &Query<T>) -> bool {
+        // Queries on a CTE (like the top N per group) only get reordered and relimited, since
+        // the dataset's measures and columns aren't available outside of the CTE
+        let is_on_dataset = query.source == QuerySource::Dataset;
+        // Queries that pass columns through (like the sample rows) can't group by anything
+        // without aggregating those columns too
+        let is_aggregate = query.columns.is_empty();
+
+        match self {
+            Mutation::AddDimension => {
+                is_on_dataset && is_aggregate && !dimension_candidates(query).is_empty()
+            }
+            Mutation::RemoveDimension => {
+                is_on_dataset && is_aggregate && !query.dimensions.is_empty()
+            }
+            Mutation::SwapMeasure => {
+                is_on_dataset && !query.measures.is_empty() && !measure_candidates(query).is_empty()
+            }
+            Mutation::FlipOrder => !query.order_by.is_empty(),
+            Mutation::ChangeLimit => true,
+            Mutation::AddFilter => is_on_dataset && !query.dataset.columns.is_empty(),
+        }
+    }
+}
+
+// Limits that `ChangeLimit` picks from
+const LIMITS: [usize; 6] = [10, 25, 50, 100, 500, 1000];
+
+/// Dimensions that the query's syntax supports, on columns that the query doesn't group by
+/// yet (one dimension per column is plenty)
+fn dimension_candidates<T: Syntax>(query: &Query<T>) -> Vec<Dimension> {
+    build_dimensions(&query.dataset)
+        .into_iter()
+        .filter(|d| {
+            d.transform
+                .as_ref()
+                .is_none_or(|t| query.syntax().supports_dimension_transform(t))
+                && !query.dimensions.iter().any(|q| q.column == d.column)
+        })
+        .collect()
+}
+
+/// Measures that the query's syntax supports and that the query doesn't have yet
+fn measure_candidates<T: Syntax>(query: &Query<T>) -> Vec<Measure> {
+    build_measures(&query.dataset)
+        .into_iter()
+        .filter(|m| query.syntax().supports_measure_type(&m.type_) && !query.measures.contains(m))
+        .collect()
+}
+
+fn add_dimension<R: Rng, T: Syntax + Clone>(
+    rng: &mut R,
+    query: &mut Query<T>,
+    scorer: &dyn ColumnScorer,
+) -> Option<()> {
+    let candidates = dimension_candidates(query);
+    let dimension = choose_weighted(rng, &candidates, 1, |d| scorer.score_dimension(d)).pop()?;
+
+    query.dimensions.push(dimension);
+    Some(())
+}
+
+fn remove_dimension<R: Rng, T: Syntax + Clone>(rng: &mut R, query: &mut Query<T>) -> Option<()> {
+    if query.dimensions.is_empty() {
+        return None;
+    }
+    query.remove_dimension(rng.gen_range(0..query.dimensions.len()));
+    Some(())
+}
+
+fn replace_in_order_by(order_by: &mut OrderBy, old: &Measure, new: &Measure) {
+    if order_by.item == OrderByItem::Measure(old.clone()) {
+        order_by.item = OrderByItem::Measure(new.clone());
+    }
+}
+
+fn swap_measure<R: Rng, T: Syntax + Clone>(
+    rng: &mut R,
+    query: &mut Query<T>,
+    scorer: &dyn ColumnScorer,
+) -> Option<()> {
+    if query.measures.is_empty() {
+        return None;
+    }
+    let index = rng.gen_range(0..query.measures.len());
+
+    let candidates = measure_candidates(query);
+    let new = choose_weighted(rng, &candidates, 1, |m| scorer.score_measure(m)).pop()?;
+    let old = std::mem::replace(&mut query.measures[index], new.clone());
+
+    // Carry the ordering and windows over to the new measure. The HAVING thresholds
+    // were picked for the old one, so they don't make sense anymore.
+    for order_by in query.order_by.iter_mut() {
+        replace_in_order_by(order_by, &old, &new);
+    }
+    for window_measure in query.window_measures.iter_mut() {
+        match &mut window_measure.function {
+            WindowFunction::Sum(m) | WindowFunction::ShareOfTotal(m) if *m == old => {
+                *m = new.clone()
+            }
+            _ => {}
+        }
+        for order_by in window_measure.window.order_by.iter_mut() {
+            replace_in_order_by(order_by, &old, &new);
+        }
+    }
+    query.having.retain(|h| match h {
+        HavingCondition::Comparison { measure, .. } | HavingCondition::Between { measure, .. } => {
+            *measure != old
+        }
+    });
+
+    Some(())
+}
+
+fn flip_order<R: Rng, T: Syntax + Clone>(rng: &mut R, query: &mut Query<T>) -> Option<()> {
+    let order_by = query.order_by.choose_mut(rng)?;
+    order_by.direction = match order_by.direction {
+        OrderByDirection::Asc => OrderByDirection::Desc,
+        OrderByDirection::Desc => OrderByDirection::Asc,
+    };
+    Some(())
+}
+
+fn change_limit<R: Rng, T: Syntax + Clone>(rng: &mut R, query: &mut Query<T>) -> Option<()> {
+    let limits: Vec<usize> = LIMITS
+        .into_iter()
+        .filter(|l| query.limit != Some(*l))
+        .collect();
+    query.limit = Some(*limits.choose(rng)?);
+    Some(())
+}
+
+fn add_filter<R: Rng, T: Syntax + Clone>(rng: &mut R, query: &mut Query<T>) -> Option<()> {
+    let filters: Vec<Filter> = query
+        .dataset
+        .columns
+        .iter()
+        .filter_map(|c| random_filter(rng, c))
+        .collect();
+    query.add_filter(filters.choose(rng)?.clone());
+    Some(())
+}
+
+/// Apply a mutation to a copy of the query. Returns None if the mutation doesn't apply
+/// (e.g. removing a dimension from a query without any) or if the result isn't valid.
+pub fn mutate<R: Rng, T: Syntax + Clone>(
+    rng: &mut R,
+    query: &Query<T>,
+    mutation: Mutation,
+    scorer: &dyn ColumnScorer,
+) -> Option<Query<T>> {
+    if !mutation.applies_to(query) {
+        return None;
+    }
+    let mut result = query.clone();
+
+    match mutation {
+        Mutation::AddDimension => add_dimension(rng, &mut result, scorer),
+        Mutation::RemoveDimension => remove_dimension(rng, &mut result),
+        Mutation::SwapMeasure => swap_measure(rng, &mut result, scorer),
+        Mutation::FlipOrder => flip_order(rng, &mut result),
+        Mutation::ChangeLimit => change_limit(rng, &mut result),
+        Mutation::AddFilter => add_filter(rng, &mut result),
+    }?;
+
+    if result.validate().is_empty() && result.to_sql() != query.to_sql() {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// Apply one of the given mutations, picked at random among those that apply
+#[allow(dead_code)]
+pub fn random_mutation<R: Rng, T: Syntax + Clone>(
+    rng: &mut R,
+    query: &Query<T>,
+    mutations: &[Mutation],
+    scorer: &dyn ColumnScorer,
+) -> Option<(Mutation, Query<T>)> {
+    let mut mutations = mutations.to_vec();
+    mutations.shuffle(rng);
+    mutations
+        .into_iter()
+        .find_map(|m| mutate(rng, query, m, scorer).map(|q| (m, q)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::mutate::{mutate, random_mutation, Mutation};
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, random_query, random_top_n_per_group_query,
+        DefaultSyntax, Filter, Measure, MeasureType, OrderBy, OrderByDirection, OrderByItem, Query,
+    };
+    use crate::query_gen::scoring::DefaultScorer;
+    use crate::query_gen::shapes::{QueryShape, SampleRowsShape, ShapeContext};
+    use crate::query_gen::soql::SoqlSyntax;
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_mutations() {
+        let dataset = edmonton_dataset();
+        let dimensions = build_dimensions(&dataset);
+        let count = Measure {
+            type_: MeasureType::Count,
+            column: None,
+        };
+        let query = Query::new(
+            dataset.clone(),
+            vec![count.clone()],
+            vec![dimensions[0].clone()],
+            vec![OrderBy {
+                item: OrderByItem::Measure(count.clone()),
+                direction: OrderByDirection::Desc,
+            }],
+            Some(100),
+            DefaultSyntax {},
+        );
+        let scorer = DefaultScorer {};
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let added = mutate(&mut rng, &query, Mutation::AddDimension, &scorer).unwrap();
+        assert_eq!(added.dimensions.len(), 2);
+        assert_ne!(added.dimensions[0].column, added.dimensions[1].column);
+
+        let removed = mutate(&mut rng, &query, Mutation::RemoveDimension, &scorer).unwrap();
+        assert!(removed.dimensions.is_empty());
+        // Can't remove what isn't there
+        assert!(!Mutation::RemoveDimension.applies_to(&removed));
+        assert_eq!(
+            mutate(&mut rng, &removed, Mutation::RemoveDimension, &scorer),
+            None
+        );
+
+        let swapped = mutate(&mut rng, &query, Mutation::SwapMeasure, &scorer).unwrap();
+        assert_ne!(swapped.measures[0], count);
+        // The ORDER BY follows the new measure
+        assert_eq!(
+            swapped.order_by[0].item,
+            OrderByItem::Measure(swapped.measures[0].clone())
+        );
+
+        let flipped = mutate(&mut rng, &query, Mutation::FlipOrder, &scorer).unwrap();
+        assert_eq!(flipped.order_by[0].direction, OrderByDirection::Asc);
+
+        let limited = mutate(&mut rng, &query, Mutation::ChangeLimit, &scorer).unwrap();
+        assert_ne!(limited.limit, Some(100));
+        assert!(limited.limit.is_some());
+
+        let filtered = mutate(&mut rng, &query, Mutation::AddFilter, &scorer).unwrap();
+        assert!(filtered.filter.is_some());
+        let filtered_again = mutate(&mut rng, &filtered, Mutation::AddFilter, &scorer).unwrap();
+        assert!(matches!(filtered_again.filter, Some(Filter::And(_))));
+
+        // Only dimensions and measures that the syntax supports get picked, so these don't
+        // fail on SoQL (which can't bin numbers or take percentiles)
+        let query = query.with_syntax(SoqlSyntax {});
+        for _ in 0..20 {
+            assert!(mutate(&mut rng, &query, Mutation::AddDimension, &scorer).is_some());
+            assert!(mutate(&mut rng, &query, Mutation::SwapMeasure, &scorer).is_some());
+        }
+    }
+
+    #[test]
+    fn test_mutations_sample_rows() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+        let scorer = DefaultScorer {};
+        let mut rng = StdRng::seed_from_u64(0);
+        let query = SampleRowsShape {
+            max_columns: 5,
+            rows: 10,
+        }
+        .fill(
+            &mut rng,
+            &ShapeContext {
+                dataset: &dataset,
+                measures: &measures,
+                dimensions: &dimensions,
+                scorer: &scorer,
+            },
+            DefaultSyntax {},
+        )
+        .unwrap();
+
+        // Grouping would need the passed-through columns to be aggregated
+        assert_eq!(
+            Mutation::ALL.map(|m| m.applies_to(&query)),
+            [false, false, false, false, true, true]
+        );
+        assert_eq!(
+            mutate(&mut rng, &query, Mutation::AddDimension, &scorer),
+            None
+        );
+        assert!(mutate(&mut rng, &query, Mutation::AddFilter, &scorer).is_some());
+    }
+
+    #[test]
+    fn test_random_mutations() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+        let scorer = DefaultScorer {};
+
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let query = random_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &default_params(),
+                DefaultSyntax {},
+            );
+
+            for mutation in Mutation::ALL {
+                if let Some(mutated) = mutate(&mut rng, &query, mutation, &scorer) {
+                    assert!(mutation.applies_to(&query), "{:?}", mutation);
+                    assert_eq!(mutated.validate(), vec![], "{:?}", mutation);
+                    assert_ne!(mutated, query);
+                }
+            }
+
+            let (_, mutated) = random_mutation(&mut rng, &query, &Mutation::ALL, &scorer).unwrap();
+            assert_ne!(mutated, query);
+
+            // Only the ordering and the limit of a top N per group can change
+            if let Some(top_n) = random_top_n_per_group_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &scorer,
+                DefaultSyntax {},
+            ) {
+                assert_eq!(
+                    Mutation::ALL.map(|m| m.applies_to(&top_n)),
+                    [false, false, false, true, true, false]
+                );
+                assert_eq!(mutate(&mut rng, &top_n, Mutation::AddFilter, &scorer), None);
+                assert!(mutate(&mut rng, &top_n, Mutation::ChangeLimit, &scorer).is_some());
+            }
+        }
+    }
+}
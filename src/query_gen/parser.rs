@@ -0,0 +1,1462 @@
+use crate::query_gen::query::{
+    Binning, CommonTableExpression, ComparisonOperator, DatePart, DateTruncUnit, Dimension,
+    DimensionTransform, Filter, HavingCondition, Literal, Measure, MeasureType, OrderBy,
+    OrderByDirection, OrderByItem, Query, QuerySource, Syntax, Window, WindowFrame,
+    WindowFrameBound, WindowFrameUnits, WindowFunction, WindowMeasure,
+};
+use crate::query_gen::socrata::{Column, ColumnFormat, ColumnStats, DataType, Dataset};
+use std::fmt;
+
+// Placeholders that we render through the Syntax instead of real expressions / parameters
+// to get a template that we can match the SQL against. They're in the Unicode private use
+// area, so they can't clash with anything in the actual SQL.
+const EXPRESSION_HOLE: char = '\u{E000}';
+const FIRST_PARAMETER_HOLE: char = '\u{E001}';
+const SECOND_PARAMETER_HOLE: char = '\u{E002}';
+const THIRD_PARAMETER_HOLE: char = '\u{E003}';
+// Percentiles and bucket counts are numbers, so we can't render a hole for them. We render
+// these instead and turn the numbers they come out as into parameter holes (a bucket count
+// sometimes goes into arithmetic, so count + 1 gets a hole of its own).
+const PERCENTILE_HOLE: u8 = 37;
+const BUCKET_COUNT_HOLE: u32 = 37;
+const PARAMETER_HOLES: usize = 4;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Token {
+    // Keyword, function name or unquoted identifier
+    Word(String),
+    // "Quoted identifier"
+    QuotedIdentifier(String),
+    // 'String literal'
+    String(String),
+    Number(String),
+    Symbol(String),
+    // Placeholder in a template: 0 is an expression, everything else a parameter
+    Hole(usize),
+}
+
+impl Token {
+    fn matches(&self, other: &Token) -> bool {
+        match (self, other) {
+            // Keywords and function names are case-insensitive
+            (Token::Word(a), Token::Word(b)) => a.eq_ignore_ascii_case(b),
+            _ => self == other,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    pub message: String,
+    // Index of the token we failed at
+    pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:} (at token {:})", self.message, self.position)
+    }
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let error = |message: &str, tokens: &Vec<Token>| ParseError {
+        message: message.to_string(),
+        position: tokens.len(),
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            // Comment until the end of the line
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '"' || c == '\'' {
+            // Quoted identifier or string, with the quote character escaped by doubling it
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match (chars.get(i), chars.get(i + 1)) {
+                    (Some(q), Some(next)) if *q == c && *next == c => {
+                        value.push(c);
+                        i += 2;
+                    }
+                    (Some(q), _) if *q == c => {
+                        i += 1;
+                        break;
+                    }
+                    (Some(other), _) => {
+                        value.push(*other);
+                        i += 1;
+                    }
+                    (None, _) => return Err(error("unterminated quote", &tokens)),
+                }
+            }
+            tokens.push(if c == '"' {
+                Token::QuotedIdentifier(value)
+            } else {
+                Token::String(value)
+            });
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$')
+            {
+                i += 1;
+            }
+            // Decimal point in a number
+            if chars[start].is_ascii_digit()
+                && chars.get(i) == Some(&'.')
+                && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())
+            {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+
+            let word: String = chars[start..i].iter().collect();
+            tokens.push(if word.chars().all(|c| c.is_ascii_digit() || c == '.') {
+                Token::Number(word)
+            } else {
+                Token::Word(word)
+            });
+        } else if (EXPRESSION_HOLE..=THIRD_PARAMETER_HOLE).contains(&c) {
+            tokens.push(Token::Hole(c as usize - EXPRESSION_HOLE as usize));
+            i += 1;
+        } else {
+            let pair: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            if ["<=", ">=", "<>", "!="].contains(&pair.as_str()) {
+                tokens.push(Token::Symbol(pair));
+                i += 2;
+            } else {
+                tokens.push(Token::Symbol(c.to_string()));
+                i += 1;
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Replace the numbers that we rendered instead of a hole with the hole
+fn with_number_holes(tokens: Vec<Token>, numbers: &[(String, usize)]) -> Vec<Token> {
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            Token::Number(n) => match numbers.iter().find(|(number, _)| *number == n) {
+                Some((_, hole)) => Token::Hole(*hole),
+                None => Token::Number(n),
+            },
+            token => token,
+        })
+        .collect()
+}
+
+// Percentiles are rendered as fractions, e.g. 0.9 for the 90th percentile
+fn parse_percentile(fraction: &str) -> Option<u8> {
+    let percentile = fraction.parse::<f64>().ok()? * 100.0;
+    match percentile.round() {
+        p if (p - percentile).abs() < 1e-9 && (0.0..=100.0).contains(&p) => Some(p as u8),
+        _ => None,
+    }
+}
+
+// The bucket count, checked against count + 1 if the syntax uses that too
+fn parse_bucket_count(count: &str, count_plus_one: &Option<String>) -> Option<u32> {
+    match count.parse::<u32>() {
+        Ok(count)
+            if count > 0
+                && count_plus_one
+                    .as_ref()
+                    .is_none_or(|next| *next == (count + 1).to_string()) =>
+        {
+            Some(count)
+        }
+        _ => None,
+    }
+}
+
+enum SelectItem {
+    Dimension(Dimension),
+    Measure(Measure),
+    Window(WindowMeasure),
+}
+
+struct Parser<'a, T: Syntax + Clone> {
+    tokens: Vec<Token>,
+    position: usize,
+    dataset: &'a Dataset,
+    syntax: T,
+    // Names and output columns of CTEs defined so far
+    ctes: Vec<(String, Vec<Column>)>,
+    // Templates for all measures and dimension transforms the emitter knows about,
+    // rendered through the syntax that we're parsing
+    measure_templates: Vec<(MeasureType, Vec<Token>)>,
+    dimension_templates: Vec<(Option<DimensionTransform>, Vec<Token>)>,
+    // Whether the syntax spells the median as the 50th percentile
+    median_is_percentile: bool,
+}
+
+impl<'a, T: Syntax + Clone> Parser<'a, T> {
+    fn new(sql: &str, dataset: &'a Dataset, syntax: T) -> Result<Self, ParseError> {
+        let emitter = Query::new(
+            dataset.clone(),
+            vec![],
+            vec![],
+            vec![],
+            None,
+            syntax.clone(),
+        );
+        let placeholder = Column {
+            human_name: "".to_string(),
+            pg_name: EXPRESSION_HOLE.to_string(),
+            data_type: DataType::Number,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let placeholder_sql = syntax.get_column_sql(&placeholder);
+        let template = |sql: String, numbers: &[(String, usize)]| {
+            tokenize(&sql.replace(&placeholder_sql, &EXPRESSION_HOLE.to_string()))
+                .map(|tokens| with_number_holes(tokens, numbers))
+        };
+        let percentile_numbers = [((PERCENTILE_HOLE as f64 / 100.0).to_string(), 1)];
+        let bucket_count_numbers = [
+            (BUCKET_COUNT_HOLE.to_string(), 3),
+            ((BUCKET_COUNT_HOLE + 1).to_string(), 4),
+        ];
+
+        let emit_measure = |type_: MeasureType| {
+            emitter.emit_measure(&Measure {
+                type_,
+                column: Some(placeholder.clone()),
+            })
+        };
+        let median_is_percentile =
+            emit_measure(MeasureType::Median) == emit_measure(MeasureType::Percentile(50));
+
+        // Percentiles go before the median, which only matches in dialects that have a
+        // function for it
+        let measure_types = vec![
+            MeasureType::Count,
+            MeasureType::CountDistinct,
+            MeasureType::CountNonNull,
+            MeasureType::Sum,
+            MeasureType::Average,
+            MeasureType::Min,
+            MeasureType::Max,
+            MeasureType::Percentile(PERCENTILE_HOLE),
+            MeasureType::Median,
+            MeasureType::StddevSamp,
+            MeasureType::VarSamp,
+            MeasureType::Extent,
+            MeasureType::Centroid,
+            MeasureType::CountWithinRadius {
+                latitude: FIRST_PARAMETER_HOLE.to_string(),
+                longitude: SECOND_PARAMETER_HOLE.to_string(),
+                meters: THIRD_PARAMETER_HOLE.to_string(),
+            },
+        ];
+
+        let measure_templates = measure_types
+            .into_iter()
+            .map(|type_| {
+                let measure = Measure {
+                    column: match type_ {
+                        MeasureType::Count => None,
+                        _ => Some(placeholder.clone()),
+                    },
+                    type_,
+                };
+                let numbers: &[(String, usize)] = match measure.type_ {
+                    MeasureType::Percentile(_) => &percentile_numbers,
+                    _ => &[],
+                };
+                Ok((
+                    measure.type_.clone(),
+                    template(emitter.emit_measure(&measure), numbers)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, ParseError>>()?;
+
+        let mut transforms: Vec<Option<DimensionTransform>> = vec![
+            DateTruncUnit::Day,
+            DateTruncUnit::Week,
+            DateTruncUnit::Month,
+            DateTruncUnit::Quarter,
+            DateTruncUnit::Year,
+        ]
+        .into_iter()
+        .map(|u| Some(DimensionTransform::DateTrunc(u)))
+        .collect();
+        transforms.extend(
+            [
+                DatePart::Year,
+                DatePart::Month,
+                DatePart::DayOfWeek,
+                DatePart::Hour,
+            ]
+            .into_iter()
+            .map(|p| Some(DimensionTransform::Extract(p))),
+        );
+        transforms.extend(
+            [
+                Binning::Fixed {
+                    width: FIRST_PARAMETER_HOLE.to_string(),
+                },
+                Binning::Log,
+                Binning::Range {
+                    low: FIRST_PARAMETER_HOLE.to_string(),
+                    high: SECOND_PARAMETER_HOLE.to_string(),
+                    count: BUCKET_COUNT_HOLE,
+                },
+            ]
+            .into_iter()
+            .map(|b| Some(DimensionTransform::Bin(b))),
+        );
+        transforms.push(Some(DimensionTransform::SnapToGrid {
+            size: FIRST_PARAMETER_HOLE.to_string(),
+        }));
+        // Plain columns go last, since they'd match the beginning of anything else
+        transforms.push(None);
+
+        let dimension_templates = transforms
+            .into_iter()
+            .map(|transform| {
+                let dimension = Dimension {
+                    column: placeholder.clone(),
+                    transform,
+                    alias: None,
+                };
+                let numbers: &[(String, usize)] = match dimension.transform {
+                    Some(DimensionTransform::Bin(Binning::Range { .. })) => &bucket_count_numbers,
+                    _ => &[],
+                };
+                Ok((
+                    dimension.transform.clone(),
+                    template(emitter.emit_dimension(&dimension), numbers)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, ParseError>>()?;
+
+        Ok(Self {
+            tokens: tokenize(sql)?,
+            position: 0,
+            dataset,
+            syntax,
+            ctes: vec![],
+            measure_templates,
+            dimension_templates,
+            median_is_percentile,
+        })
+    }
+
+    fn error<R>(&self, message: &str) -> Result<R, ParseError> {
+        Err(ParseError {
+            message: match self.tokens.get(self.position) {
+                Some(t) => format!("{message}, found {t:?}"),
+                None => format!("{message}, found the end of the query"),
+            },
+            position: self.position,
+        })
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        let result = self.peek_keyword(keyword);
+        if result {
+            self.position += 1;
+        }
+        result
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.accept_keyword(keyword) {
+            Ok(())
+        } else {
+            self.error(&format!("expected {keyword}"))
+        }
+    }
+
+    fn accept_symbol(&mut self, symbol: &str) -> bool {
+        let result =
+            matches!(self.tokens.get(self.position), Some(Token::Symbol(s)) if s == symbol);
+        if result {
+            self.position += 1;
+        }
+        result
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), ParseError> {
+        if self.accept_symbol(symbol) {
+            Ok(())
+        } else {
+            self.error(&format!("expected {symbol}"))
+        }
+    }
+
+    /// Consume a sequence of tokens if the query continues with it
+    fn accept_tokens(&mut self, tokens: &[Token]) -> bool {
+        let end = self.position + tokens.len();
+        let result = end <= self.tokens.len()
+            && self.tokens[self.position..end]
+                .iter()
+                .zip(tokens)
+                .all(|(a, b)| a.matches(b));
+        if result {
+            self.position = end;
+        }
+        result
+    }
+
+    fn parse_identifier(&mut self) -> Result<String, ParseError> {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(w)) | Some(Token::QuotedIdentifier(w)) => {
+                self.position += 1;
+                Ok(w.clone())
+            }
+            _ => self.error("expected an identifier"),
+        }
+    }
+
+    fn parse_number(&mut self) -> Option<String> {
+        let start = self.position;
+        let negative = self.accept_symbol("-");
+
+        match self.tokens.get(self.position) {
+            Some(Token::Number(n)) => {
+                self.position += 1;
+                Some(if negative { format!("-{n}") } else { n.clone() })
+            }
+            _ => {
+                self.position = start;
+                None
+            }
+        }
+    }
+
+    fn parse_column(&mut self, columns: &[Column]) -> Result<Column, ParseError> {
+        // Match the way the syntax renders every column, preferring the longest match
+        let matched = columns
+            .iter()
+            .filter_map(|c| {
+                let tokens = tokenize(&self.syntax.get_column_sql(c)).ok()?;
+                let end = self.position + tokens.len();
+                (end <= self.tokens.len()
+                    && self.tokens[self.position..end]
+                        .iter()
+                        .zip(&tokens)
+                        .all(|(a, b)| a == b))
+                .then_some((c, tokens.len()))
+            })
+            .max_by_key(|(_, length)| *length);
+
+        match matched {
+            Some((column, length)) => {
+                self.position += length;
+                Ok(column.clone())
+            }
+            None => self.error("expected a column"),
+        }
+    }
+
+    /// Match a template rendered through the syntax, returning the column that went into
+    /// the expression hole and the values of the parameters
+    fn match_template(
+        &mut self,
+        template: &[Token],
+        columns: &[Column],
+    ) -> Option<(Option<Column>, Vec<Option<String>>)> {
+        let start = self.position;
+        let mut column: Option<Column> = None;
+        let mut parameters: Vec<Option<String>> = vec![None; PARAMETER_HOLES];
+
+        for token in template {
+            let matched = match token {
+                Token::Hole(0) => match self.parse_column(columns) {
+                    Ok(c) if column.as_ref().is_none_or(|prev| *prev == c) => {
+                        column = Some(c);
+                        true
+                    }
+                    _ => false,
+                },
+                Token::Hole(n) => match self.parse_number() {
+                    // The same parameter can be used more than once
+                    Some(v) if parameters[n - 1].as_ref().is_none_or(|prev| *prev == v) => {
+                        parameters[n - 1] = Some(v);
+                        true
+                    }
+                    _ => false,
+                },
+                _ => self.accept_tokens(std::slice::from_ref(token)),
+            };
+
+            if !matched {
+                self.position = start;
+                return None;
+            }
+        }
+
+        Some((column, parameters))
+    }
+
+    fn try_parse_measure(&mut self, columns: &[Column]) -> Option<Measure> {
+        for i in 0..self.measure_templates.len() {
+            let start = self.position;
+            let template = self.measure_templates[i].1.clone();
+            if let Some((column, parameters)) = self.match_template(&template, columns) {
+                let parameter = |i: usize| parameters[i].clone().unwrap_or_default();
+
+                let type_ = match &self.measure_templates[i].0 {
+                    MeasureType::CountWithinRadius { .. } => Some(MeasureType::CountWithinRadius {
+                        latitude: parameter(0),
+                        longitude: parameter(1),
+                        meters: parameter(2),
+                    }),
+                    MeasureType::Percentile(_) => match parse_percentile(&parameter(0)) {
+                        Some(50) if self.median_is_percentile => Some(MeasureType::Median),
+                        percentile => percentile.map(MeasureType::Percentile),
+                    },
+                    type_ => Some(type_.clone()),
+                };
+
+                match type_ {
+                    Some(type_) => return Some(Measure { type_, column }),
+                    // Not a percentile that we can represent, try the other templates
+                    None => self.position = start,
+                }
+            }
+        }
+        None
+    }
+
+    fn try_parse_dimension(&mut self, columns: &[Column]) -> Option<Dimension> {
+        for i in 0..self.dimension_templates.len() {
+            let start = self.position;
+            let template = self.dimension_templates[i].1.clone();
+            if let Some((column, parameters)) = self.match_template(&template, columns) {
+                let parameter = |i: usize| parameters[i].clone().unwrap_or_default();
+
+                let transform = match &self.dimension_templates[i].0 {
+                    Some(DimensionTransform::Bin(Binning::Fixed { .. })) => {
+                        Some(DimensionTransform::Bin(Binning::Fixed {
+                            width: parameter(0),
+                        }))
+                    }
+                    Some(DimensionTransform::Bin(Binning::Range { .. })) => {
+                        match parse_bucket_count(&parameter(2), &parameters[3]) {
+                            Some(count) => Some(DimensionTransform::Bin(Binning::Range {
+                                low: parameter(0),
+                                high: parameter(1),
+                                count,
+                            })),
+                            None => {
+                                self.position = start;
+                                continue;
+                            }
+                        }
+                    }
+                    Some(DimensionTransform::SnapToGrid { .. }) => {
+                        Some(DimensionTransform::SnapToGrid { size: parameter(0) })
+                    }
+                    transform => transform.clone(),
+                };
+
+                return Some(Dimension {
+                    column: column?,
+                    transform,
+                    alias: None,
+                });
+            }
+        }
+        None
+    }
+
+    fn parse_dimension(&mut self, columns: &[Column]) -> Result<Dimension, ParseError> {
+        match self.try_parse_dimension(columns) {
+            Some(d) => Ok(d),
+            None => self.error("expected a column or a dimension"),
+        }
+    }
+
+    fn parse_measure(&mut self, columns: &[Column]) -> Result<Measure, ParseError> {
+        match self.try_parse_measure(columns) {
+            Some(m) => Ok(m),
+            None => self.error("expected an aggregate"),
+        }
+    }
+
+    fn parse_literal(&mut self, data_type: &DataType) -> Result<Literal, ParseError> {
+        // Some dialects write booleans as numbers, so only look for them on checkboxes
+        if *data_type == DataType::Checkbox {
+            for value in [true, false] {
+                let tokens = tokenize(&self.syntax.get_literal_sql(&Literal::Boolean(value)))?;
+                if self.accept_tokens(&tokens) {
+                    return Ok(Literal::Boolean(value));
+                }
+            }
+        }
+
+        if let Some(n) = self.parse_number() {
+            return Ok(Literal::Number(n));
+        }
+
+        match self.tokens.get(self.position).cloned() {
+            // Dates are written as strings, so use the type of the other side to tell them apart
+            Some(Token::String(s)) => {
+                self.position += 1;
+                Ok(match data_type {
+                    DataType::CalendarDate | DataType::FloatingTimestamp => Literal::Date(s),
+                    _ => Literal::Text(s),
+                })
+            }
+            _ => self.error("expected a literal"),
+        }
+    }
+
+    fn parse_comparison_operator(&mut self) -> Option<ComparisonOperator> {
+        for (symbol, operator) in [
+            ("=", ComparisonOperator::Eq),
+            ("<>", ComparisonOperator::NotEq),
+            ("!=", ComparisonOperator::NotEq),
+            ("<", ComparisonOperator::Lt),
+            ("<=", ComparisonOperator::LtEq),
+            (">", ComparisonOperator::Gt),
+            (">=", ComparisonOperator::GtEq),
+        ] {
+            if self.accept_symbol(symbol) {
+                return Some(operator);
+            }
+        }
+        None
+    }
+
+    fn parse_filter_or(&mut self, columns: &[Column]) -> Result<Filter, ParseError> {
+        let mut filters = vec![self.parse_filter_and(columns)?];
+        while self.accept_keyword("OR") {
+            filters.push(self.parse_filter_and(columns)?);
+        }
+
+        Ok(match filters.len() {
+            1 => filters.pop().unwrap(),
+            _ => Filter::Or(filters),
+        })
+    }
+
+    fn parse_filter_and(&mut self, columns: &[Column]) -> Result<Filter, ParseError> {
+        let mut filters = vec![self.parse_filter_primary(columns)?];
+        while self.accept_keyword("AND") {
+            filters.push(self.parse_filter_primary(columns)?);
+        }
+
+        Ok(match filters.len() {
+            1 => filters.pop().unwrap(),
+            _ => Filter::And(filters),
+        })
+    }
+
+    fn parse_filter_primary(&mut self, columns: &[Column]) -> Result<Filter, ParseError> {
+        if self.accept_symbol("(") {
+            let filter = self.parse_filter_or(columns)?;
+            self.expect_symbol(")")?;
+            return Ok(filter);
+        }
+
+        let column = self.parse_column(columns)?;
+
+        if self.accept_keyword("IS") {
+            let negated = self.accept_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            Ok(Filter::IsNull { column, negated })
+        } else if self.accept_keyword("IN") {
+            self.expect_symbol("(")?;
+            let mut values = vec![self.parse_literal(&column.data_type)?];
+            while self.accept_symbol(",") {
+                values.push(self.parse_literal(&column.data_type)?);
+            }
+            self.expect_symbol(")")?;
+            Ok(Filter::In { column, values })
+        } else if self.accept_keyword("BETWEEN") {
+            let low = self.parse_literal(&column.data_type)?;
+            self.expect_keyword("AND")?;
+            let high = self.parse_literal(&column.data_type)?;
+            Ok(Filter::Between { column, low, high })
+        } else if self.accept_keyword("LIKE") {
+            match self.parse_literal(&DataType::Text)? {
+                Literal::Text(pattern) => Ok(Filter::Like { column, pattern }),
+                _ => self.error("expected a LIKE pattern"),
+            }
+        } else if let Some(operator) = self.parse_comparison_operator() {
+            let value = self.parse_literal(&column.data_type)?;
+            Ok(Filter::Comparison {
+                column,
+                operator,
+                value,
+            })
+        } else {
+            self.error("expected a condition")
+        }
+    }
+
+    fn parse_having(&mut self, columns: &[Column]) -> Result<HavingCondition, ParseError> {
+        let measure = self.parse_measure(columns)?;
+        let data_type = measure.data_type();
+
+        if self.accept_keyword("BETWEEN") {
+            let low = self.parse_literal(&data_type)?;
+            self.expect_keyword("AND")?;
+            let high = self.parse_literal(&data_type)?;
+            Ok(HavingCondition::Between { measure, low, high })
+        } else if let Some(operator) = self.parse_comparison_operator() {
+            let value = self.parse_literal(&data_type)?;
+            Ok(HavingCondition::Comparison {
+                measure,
+                operator,
+                value,
+            })
+        } else {
+            self.error("expected a condition")
+        }
+    }
+
+    fn parse_order_by(&mut self, columns: &[Column]) -> Result<OrderBy, ParseError> {
+        let item = match self.try_parse_measure(columns) {
+            Some(m) => OrderByItem::Measure(m),
+            None => OrderByItem::Dimension(self.parse_dimension(columns)?),
+        };
+
+        let direction = if self.accept_keyword("DESC") {
+            OrderByDirection::Desc
+        } else {
+            self.accept_keyword("ASC");
+            OrderByDirection::Asc
+        };
+
+        Ok(OrderBy { item, direction })
+    }
+
+    fn parse_frame_bound(&mut self) -> Result<WindowFrameBound, ParseError> {
+        if self.accept_keyword("UNBOUNDED") {
+            if self.accept_keyword("PRECEDING") {
+                return Ok(WindowFrameBound::UnboundedPreceding);
+            }
+            self.expect_keyword("FOLLOWING")?;
+            return Ok(WindowFrameBound::UnboundedFollowing);
+        }
+
+        if self.accept_keyword("CURRENT") {
+            self.expect_keyword("ROW")?;
+            return Ok(WindowFrameBound::CurrentRow);
+        }
+
+        let n: u32 = match self.parse_number().map(|n| n.parse()) {
+            Some(Ok(n)) => n,
+            _ => return self.error("expected a window frame bound"),
+        };
+        if self.accept_keyword("PRECEDING") {
+            return Ok(WindowFrameBound::Preceding(n));
+        }
+        self.expect_keyword("FOLLOWING")?;
+        Ok(WindowFrameBound::Following(n))
+    }
+
+    fn parse_window(&mut self, columns: &[Column]) -> Result<Window, ParseError> {
+        self.expect_keyword("OVER")?;
+        self.expect_symbol("(")?;
+
+        let mut partition_by = vec![];
+        if self.accept_keyword("PARTITION") {
+            self.expect_keyword("BY")?;
+            partition_by.push(self.parse_dimension(columns)?);
+            while self.accept_symbol(",") {
+                partition_by.push(self.parse_dimension(columns)?);
+            }
+        }
+
+        let mut order_by = vec![];
+        if self.accept_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            order_by.push(self.parse_order_by(columns)?);
+            while self.accept_symbol(",") {
+                order_by.push(self.parse_order_by(columns)?);
+            }
+        }
+
+        let units = if self.accept_keyword("ROWS") {
+            Some(WindowFrameUnits::Rows)
+        } else if self.accept_keyword("RANGE") {
+            Some(WindowFrameUnits::Range)
+        } else {
+            None
+        };
+        let frame = match units {
+            Some(units) => {
+                self.expect_keyword("BETWEEN")?;
+                let start = self.parse_frame_bound()?;
+                self.expect_keyword("AND")?;
+                let end = self.parse_frame_bound()?;
+                Some(WindowFrame { units, start, end })
+            }
+            None => None,
+        };
+
+        self.expect_symbol(")")?;
+
+        Ok(Window {
+            partition_by,
+            order_by,
+            frame,
+        })
+    }
+
+    fn try_parse_window_function(&mut self, columns: &[Column]) -> Option<WindowFunction> {
+        let start = self.position;
+
+        for (keyword, function) in [
+            ("RANK", WindowFunction::Rank),
+            ("DENSE_RANK", WindowFunction::DenseRank),
+            ("ROW_NUMBER", WindowFunction::RowNumber),
+        ] {
+            if self.accept_keyword(keyword) && self.accept_symbol("(") && self.accept_symbol(")") {
+                return Some(function);
+            }
+            self.position = start;
+        }
+
+        // SUM(measure) OVER (...)
+        if self.accept_keyword("SUM") && self.accept_symbol("(") {
+            if let Some(measure) = self.try_parse_measure(columns) {
+                if self.accept_symbol(")") && self.peek_keyword("OVER") {
+                    return Some(WindowFunction::Sum(measure));
+                }
+            }
+        }
+        self.position = start;
+
+        // measure / SUM(measure) OVER (...)
+        if let Some(measure) = self.try_parse_measure(columns) {
+            if self.accept_symbol("/") && self.accept_keyword("SUM") && self.accept_symbol("(") {
+                if let Some(total) = self.try_parse_measure(columns) {
+                    if total == measure && self.accept_symbol(")") && self.peek_keyword("OVER") {
+                        return Some(WindowFunction::ShareOfTotal(measure));
+                    }
+                }
+            }
+        }
+        self.position = start;
+
+        None
+    }
+
+    /// Parse an item in the SELECT list, as well as its alias and where we found it
+    fn parse_select_item(
+        &mut self,
+        columns: &[Column],
+    ) -> Result<(SelectItem, Option<(String, usize)>), ParseError> {
+        let item = if let Some(function) = self.try_parse_window_function(columns) {
+            SelectItem::Window(WindowMeasure {
+                function,
+                window: self.parse_window(columns)?,
+            })
+        } else if let Some(measure) = self.try_parse_measure(columns) {
+            SelectItem::Measure(measure)
+        } else {
+            SelectItem::Dimension(self.parse_dimension(columns)?)
+        };
+
+        let alias = if self.accept_keyword("AS") {
+            let position = self.position;
+            Some((self.parse_identifier()?, position))
+        } else {
+            None
+        };
+
+        Ok((item, alias))
+    }
+
+    /// Skip to the FROM of the current SELECT, ignoring the ones inside of parentheses,
+    /// e.g. in EXTRACT(YEAR FROM x)
+    fn skip_to_from(&mut self) -> Result<(), ParseError> {
+        let mut depth = 0;
+        while self.position < self.tokens.len() {
+            match &self.tokens[self.position] {
+                Token::Symbol(s) if s == "(" => depth += 1,
+                Token::Symbol(s) if s == ")" => depth -= 1,
+                Token::Word(w) if depth == 0 && w.eq_ignore_ascii_case("FROM") => return Ok(()),
+                _ => {}
+            }
+            self.position += 1;
+        }
+        self.error("expected FROM")
+    }
+
+    fn parse_source(&mut self) -> Result<(QuerySource, Vec<Column>), ParseError> {
+        let dataset_tokens = tokenize(&self.syntax.get_dataset_sql(self.dataset))?;
+        if self.accept_tokens(&dataset_tokens) {
+            return Ok((QuerySource::Dataset, self.dataset.columns.clone()));
+        }
+
+        let position = self.position;
+        let name = self.parse_identifier()?;
+        match self.ctes.iter().find(|(n, _)| *n == name) {
+            Some((_, columns)) => Ok((
+                QuerySource::CommonTableExpression(name.clone()),
+                columns.clone(),
+            )),
+            None => {
+                self.position = position;
+                self.error("expected the dataset or a CTE")
+            }
+        }
+    }
+
+    fn parse_row_count(&mut self) -> Result<usize, ParseError> {
+        match self.parse_number().map(|n| n.parse()) {
+            Some(Ok(l)) => Ok(l),
+            _ => self.error("expected a row count"),
+        }
+    }
+
+    fn parse_select(&mut self) -> Result<Query<T>, ParseError> {
+        self.expect_keyword("SELECT")?;
+
+        let mut limit = if self.accept_keyword("TOP") {
+            Some(self.parse_row_count()?)
+        } else {
+            None
+        };
+
+        // We need to know what we're selecting from to resolve the columns
+        let select_start = self.position;
+        self.skip_to_from()?;
+        self.expect_keyword("FROM")?;
+        let (source, columns) = self.parse_source()?;
+        let select_end = self.position;
+
+        self.position = select_start;
+        let mut items = vec![self.parse_select_item(&columns)?];
+        while self.accept_symbol(",") {
+            items.push(self.parse_select_item(&columns)?);
+        }
+        if !self.peek_keyword("FROM") {
+            return self.error("expected , or FROM");
+        }
+        self.position = select_end;
+
+        let filter = if self.accept_keyword("WHERE") {
+            Some(self.parse_filter_or(&columns)?)
+        } else {
+            None
+        };
+
+        let mut group_by = vec![];
+        if self.accept_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            group_by.push(self.parse_dimension(&columns)?);
+            while self.accept_symbol(",") {
+                group_by.push(self.parse_dimension(&columns)?);
+            }
+        }
+
+        let mut having = vec![];
+        if self.accept_keyword("HAVING") {
+            having.push(self.parse_having(&columns)?);
+            while self.accept_keyword("AND") {
+                having.push(self.parse_having(&columns)?);
+            }
+        }
+
+        let mut order_by = vec![];
+        if self.accept_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            order_by.push(self.parse_order_by(&columns)?);
+            while self.accept_symbol(",") {
+                order_by.push(self.parse_order_by(&columns)?);
+            }
+        }
+
+        if self.accept_keyword("LIMIT") {
+            limit = Some(self.parse_row_count()?);
+        } else if self.accept_keyword("FETCH") {
+            if !self.accept_keyword("FIRST") {
+                self.expect_keyword("NEXT")?;
+            }
+            limit = Some(self.parse_row_count()?);
+            if !self.accept_keyword("ROWS") {
+                self.expect_keyword("ROW")?;
+            }
+            self.expect_keyword("ONLY")?;
+        }
+
+        let mut plain_columns = vec![];
+        let mut dimensions = vec![];
+        let mut measures = vec![];
+        let mut window_measures = vec![];
+        // Aliases of all of the above, in the same order as output_aliases()
+        let mut aliases = [vec![], vec![], vec![], vec![]];
+
+        for (item, alias) in items {
+            match item {
+                // Without a GROUP BY, plain columns are just passed through
+                SelectItem::Dimension(Dimension {
+                    column,
+                    transform: None,
+                    ..
+                }) if group_by.is_empty() => {
+                    plain_columns.push(column);
+                    aliases[0].push(alias);
+                }
+                SelectItem::Dimension(d) => {
+                    dimensions.push(d);
+                    aliases[1].push(alias);
+                }
+                SelectItem::Measure(m) => {
+                    measures.push(m);
+                    aliases[2].push(alias);
+                }
+                SelectItem::Window(w) => {
+                    window_measures.push(w);
+                    aliases[3].push(alias);
+                }
+            }
+        }
+
+        // Every dimension is both selected and grouped on
+        if dimensions != group_by {
+            return Err(ParseError {
+                message: "GROUP BY doesn't match the selected dimensions".to_string(),
+                position: select_start,
+            });
+        }
+
+        let mut query = Query::new(
+            self.dataset.clone(),
+            measures,
+            dimensions,
+            order_by,
+            limit,
+            self.syntax.clone(),
+        );
+        query.source = source;
+        query.columns = plain_columns;
+        query.window_measures = window_measures;
+        query.filter = filter;
+        query.having = having;
+
+        // Dimensions can be called anything, but the other aliases are derived from the
+        // expression, so we can only check them
+        let generated = query.output_aliases();
+        let offset = query.columns.len();
+        for (i, alias) in aliases[1].iter().enumerate() {
+            if let Some((alias, _)) = alias {
+                if generated[offset + i] != *alias {
+                    query.dimensions[i].alias = Some(alias.clone());
+                }
+            }
+        }
+
+        for (alias, generated) in aliases.concat().into_iter().zip(query.output_aliases()) {
+            if let Some((alias, position)) = alias {
+                if alias != generated {
+                    return Err(ParseError {
+                        message: format!("expected alias {generated}, found {alias}"),
+                        position,
+                    });
+                }
+            }
+        }
+
+        Ok(query)
+    }
+
+    fn parse_query(&mut self) -> Result<Query<T>, ParseError> {
+        let mut ctes = vec![];
+
+        if self.accept_keyword("WITH") {
+            loop {
+                let name = self.parse_identifier()?;
+                self.expect_keyword("AS")?;
+                self.expect_symbol("(")?;
+                let query = self.parse_select()?;
+                self.expect_symbol(")")?;
+
+                self.ctes.push((name.clone(), query.output_columns()));
+                ctes.push(CommonTableExpression { name, query });
+
+                if !self.accept_symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        let mut query = self.parse_select()?;
+        query.ctes = ctes;
+
+        self.accept_symbol(";");
+        if self.position < self.tokens.len() {
+            return self.error("expected the end of the query");
+        }
+
+        Ok(query)
+    }
+}
+
+/// Parse the SQL emitted by `Query::to_sql` back into a `Query`, resolving columns against the
+/// dataset. Identifiers and functions are matched against the way `syntax` renders them.
+pub fn parse_query<T: Syntax + Clone>(
+    sql: &str,
+    dataset: &Dataset,
+    syntax: T,
+) -> Result<Query<T>, ParseError> {
+    Parser::new(sql, dataset, syntax)?.parse_query()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::dialect::DuckDbSyntax;
+    use crate::query_gen::parser::parse_query;
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, random_query, random_top_n_per_group_query, Binning,
+        ComparisonOperator, DateTruncUnit, DefaultSyntax, Dimension, DimensionTransform, Filter,
+        HavingCondition, Literal, Measure, MeasureType, OrderBy, OrderByDirection, OrderByItem,
+        Query, RandomQueryParams,
+    };
+    use crate::query_gen::scoring::DefaultScorer;
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_parse_query() {
+        let dataset = edmonton_dataset();
+        let column = |pg_name: &str| {
+            dataset
+                .columns
+                .iter()
+                .find(|c| c.pg_name == pg_name)
+                .unwrap()
+                .clone()
+        };
+
+        let query = parse_query(
+            r#"select
+  date_trunc('month', notification_start_date) AS month_notification_start_date,
+  COUNT(*),
+  AVG(latitude) AS avg_latitude
+FROM "u7r4-acwa"
+WHERE
+  status = 'Open'
+  AND (notification_start_date >= '2020-01-01' OR latitude IS NOT NULL)
+GROUP BY
+  date_trunc('month', notification_start_date)
+HAVING
+  COUNT(*) > 10
+ORDER BY
+  date_trunc('month', notification_start_date) ASC,
+  COUNT(*) DESC
+LIMIT 100;"#,
+            &dataset,
+            DefaultSyntax {},
+        )
+        .unwrap();
+
+        let month = Dimension {
+            column: column("notification_start_date"),
+            transform: Some(DimensionTransform::DateTrunc(DateTruncUnit::Month)),
+            alias: None,
+        };
+        let count = Measure {
+            type_: MeasureType::Count,
+            column: None,
+        };
+
+        let mut expected = Query::new(
+            dataset.clone(),
+            vec![
+                count.clone(),
+                Measure {
+                    type_: MeasureType::Average,
+                    column: Some(column("latitude")),
+                },
+            ],
+            vec![month.clone()],
+            vec![
+                OrderBy {
+                    item: OrderByItem::Dimension(month),
+                    direction: OrderByDirection::Asc,
+                },
+                OrderBy {
+                    item: OrderByItem::Measure(count.clone()),
+                    direction: OrderByDirection::Desc,
+                },
+            ],
+            Some(100),
+            DefaultSyntax {},
+        );
+        expected.filter = Some(Filter::And(vec![
+            Filter::Comparison {
+                column: column("status"),
+                operator: ComparisonOperator::Eq,
+                value: Literal::Text("Open".to_string()),
+            },
+            Filter::Or(vec![
+                Filter::Comparison {
+                    column: column("notification_start_date"),
+                    operator: ComparisonOperator::GtEq,
+                    value: Literal::Date("2020-01-01".to_string()),
+                },
+                Filter::IsNull {
+                    column: column("latitude"),
+                    negated: true,
+                },
+            ]),
+        ]));
+        expected.having = vec![HavingCondition::Comparison {
+            measure: count,
+            operator: ComparisonOperator::Gt,
+            value: Literal::Number("10".to_string()),
+        }];
+
+        assert_eq!(query, expected);
+    }
+
+    #[test]
+    fn test_parse_query_dimension_alias() {
+        let dataset = edmonton_dataset();
+
+        let query = parse_query(
+            "SELECT status AS state, COUNT(*) FROM \"u7r4-acwa\" GROUP BY status",
+            &dataset,
+            DefaultSyntax {},
+        )
+        .unwrap();
+
+        assert_eq!(query.dimensions[0].alias, Some("state".to_string()));
+        assert_eq!(
+            query.output_aliases(),
+            vec!["state".to_string(), "count".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_parse_query_geospatial() {
+        let dataset = edmonton_dataset();
+        let location = dataset
+            .columns
+            .iter()
+            .find(|c| c.pg_name == "location")
+            .unwrap()
+            .clone();
+        let nearby = Measure {
+            type_: MeasureType::CountWithinRadius {
+                latitude: "53.5".to_string(),
+                longitude: "-113.5".to_string(),
+                meters: "1000".to_string(),
+            },
+            column: Some(location.clone()),
+        };
+
+        let query = Query::new(
+            dataset.clone(),
+            vec![
+                Measure {
+                    type_: MeasureType::Extent,
+                    column: Some(location.clone()),
+                },
+                Measure {
+                    type_: MeasureType::Centroid,
+                    column: Some(location.clone()),
+                },
+                nearby.clone(),
+            ],
+            vec![Dimension {
+                column: location,
+                transform: Some(DimensionTransform::SnapToGrid {
+                    size: "0.1".to_string(),
+                }),
+                alias: None,
+            }],
+            vec![OrderBy {
+                item: OrderByItem::Measure(nearby),
+                direction: OrderByDirection::Desc,
+            }],
+            Some(100),
+            DefaultSyntax {},
+        );
+
+        assert_eq!(
+            parse_query(&query.to_sql(), &dataset, DefaultSyntax {}).unwrap(),
+            query
+        );
+    }
+
+    #[test]
+    fn test_parse_query_numeric_parameters() {
+        let dataset = edmonton_dataset();
+        let latitude = dataset
+            .columns
+            .iter()
+            .find(|c| c.pg_name == "latitude")
+            .unwrap()
+            .clone();
+        let measure = |type_: MeasureType| Measure {
+            type_,
+            column: Some(latitude.clone()),
+        };
+        let measures = vec![
+            measure(MeasureType::Percentile(0)),
+            measure(MeasureType::Percentile(7)),
+            measure(MeasureType::Percentile(100)),
+            measure(MeasureType::Median),
+        ];
+        let buckets = Dimension {
+            column: latitude.clone(),
+            transform: Some(DimensionTransform::Bin(Binning::Range {
+                low: "53".to_string(),
+                high: "54".to_string(),
+                count: 150,
+            })),
+            alias: None,
+        };
+
+        let query = Query::new(
+            dataset.clone(),
+            measures.clone(),
+            vec![buckets.clone()],
+            vec![],
+            Some(100),
+            DefaultSyntax {},
+        );
+        assert_eq!(
+            parse_query(&query.to_sql(), &dataset, DefaultSyntax {}).unwrap(),
+            query
+        );
+
+        // DuckDB has a median function and puts the bucket count into arithmetic
+        let query = Query::new(
+            dataset.clone(),
+            measures,
+            vec![buckets],
+            vec![],
+            Some(100),
+            DuckDbSyntax {},
+        );
+        assert_eq!(
+            parse_query(&query.to_sql(), &dataset, DuckDbSyntax {}).unwrap(),
+            query
+        );
+
+        // The 50th percentile is the median where they're spelled the same
+        let parsed = parse_query(
+            "SELECT PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY latitude) AS median_latitude \
+             FROM \"u7r4-acwa\"",
+            &dataset,
+            DefaultSyntax {},
+        )
+        .unwrap();
+        assert_eq!(parsed.measures, vec![measure(MeasureType::Median)]);
+
+        // Not a whole percentile
+        assert!(parse_query(
+            "SELECT PERCENTILE_CONT(0.375) WITHIN GROUP (ORDER BY latitude) AS p37_latitude \
+             FROM \"u7r4-acwa\"",
+            &dataset,
+            DefaultSyntax {}
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_query_errors() {
+        let dataset = edmonton_dataset();
+
+        // Unknown column
+        let error = parse_query(
+            "SELECT no_such_column FROM \"u7r4-acwa\"",
+            &dataset,
+            DefaultSyntax {},
+        )
+        .unwrap_err();
+        assert_eq!(error.position, 1);
+
+        // Unknown table
+        assert!(parse_query("SELECT status FROM some_table", &dataset, DefaultSyntax {}).is_err());
+
+        // Alias that we wouldn't have generated
+        assert!(parse_query(
+            "SELECT AVG(latitude) AS lat FROM \"u7r4-acwa\"",
+            &dataset,
+            DefaultSyntax {}
+        )
+        .is_err());
+
+        // Grouping on something that isn't selected
+        assert!(parse_query(
+            "SELECT status, COUNT(*) FROM \"u7r4-acwa\" GROUP BY status, reason",
+            &dataset,
+            DefaultSyntax {}
+        )
+        .is_err());
+
+        // Trailing garbage
+        assert!(parse_query(
+            "SELECT status FROM \"u7r4-acwa\" LIMIT 10 OFFSET 10",
+            &dataset,
+            DefaultSyntax {}
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_query_round_trip() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        for seed in 0..200 {
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            let query = random_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &RandomQueryParams {
+                    no_dimensions: 0..3,
+                    no_window_measures: 0..3,
+                    no_filters: 0..4,
+                    ..default_params()
+                },
+                DefaultSyntax {},
+            );
+            assert_eq!(
+                parse_query(&query.to_sql(), &dataset, DefaultSyntax {}).unwrap(),
+                query
+            );
+
+            let query = random_top_n_per_group_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &DefaultScorer {},
+                DefaultSyntax {},
+            )
+            .unwrap();
+            assert_eq!(
+                parse_query(&query.to_sql(), &dataset, DefaultSyntax {}).unwrap(),
+                query
+            );
+        }
+    }
+}
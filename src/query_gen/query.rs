@@ -1,4 +1,9 @@
-use crate::query_gen::socrata::{Column, DataType, Dataset};
+use crate::query_gen::cost::{estimate_relative_cost, reduce_cost};
+use crate::query_gen::format::{format_clause, set_keyword_case, FormatOptions, ListSeparator};
+use crate::query_gen::scoring::{choose_weighted, ColumnScorer};
+use crate::query_gen::socrata::{Column, ColumnFormat, ColumnStats, DataType, Dataset};
+#[cfg(feature = "sql-check")]
+use crate::query_gen::sql_check::check_sql;
 use itertools::Itertools;
 use rand::seq::SliceRandom;
 use rand::Rng;
@@ -6,11 +11,31 @@ use std::ops::Range;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum MeasureType {
+    // COUNT(*)
     Count,
+    // COUNT(DISTINCT x)
+    CountDistinct,
+    // COUNT(x), i.e. the number of non-null values
+    CountNonNull,
     Sum,
     Average,
     Min,
     Max,
+    Median,
+    // Continuous percentile, e.g. Percentile(90) for the 90th percentile
+    Percentile(u8),
+    StddevSamp,
+    VarSamp,
+    // Bounding box of all geometries, e.g. ST_Extent(x)
+    Extent,
+    // Center of all geometries, e.g. ST_Centroid(ST_Collect(x))
+    Centroid,
+    // Number of rows with a geometry within `meters` of a point
+    CountWithinRadius {
+        latitude: String,
+        longitude: String,
+        meters: String,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -19,11 +44,92 @@ pub struct Measure {
     pub column: Option<Column>,
 }
 
+impl Measure {
+    /// Type of the aggregated value
+    pub fn data_type(&self) -> DataType {
+        match (&self.type_, &self.column) {
+            (
+                MeasureType::Sum
+                | MeasureType::Average
+                | MeasureType::Min
+                | MeasureType::Max
+                | MeasureType::Median
+                | MeasureType::Percentile(_),
+                Some(c),
+            ) => c.data_type.clone(),
+            (MeasureType::Extent, _) => DataType::Multipolygon,
+            (MeasureType::Centroid, _) => DataType::Point,
+            _ => DataType::Number,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DateTruncUnit {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DatePart {
+    Year,
+    Month,
+    DayOfWeek,
+    Hour,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Binning {
+    // Fixed-width buckets: floor(x / width) * width
+    Fixed {
+        width: String,
+    },
+    // `count` equal buckets between `low` and `high`: width_bucket(x, low, high, count)
+    Range {
+        low: String,
+        high: String,
+        count: u32,
+    },
+    // Order of magnitude: floor(log(x))
+    Log,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DimensionTransform {
+    // Bucket a timestamp, e.g. date_trunc('month', x)
+    DateTrunc(DateTruncUnit),
+    // Get a part of a timestamp, e.g. EXTRACT(YEAR FROM x)
+    Extract(DatePart),
+    // Bucket a number, e.g. floor(x / 100) * 100
+    Bin(Binning),
+    // Snap a point to a grid of `size` degrees of latitude/longitude, e.g.
+    // ST_SnapToGrid(x, 0.01)
+    SnapToGrid { size: String },
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Dimension {
-    // No need to specify a dimension type since we can't use functions
-    // in GROUP BY clauses right now
     pub column: Column,
+    pub transform: Option<DimensionTransform>,
+    // Name of the output column, if it shouldn't be derived from the column and transform
+    pub alias: Option<String>,
+}
+
+impl Dimension {
+    /// Type of the grouped value
+    pub fn data_type(&self) -> DataType {
+        match self.transform {
+            None
+            | Some(DimensionTransform::DateTrunc(_))
+            | Some(DimensionTransform::SnapToGrid { .. }) => self.column.data_type.clone(),
+            Some(DimensionTransform::Extract(_)) | Some(DimensionTransform::Bin(_)) => {
+                DataType::Number
+            }
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -44,11 +150,202 @@ pub struct OrderBy {
     pub direction: OrderByDirection,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Literal {
+    // Numbers are kept as their SQL text so that we don't lose precision
+    // (and can still derive Eq)
+    Number(String),
+    Text(String),
+    Boolean(bool),
+    // ISO 8601 date, e.g. 2020-01-31
+    Date(String),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ComparisonOperator {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Filter {
+    Comparison {
+        column: Column,
+        operator: ComparisonOperator,
+        value: Literal,
+    },
+    In {
+        column: Column,
+        values: Vec<Literal>,
+    },
+    Between {
+        column: Column,
+        low: Literal,
+        high: Literal,
+    },
+    IsNull {
+        column: Column,
+        negated: bool,
+    },
+    Like {
+        column: Column,
+        pattern: String,
+    },
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum HavingCondition {
+    Comparison {
+        measure: Measure,
+        operator: ComparisonOperator,
+        value: Literal,
+    },
+    Between {
+        measure: Measure,
+        low: Literal,
+        high: Literal,
+    },
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum WindowFrameUnits {
+    Rows,
+    Range,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum WindowFrameBound {
+    UnboundedPreceding,
+    Preceding(u32),
+    CurrentRow,
+    Following(u32),
+    UnboundedFollowing,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct WindowFrame {
+    pub units: WindowFrameUnits,
+    pub start: WindowFrameBound,
+    pub end: WindowFrameBound,
+}
+
+/// The OVER (...) clause of a window function
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Window {
+    pub partition_by: Vec<Dimension>,
+    pub order_by: Vec<OrderBy>,
+    pub frame: Option<WindowFrame>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum WindowFunction {
+    Rank,
+    DenseRank,
+    RowNumber,
+    // Aggregate a measure again over the window, e.g. SUM(SUM(x)) OVER (ORDER BY ...)
+    // for a running total
+    Sum(Measure),
+    // Measure as a fraction of its total over the window: SUM(x) / SUM(SUM(x)) OVER (...)
+    ShareOfTotal(Measure),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct WindowMeasure {
+    pub function: WindowFunction,
+    pub window: Window,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum QuerySource {
+    // The dataset itself
+    Dataset,
+    // A CTE defined in the query's WITH clause
+    CommonTableExpression(String),
+}
+
+/// WITH name AS (query)
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct CommonTableExpression<T: Syntax> {
+    pub name: String,
+    pub query: Query<T>,
+}
+
+/// Problem found by `Query::validate`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Diagnostic {
+    // Nothing to SELECT
+    EmptySelect,
+    // More than one output column with the same name
+    DuplicateAlias(String),
+    // A plain column next to aggregates, without being in the GROUP BY
+    UngroupedColumn(Column),
+    // A dimension used in a window function, without being in the GROUP BY
+    UngroupedDimension(Dimension),
+    // ORDER BY something that isn't in the result of the query
+    OrderByNotSelectable(OrderBy),
+    // Aggregate that doesn't work on the column's type, e.g. SUM(text)
+    IncompatibleMeasure(Measure),
+    // Transform that doesn't work on the column's type, e.g. date_trunc on a number
+    IncompatibleDimension(Dimension),
+    // Identifier that the Syntax can't put in the query
+    UnrepresentableIdentifier(String),
+    // Aggregate that the Syntax doesn't have, e.g. a median in SQLite
+    UnsupportedMeasure(Measure),
+    // Transform that the Syntax doesn't have
+    UnsupportedDimension(Dimension),
+    // ORDER BY something that can't be sorted, e.g. a geometry
+    UnorderableOrderBy(OrderBy),
+    // Window function in a Syntax that doesn't have them
+    UnsupportedWindowMeasure(WindowMeasure),
+    // CTE in a Syntax that doesn't have them
+    UnsupportedCommonTableExpression(String),
+    // Emitted SQL that a real parser for the Syntax's dialect rejects (with the
+    // `sql-check` feature)
+    #[cfg_attr(not(feature = "sql-check"), allow(dead_code))]
+    UnparseableSql(String),
+}
+
+/// SQL dialect that a Syntax targets, to check its output against
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum SqlDialect {
+    Generic,
+    PostgreSql,
+    SQLite,
+    DuckDb,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum LimitStyle {
+    // ... LIMIT n
+    Limit,
+    // SELECT TOP n ...
+    #[allow(dead_code)]
+    Top,
+    // ... FETCH FIRST n ROWS ONLY
+    #[allow(dead_code)]
+    FetchFirst,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Query<T: Syntax> {
     pub dataset: Dataset,
+    pub ctes: Vec<CommonTableExpression<T>>,
+    pub source: QuerySource,
+    // Plain columns that aren't grouped on, e.g. when selecting from a CTE
+    pub columns: Vec<Column>,
     pub measures: Vec<Measure>,
     pub dimensions: Vec<Dimension>,
+    // Window functions over the aggregated measures
+    pub window_measures: Vec<WindowMeasure>,
+    pub filter: Option<Filter>,
+    // All conditions are ANDed together
+    pub having: Vec<HavingCondition>,
     pub order_by: Vec<OrderBy>,
     pub limit: Option<usize>,
 
@@ -60,57 +357,511 @@ pub trait Syntax {
     fn get_dataset_sql(&self, dataset: &Dataset) -> String;
     /// Get an SQL identifier for a column
     fn get_column_sql(&self, column: &Column) -> String;
+    /// Get an SQL identifier for anything else (e.g. a CTE)
+    fn get_identifier_sql(&self, identifier: &str) -> String;
+    /// Whether an identifier (column, alias or CTE name) can be used in a query. By default,
+    /// only allow identifiers that don't need quoting.
+    fn can_represent_identifier(&self, identifier: &str) -> bool {
+        let mut chars = identifier.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+    /// Turn a generated name into an alias that can be used unquoted and isn't taken yet.
+    /// The result still needs to go through `get_identifier_sql`.
+    fn get_alias(&self, name: &str, taken: &[String]) -> String {
+        // Replace everything that would need quoting, e.g. :@computed_region_7ccj_gre3
+        // becomes computed_region_7ccj_gre3
+        let mut alias = name
+            .to_ascii_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|p| !p.is_empty())
+            .join("_");
+        if !alias.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            alias = format!("c_{alias}");
+        }
+
+        let mut candidate = alias.clone();
+        let mut suffix = 2;
+        while taken.contains(&candidate) {
+            candidate = format!("{alias}_{suffix}");
+            suffix += 1;
+        }
+        candidate
+    }
+    /// Get an SQL literal for a constant
+    fn get_literal_sql(&self, literal: &Literal) -> String {
+        match literal {
+            Literal::Number(n) => n.clone(),
+            Literal::Text(s) | Literal::Date(s) => format!("'{:}'", s.replace('\'', "''")),
+            Literal::Boolean(true) => "TRUE".to_string(),
+            Literal::Boolean(false) => "FALSE".to_string(),
+        }
+    }
+    /// Truncate a timestamp expression to a given unit
+    fn get_date_trunc_sql(&self, unit: &DateTruncUnit, expression: &str) -> String {
+        let unit = match unit {
+            DateTruncUnit::Day => "day",
+            DateTruncUnit::Week => "week",
+            DateTruncUnit::Month => "month",
+            DateTruncUnit::Quarter => "quarter",
+            DateTruncUnit::Year => "year",
+        };
+        format!("date_trunc('{unit}', {expression})")
+    }
+    /// Extract a part of a timestamp expression
+    fn get_extract_sql(&self, part: &DatePart, expression: &str) -> String {
+        let part = match part {
+            DatePart::Year => "YEAR",
+            DatePart::Month => "MONTH",
+            DatePart::DayOfWeek => "DOW",
+            DatePart::Hour => "HOUR",
+        };
+        format!("EXTRACT({part} FROM {expression})")
+    }
+    /// Whether this dialect has a given aggregate
+    fn supports_measure_type(&self, _measure_type: &MeasureType) -> bool {
+        true
+    }
+    /// Whether this dialect has a given dimension transform
+    fn supports_dimension_transform(&self, _transform: &DimensionTransform) -> bool {
+        true
+    }
+    /// Whether this dialect has window functions (OVER (...))
+    fn supports_window_functions(&self) -> bool {
+        true
+    }
+    /// Whether this dialect has WITH ... AS (...)
+    fn supports_common_table_expressions(&self) -> bool {
+        true
+    }
+    /// Get an aggregate of an expression (ignored for COUNT(*))
+    fn get_aggregate_sql(&self, measure_type: &MeasureType, expression: &str) -> String {
+        match measure_type {
+            MeasureType::Count => "COUNT(*)".to_string(),
+            MeasureType::CountDistinct => format!("COUNT(DISTINCT {expression})"),
+            MeasureType::CountNonNull => format!("COUNT({expression})"),
+            MeasureType::Sum => format!("SUM({expression})"),
+            MeasureType::Average => format!("AVG({expression})"),
+            MeasureType::Min => format!("MIN({expression})"),
+            MeasureType::Max => format!("MAX({expression})"),
+            MeasureType::Median => self.get_median_sql(expression),
+            MeasureType::Percentile(p) => self.get_percentile_sql(*p, expression),
+            MeasureType::StddevSamp => format!("STDDEV_SAMP({expression})"),
+            MeasureType::VarSamp => format!("VAR_SAMP({expression})"),
+            MeasureType::Extent => self.get_extent_sql(expression),
+            MeasureType::Centroid => self.get_centroid_sql(expression),
+            MeasureType::CountWithinRadius {
+                latitude,
+                longitude,
+                meters,
+            } => self.get_count_within_radius_sql(latitude, longitude, meters, expression),
+        }
+    }
+    /// How to limit the number of rows in the result
+    fn get_limit_style(&self) -> LimitStyle {
+        LimitStyle::Limit
+    }
+    /// Dialect that the emitted SQL should parse in, or None if it isn't SQL
+    #[cfg_attr(not(feature = "sql-check"), allow(dead_code))]
+    fn get_sql_dialect(&self) -> Option<SqlDialect> {
+        Some(SqlDialect::Generic)
+    }
+    /// Get a continuous percentile (0-100) of a numeric expression
+    fn get_percentile_sql(&self, percentile: u8, expression: &str) -> String {
+        format!(
+            "PERCENTILE_CONT({:}) WITHIN GROUP (ORDER BY {expression})",
+            percentile as f64 / 100.0
+        )
+    }
+    /// Get the median of a numeric expression
+    fn get_median_sql(&self, expression: &str) -> String {
+        self.get_percentile_sql(50, expression)
+    }
+    /// Put a numeric expression into a bucket
+    fn get_bin_sql(&self, binning: &Binning, expression: &str) -> String {
+        match binning {
+            Binning::Fixed { width } => format!("floor({expression} / {width}) * {width}"),
+            Binning::Range { low, high, count } => {
+                format!("width_bucket({expression}, {low}, {high}, {count})")
+            }
+            // Lump everything below 1 (including zeroes and negative numbers that would
+            // make log() fail) into the 0th bucket
+            Binning::Log => format!("floor(log(greatest({expression}, 1)))"),
+        }
+    }
+    // The geospatial functions default to PostGIS, with geometries in WGS 84 (SRID 4326)
+    /// Get the bounding box of a geometry expression
+    fn get_extent_sql(&self, expression: &str) -> String {
+        format!("ST_Extent({expression})")
+    }
+    /// Get the center of all values of a geometry expression
+    fn get_centroid_sql(&self, expression: &str) -> String {
+        format!("ST_Centroid(ST_Collect({expression}))")
+    }
+    /// Count the values of a geometry expression that are within `meters` of a point
+    fn get_count_within_radius_sql(
+        &self,
+        latitude: &str,
+        longitude: &str,
+        meters: &str,
+        expression: &str,
+    ) -> String {
+        // Go through geography to measure in meters instead of degrees
+        format!(
+            "SUM(CASE WHEN ST_DWithin({expression}::geography, ST_MakePoint({longitude}, {latitude})::geography, {meters}) THEN 1 ELSE 0 END)"
+        )
+    }
+    /// Snap a point expression to a grid of `size` degrees
+    fn get_snap_to_grid_sql(&self, size: &str, expression: &str) -> String {
+        format!("ST_SnapToGrid({expression}, {size})")
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
 pub struct DefaultSyntax {}
 
 impl Syntax for DefaultSyntax {
     fn get_dataset_sql(&self, dataset: &Dataset) -> String {
-        dataset.socrata_id.clone()
+        // Socrata IDs look like u7r4-acwa, which isn't a valid identifier on its own
+        if self.can_represent_identifier(&dataset.socrata_id) {
+            dataset.socrata_id.clone()
+        } else {
+            format!("\"{:}\"", dataset.socrata_id.replace('"', "\"\""))
+        }
     }
 
     fn get_column_sql(&self, column: &Column) -> String {
         column.pg_name.clone()
     }
+
+    fn get_identifier_sql(&self, identifier: &str) -> String {
+        identifier.to_string()
+    }
 }
 
 impl<T: Syntax> Query<T> {
-    fn emit_measure(&self, measure: &Measure, include_alias: bool) -> String {
-        let func = match measure.type_ {
-            MeasureType::Count => "COUNT",
-            MeasureType::Sum => "SUM",
-            MeasureType::Average => "AVG",
-            MeasureType::Min => "MIN",
-            MeasureType::Max => "MAX",
-        };
+    /// Names of the result columns, in the order they're selected. Everything that we
+    /// alias goes through the Syntax, so that we get valid and unique names.
+    pub fn output_aliases(&self) -> Vec<String> {
+        let mut aliases: Vec<String> = self.columns.iter().map(|c| c.pg_name.clone()).collect();
+
+        let names = self
+            .dimensions
+            .iter()
+            .map(|d| {
+                d.alias.clone().unwrap_or_else(|| {
+                    Self::dimension_alias(d).unwrap_or_else(|| d.column.pg_name.clone())
+                })
+            })
+            .chain(self.measures.iter().map(Self::measure_alias))
+            .chain(self.window_measures.iter().map(Self::window_measure_alias))
+            .collect::<Vec<String>>();
+
+        for name in names {
+            let alias = self.syntax.get_alias(&name, &aliases);
+            aliases.push(alias);
+        }
+
+        aliases
+    }
+
+    /// Columns of the result of this query, e.g. to select from it in a CTE
+    pub fn output_columns(&self) -> Vec<Column> {
+        let dimension_columns = self.dimensions.iter().map(|d| Column {
+            human_name: d.column.human_name.clone(),
+            pg_name: String::new(),
+            data_type: d.data_type(),
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        });
+
+        let measure_columns = self.measures.iter().map(|m| Column {
+            human_name: String::new(),
+            pg_name: String::new(),
+            data_type: m.data_type(),
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        });
+
+        let window_columns = self.window_measures.iter().map(|_| Column {
+            human_name: String::new(),
+            pg_name: String::new(),
+            data_type: DataType::Number,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        });
+
+        let aliased_columns = dimension_columns
+            .chain(measure_columns)
+            .chain(window_columns)
+            .zip(self.output_aliases().into_iter().skip(self.columns.len()))
+            .map(|(column, alias)| Column {
+                // Measures don't have a better human-readable name
+                human_name: if column.human_name.is_empty() {
+                    alias.clone()
+                } else {
+                    column.human_name
+                },
+                pg_name: alias,
+                ..column
+            });
+
+        self.columns
+            .iter()
+            .cloned()
+            .chain(aliased_columns)
+            .collect()
+    }
 
+    pub(crate) fn emit_measure(&self, measure: &Measure) -> String {
         if measure.type_ == MeasureType::Count {
             return "COUNT(*)".to_string();
         }
 
         let column_sql = self.syntax.get_column_sql(measure.column.as_ref().unwrap());
-        // TODO: use the same self.syntax route for unquoted columns
-        let column_unquoted = &measure.column.as_ref().unwrap().pg_name;
+        self.syntax.get_aggregate_sql(&measure.type_, &column_sql)
+    }
+
+    pub(crate) fn measure_alias(measure: &Measure) -> String {
+        let prefix = match &measure.type_ {
+            MeasureType::Count => return "count".to_string(),
+            MeasureType::CountDistinct => "count_distinct".to_string(),
+            MeasureType::CountNonNull => "count".to_string(),
+            MeasureType::Sum => "sum".to_string(),
+            MeasureType::Average => "avg".to_string(),
+            MeasureType::Min => "min".to_string(),
+            MeasureType::Max => "max".to_string(),
+            MeasureType::Median => "median".to_string(),
+            MeasureType::Percentile(p) => format!("p{p}"),
+            MeasureType::StddevSamp => "stddev".to_string(),
+            MeasureType::VarSamp => "variance".to_string(),
+            MeasureType::Extent => "extent".to_string(),
+            MeasureType::Centroid => "centroid".to_string(),
+            MeasureType::CountWithinRadius { meters, .. } => format!("count_within_{meters}m"),
+        };
+
+        // This is only the base name, see output_aliases() for the actual alias
+        format!("{prefix}_{:}", measure.column.as_ref().unwrap().pg_name)
+    }
 
-        let mut result = format!("{func}({column_sql})");
+    pub(crate) fn emit_dimension(&self, dimension: &Dimension) -> String {
+        let column_sql = self.syntax.get_column_sql(&dimension.column);
 
-        if include_alias {
-            result.push_str(format!(" AS {0}_{1}", func.to_lowercase(), column_unquoted).as_str());
+        match &dimension.transform {
+            None => column_sql,
+            Some(DimensionTransform::DateTrunc(unit)) => {
+                self.syntax.get_date_trunc_sql(unit, &column_sql)
+            }
+            Some(DimensionTransform::Extract(part)) => {
+                self.syntax.get_extract_sql(part, &column_sql)
+            }
+            Some(DimensionTransform::Bin(binning)) => self.syntax.get_bin_sql(binning, &column_sql),
+            Some(DimensionTransform::SnapToGrid { size }) => {
+                self.syntax.get_snap_to_grid_sql(size, &column_sql)
+            }
         }
+    }
 
-        result
+    pub(crate) fn dimension_alias(dimension: &Dimension) -> Option<String> {
+        let column_unquoted = &dimension.column.pg_name;
+
+        match &dimension.transform {
+            None => None,
+            Some(DimensionTransform::DateTrunc(unit)) => Some(format!(
+                "{:}_{column_unquoted}",
+                format!("{unit:?}").to_lowercase()
+            )),
+            Some(DimensionTransform::Extract(part)) => Some(format!(
+                "{:}_of_{column_unquoted}",
+                match part {
+                    DatePart::Year => "year",
+                    DatePart::Month => "month",
+                    DatePart::DayOfWeek => "dow",
+                    DatePart::Hour => "hour",
+                }
+            )),
+            Some(DimensionTransform::Bin(binning)) => Some(format!(
+                "{:}_{column_unquoted}",
+                match binning {
+                    Binning::Fixed { .. } => "bucket",
+                    Binning::Range { .. } => "width_bucket",
+                    Binning::Log => "magnitude",
+                }
+            )),
+            Some(DimensionTransform::SnapToGrid { .. }) => Some(format!("grid_{column_unquoted}")),
+        }
+    }
+
+    fn emit_window(&self, window: &Window) -> String {
+        let mut parts: Vec<String> = Vec::new();
+
+        if !window.partition_by.is_empty() {
+            parts.push(format!(
+                "PARTITION BY {:}",
+                window
+                    .partition_by
+                    .iter()
+                    .map(|d| self.emit_dimension(d))
+                    .join(", ")
+            ));
+        }
+
+        if !window.order_by.is_empty() {
+            parts.push(format!(
+                "ORDER BY {:}",
+                window
+                    .order_by
+                    .iter()
+                    .map(|o| self.emit_order_by(o))
+                    .join(", ")
+            ));
+        }
+
+        if let Some(frame) = &window.frame {
+            let emit_bound = |bound: &WindowFrameBound| match bound {
+                WindowFrameBound::UnboundedPreceding => "UNBOUNDED PRECEDING".to_string(),
+                WindowFrameBound::Preceding(n) => format!("{n} PRECEDING"),
+                WindowFrameBound::CurrentRow => "CURRENT ROW".to_string(),
+                WindowFrameBound::Following(n) => format!("{n} FOLLOWING"),
+                WindowFrameBound::UnboundedFollowing => "UNBOUNDED FOLLOWING".to_string(),
+            };
+
+            parts.push(format!(
+                "{:} BETWEEN {:} AND {:}",
+                match frame.units {
+                    WindowFrameUnits::Rows => "ROWS",
+                    WindowFrameUnits::Range => "RANGE",
+                },
+                emit_bound(&frame.start),
+                emit_bound(&frame.end)
+            ));
+        }
+
+        format!("OVER ({:})", parts.join(" "))
+    }
+
+    fn emit_window_measure(&self, window_measure: &WindowMeasure) -> String {
+        let over = self.emit_window(&window_measure.window);
+
+        match &window_measure.function {
+            WindowFunction::Rank => format!("RANK() {over}"),
+            WindowFunction::DenseRank => format!("DENSE_RANK() {over}"),
+            WindowFunction::RowNumber => format!("ROW_NUMBER() {over}"),
+            WindowFunction::Sum(m) => format!("SUM({:}) {over}", self.emit_measure(m)),
+            WindowFunction::ShareOfTotal(m) => {
+                let measure_sql = self.emit_measure(m);
+                format!("{measure_sql} / SUM({measure_sql}) {over}")
+            }
+        }
+    }
+
+    pub(crate) fn window_measure_alias(window_measure: &WindowMeasure) -> String {
+        match &window_measure.function {
+            WindowFunction::Rank => "rank".to_string(),
+            WindowFunction::DenseRank => "dense_rank".to_string(),
+            WindowFunction::RowNumber => "row_number".to_string(),
+            WindowFunction::Sum(m) => format!("running_{:}", Self::measure_alias(m)),
+            WindowFunction::ShareOfTotal(m) => format!("share_of_{:}", Self::measure_alias(m)),
+        }
+    }
+
+    fn emit_comparison_operator(operator: &ComparisonOperator) -> &'static str {
+        match operator {
+            ComparisonOperator::Eq => "=",
+            ComparisonOperator::NotEq => "<>",
+            ComparisonOperator::Lt => "<",
+            ComparisonOperator::LtEq => "<=",
+            ComparisonOperator::Gt => ">",
+            ComparisonOperator::GtEq => ">=",
+        }
+    }
+
+    pub(crate) fn emit_filter(&self, filter: &Filter) -> String {
+        match filter {
+            Filter::Comparison {
+                column,
+                operator,
+                value,
+            } => format!(
+                "{:} {:} {:}",
+                self.syntax.get_column_sql(column),
+                Self::emit_comparison_operator(operator),
+                self.syntax.get_literal_sql(value)
+            ),
+            Filter::In { column, values } => format!(
+                "{:} IN ({:})",
+                self.syntax.get_column_sql(column),
+                values
+                    .iter()
+                    .map(|v| self.syntax.get_literal_sql(v))
+                    .join(", ")
+            ),
+            Filter::Between { column, low, high } => format!(
+                "{:} BETWEEN {:} AND {:}",
+                self.syntax.get_column_sql(column),
+                self.syntax.get_literal_sql(low),
+                self.syntax.get_literal_sql(high)
+            ),
+            Filter::IsNull { column, negated } => format!(
+                "{:} IS {:}NULL",
+                self.syntax.get_column_sql(column),
+                if *negated { "NOT " } else { "" }
+            ),
+            Filter::Like { column, pattern } => format!(
+                "{:} LIKE {:}",
+                self.syntax.get_column_sql(column),
+                self.syntax.get_literal_sql(&Literal::Text(pattern.clone()))
+            ),
+            Filter::And(filters) => self.emit_filter_list(filters, " AND "),
+            Filter::Or(filters) => self.emit_filter_list(filters, " OR "),
+        }
     }
 
-    fn emit_dimension(&self, dimension: &Dimension) -> String {
-        self.syntax.get_column_sql(&dimension.column)
+    fn emit_filter_operand(&self, filter: &Filter) -> String {
+        // Always parenthesize nested AND/OR to not depend on operator precedence
+        match filter {
+            Filter::And(_) | Filter::Or(_) => format!("({:})", self.emit_filter(filter)),
+            _ => self.emit_filter(filter),
+        }
+    }
+
+    fn emit_filter_list(&self, filters: &[Filter], separator: &str) -> String {
+        filters
+            .iter()
+            .map(|f| self.emit_filter_operand(f))
+            .join(separator)
+    }
+
+    pub(crate) fn emit_having(&self, condition: &HavingCondition) -> String {
+        match condition {
+            HavingCondition::Comparison {
+                measure,
+                operator,
+                value,
+            } => format!(
+                "{:} {:} {:}",
+                self.emit_measure(measure),
+                Self::emit_comparison_operator(operator),
+                self.syntax.get_literal_sql(value)
+            ),
+            HavingCondition::Between { measure, low, high } => format!(
+                "{:} BETWEEN {:} AND {:}",
+                self.emit_measure(measure),
+                self.syntax.get_literal_sql(low),
+                self.syntax.get_literal_sql(high)
+            ),
+        }
     }
 
-    fn emit_order_by(&self, order_by: &OrderBy) -> String {
+    pub(crate) fn emit_order_by(&self, order_by: &OrderBy) -> String {
         let mut result = String::new();
 
         result.push_str(
             match &order_by.item {
-                OrderByItem::Measure(m) => self.emit_measure(m, false),
+                OrderByItem::Measure(m) => self.emit_measure(m),
                 OrderByItem::Dimension(d) => self.emit_dimension(d),
             }
             .as_str(),
@@ -124,101 +875,631 @@ impl<T: Syntax> Query<T> {
         result
     }
 
-    pub fn to_sql(&self) -> String {
-        let mut result = String::new();
-
-        result.push_str("SELECT\n  ");
+    /// Render each item of the SELECT list, aliased where the alias differs
+    /// from the name the database would give it anyway
+    pub(crate) fn emit_select_items(&self) -> Vec<String> {
+        // Expressions and the names the database gives them without an alias
+        let items = self
+            .columns
+            .iter()
+            .map(|c| (self.syntax.get_column_sql(c), Some(c.pg_name.clone())))
+            .chain(self.dimensions.iter().map(|d| {
+                (
+                    self.emit_dimension(d),
+                    d.transform.is_none().then(|| d.column.pg_name.clone()),
+                )
+            }))
+            // Even COUNT(*) gets an alias: only Postgres calls it "count" (DuckDB says
+            // "count_star()"), and CTEs and parsers refer to it by name
+            .chain(self.measures.iter().map(|m| (self.emit_measure(m), None)))
+            .chain(
+                self.window_measures
+                    .iter()
+                    .map(|w| (self.emit_window_measure(w), None)),
+            );
 
-        result.push_str(
-            self.dimensions
-                .iter()
-                .map(|d| self.emit_dimension(d))
-                .chain(self.measures.iter().map(|m| self.emit_measure(m, true)))
-                .join(",\n  ")
-                .as_str(),
-        );
+        items
+            .zip(self.output_aliases())
+            .map(|((sql, name), alias)| {
+                if name.as_ref() == Some(&alias) {
+                    sql
+                } else {
+                    format!("{sql} AS {:}", self.syntax.get_identifier_sql(&alias))
+                }
+            })
+            .collect()
+    }
 
-        result.push_str("\nFROM ");
-        result.push_str(self.syntax.get_dataset_sql(&self.dataset).as_str());
+    /// Comments that go next to the SELECT items: the human name of the column they're on
+    fn select_item_comments(&self) -> Vec<Option<String>> {
+        let measure_comment = |m: &Measure| m.column.as_ref().map(|c| c.human_name.clone());
 
-        if !self.dimensions.is_empty() {
-            result.push_str("\nGROUP BY\n  ");
-            result.push_str(
+        self.columns
+            .iter()
+            .map(|c| Some(c.human_name.clone()))
+            .chain(
                 self.dimensions
                     .iter()
-                    .map(|d| self.emit_dimension(d))
-                    .join(",\n  ")
-                    .as_str(),
+                    .map(|d| Some(d.column.human_name.clone())),
             )
+            .chain(self.measures.iter().map(measure_comment))
+            .chain(self.window_measures.iter().map(|w| match &w.function {
+                WindowFunction::Sum(m) | WindowFunction::ShareOfTotal(m) => measure_comment(m),
+                _ => None,
+            }))
+            .collect()
+    }
+
+    pub fn to_sql(&self) -> String {
+        self.to_sql_with(&FormatOptions::default())
+    }
+
+    /// Emit the query with a given layout
+    pub fn to_sql_with(&self, options: &FormatOptions) -> String {
+        set_keyword_case(&self.format_sql(options), options.keyword_case)
+    }
+
+    fn format_sql(&self, options: &FormatOptions) -> String {
+        let newline = if options.single_line { " " } else { "\n" };
+        let mut clauses: Vec<String> = Vec::new();
+
+        if !self.ctes.is_empty() {
+            clauses.push(format!(
+                "WITH {:}",
+                self.ctes
+                    .iter()
+                    .map(|c| {
+                        let name = self.syntax.get_identifier_sql(&c.name);
+                        let query = c.query.format_sql(options);
+                        if options.single_line {
+                            format!("{name} AS ({query})")
+                        } else {
+                            format!(
+                                "{name} AS (\n{:}\n)",
+                                query
+                                    .lines()
+                                    .map(|l| format!("{:}{l}", options.indent()))
+                                    .join("\n")
+                            )
+                        }
+                    })
+                    .join(format!(",{newline}").as_str())
+            ));
         }
 
-        if !self.order_by.is_empty() {
-            result.push_str("\nORDER BY\n  ");
-            result.push_str(
-                self.order_by
+        let select = match (self.limit, self.syntax.get_limit_style()) {
+            (Some(l), LimitStyle::Top) => format!("SELECT TOP {l}"),
+            _ => "SELECT".to_string(),
+        };
+        let items: Vec<(String, Option<String>)> = self
+            .emit_select_items()
+            .into_iter()
+            .zip(self.select_item_comments())
+            .collect();
+        clauses.push(format_clause(
+            &select,
+            &items,
+            ListSeparator::Comma,
+            options,
+        ));
+
+        clauses.push(format!(
+            "FROM {:}",
+            match &self.source {
+                QuerySource::Dataset => self.syntax.get_dataset_sql(&self.dataset),
+                QuerySource::CommonTableExpression(name) => self.syntax.get_identifier_sql(name),
+            }
+        ));
+
+        let without_comments = |items: Vec<String>| -> Vec<(String, Option<String>)> {
+            items.into_iter().map(|i| (i, None)).collect()
+        };
+
+        if let Some(filter) = &self.filter {
+            let conjuncts = match filter {
+                // Put each top-level conjunct on its own line
+                Filter::And(filters) => filters
                     .iter()
-                    .map(|o| self.emit_order_by(o))
-                    .join(",\n  ")
-                    .as_str(),
-            )
+                    .map(|f| self.emit_filter_operand(f))
+                    .collect(),
+                _ => vec![self.emit_filter(filter)],
+            };
+            clauses.push(format_clause(
+                "WHERE",
+                &without_comments(conjuncts),
+                ListSeparator::And,
+                options,
+            ));
+        }
+
+        if !self.dimensions.is_empty() {
+            clauses.push(format_clause(
+                "GROUP BY",
+                &without_comments(
+                    self.dimensions
+                        .iter()
+                        .map(|d| self.emit_dimension(d))
+                        .collect(),
+                ),
+                ListSeparator::Comma,
+                options,
+            ));
+        }
+
+        if !self.having.is_empty() {
+            clauses.push(format_clause(
+                "HAVING",
+                &without_comments(self.having.iter().map(|h| self.emit_having(h)).collect()),
+                ListSeparator::And,
+                options,
+            ));
+        }
+
+        if !self.order_by.is_empty() {
+            clauses.push(format_clause(
+                "ORDER BY",
+                &without_comments(
+                    self.order_by
+                        .iter()
+                        .map(|o| self.emit_order_by(o))
+                        .collect(),
+                ),
+                ListSeparator::Comma,
+                options,
+            ));
         }
 
         if let Some(l) = self.limit {
-            result.push_str("\nLIMIT ");
-            result.push_str(l.to_string().as_str());
+            match self.syntax.get_limit_style() {
+                LimitStyle::Limit => clauses.push(format!("LIMIT {l}")),
+                LimitStyle::Top => {}
+                LimitStyle::FetchFirst => clauses.push(format!("FETCH FIRST {l} ROWS ONLY")),
+            }
         }
 
-        result
+        clauses.join(newline)
     }
-    pub fn new(
-        dataset: Dataset,
-        measures: Vec<Measure>,
-        dimensions: Vec<Dimension>,
-        order_by: Vec<OrderBy>,
-        limit: Option<usize>,
-        syntax: T,
-    ) -> Self {
-        Self {
-            dataset,
-            measures,
-            dimensions,
-            order_by,
-            limit,
+
+    pub fn syntax(&self) -> &T {
+        &self.syntax
+    }
+
+    /// Render the same query in a different dialect
+    #[allow(dead_code)]
+    pub fn with_syntax<U: Syntax + Clone>(self, syntax: U) -> Query<U> {
+        Query {
+            dataset: self.dataset,
+            ctes: self
+                .ctes
+                .into_iter()
+                .map(|c| CommonTableExpression {
+                    name: c.name,
+                    query: c.query.with_syntax(syntax.clone()),
+                })
+                .collect(),
+            source: self.source,
+            columns: self.columns,
+            measures: self.measures,
+            dimensions: self.dimensions,
+            window_measures: self.window_measures,
+            filter: self.filter,
+            having: self.having,
+            order_by: self.order_by,
+            limit: self.limit,
             syntax,
         }
     }
-}
-
-pub fn build_measures(dataset: &Dataset) -> Vec<Measure> {
-    // COUNT(*) always exists
-    let mut measures = vec![Measure {
-        type_: MeasureType::Count,
-        column: None,
-    }];
 
-    for column in &dataset.columns {
-        // Ignore Socrata derived geo columns
-        if column.pg_name.contains(":@computed_region") {
-            continue;
-        };
+    /// Stop grouping on a dimension, together with everything that orders or partitions by it
+    pub fn remove_dimension(&mut self, index: usize) {
+        let removed = self.dimensions.remove(index);
+        let is_removed =
+            |d: &Dimension| d.column == removed.column && d.transform == removed.transform;
 
-        if matches!(
-            column.data_type,
-            DataType::Money
-                | DataType::Number
-                | DataType::Double
-                | DataType::FloatingTimestamp
-                | DataType::CalendarDate
-        ) {
-            measures.push(Measure {
-                type_: MeasureType::Min,
-                column: Some(column.clone()),
-            });
-            measures.push(Measure {
-                type_: MeasureType::Max,
-                column: Some(column.clone()),
-            });
-        };
+        self.order_by.retain(|o| match &o.item {
+            OrderByItem::Dimension(d) => !is_removed(d),
+            OrderByItem::Measure(_) => true,
+        });
+        self.window_measures.retain(|w| {
+            !w.window.partition_by.iter().any(is_removed)
+                && !w.window.order_by.iter().any(|o| match &o.item {
+                    OrderByItem::Dimension(d) => is_removed(d),
+                    OrderByItem::Measure(_) => false,
+                })
+        });
+    }
+
+    /// AND a filter to the query's WHERE clause
+    pub fn add_filter(&mut self, filter: Filter) {
+        self.filter = Some(match self.filter.take() {
+            None => filter,
+            Some(Filter::And(mut filters)) => {
+                filters.push(filter);
+                Filter::And(filters)
+            }
+            Some(existing) => Filter::And(vec![existing, filter]),
+        });
+    }
+
+    fn is_grouped_on(&self, dimension: &Dimension) -> bool {
+        // Aliases don't matter here
+        self.dimensions
+            .iter()
+            .any(|d| d.column == dimension.column && d.transform == dimension.transform)
+    }
+
+    /// Check the query for problems that would make the database reject it
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for cte in &self.ctes {
+            diagnostics.extend(cte.query.validate());
+        }
+
+        if self.columns.is_empty()
+            && self.dimensions.is_empty()
+            && self.measures.is_empty()
+            && self.window_measures.is_empty()
+        {
+            diagnostics.push(Diagnostic::EmptySelect);
+        }
+
+        let output_columns = self.output_columns();
+        diagnostics.extend(
+            output_columns
+                .iter()
+                .map(|c| &c.pg_name)
+                .duplicates()
+                .map(|a| Diagnostic::DuplicateAlias(a.clone())),
+        );
+
+        // Everything that's aggregated, including in HAVING, ORDER BY and window functions
+        let mut all_measures: Vec<&Measure> = self.measures.iter().collect();
+        all_measures.extend(self.having.iter().map(|h| match h {
+            HavingCondition::Comparison { measure, .. }
+            | HavingCondition::Between { measure, .. } => measure,
+        }));
+        let all_order_bys: Vec<&OrderBy> = self
+            .order_by
+            .iter()
+            .chain(
+                self.window_measures
+                    .iter()
+                    .flat_map(|w| w.window.order_by.iter()),
+            )
+            .collect();
+        all_measures.extend(all_order_bys.iter().filter_map(|o| match &o.item {
+            OrderByItem::Measure(m) => Some(m),
+            OrderByItem::Dimension(_) => None,
+        }));
+        all_measures.extend(
+            self.window_measures
+                .iter()
+                .filter_map(|w| match &w.function {
+                    WindowFunction::Sum(m) | WindowFunction::ShareOfTotal(m) => Some(m),
+                    _ => None,
+                }),
+        );
+
+        all_measures = all_measures.into_iter().fold(Vec::new(), |mut unique, m| {
+            if !unique.contains(&m) {
+                unique.push(m);
+            }
+            unique
+        });
+
+        let is_aggregation = !self.dimensions.is_empty() || !all_measures.is_empty();
+
+        if is_aggregation {
+            diagnostics.extend(
+                self.columns
+                    .iter()
+                    .map(|c| Diagnostic::UngroupedColumn(c.clone())),
+            );
+
+            diagnostics.extend(
+                self.window_measures
+                    .iter()
+                    .flat_map(|w| {
+                        w.window
+                            .partition_by
+                            .iter()
+                            .chain(w.window.order_by.iter().filter_map(|o| match &o.item {
+                                OrderByItem::Dimension(d) => Some(d),
+                                OrderByItem::Measure(_) => None,
+                            }))
+                    })
+                    .filter(|d| !self.is_grouped_on(d))
+                    .fold(Vec::new(), |mut unique, d| {
+                        if !unique.contains(&d) {
+                            unique.push(d);
+                        }
+                        unique
+                    })
+                    .into_iter()
+                    .map(|d| Diagnostic::UngroupedDimension(d.clone())),
+            );
+        }
+
+        diagnostics.extend(
+            self.order_by
+                .iter()
+                .filter(|o| match &o.item {
+                    OrderByItem::Measure(_) => !is_aggregation,
+                    OrderByItem::Dimension(d) if is_aggregation => !self.is_grouped_on(d),
+                    // Without aggregation, we can only order by the columns we're passing through
+                    OrderByItem::Dimension(d) => {
+                        d.transform.is_some() || !self.columns.contains(&d.column)
+                    }
+                })
+                .map(|o| Diagnostic::OrderByNotSelectable(o.clone())),
+        );
+
+        diagnostics.extend(
+            all_order_bys
+                .iter()
+                .filter(|o| !is_orderable(&o.item))
+                .map(|o| Diagnostic::UnorderableOrderBy((*o).clone())),
+        );
+
+        diagnostics.extend(
+            all_measures
+                .iter()
+                .filter(|m| !is_measure_compatible(m))
+                .map(|m| Diagnostic::IncompatibleMeasure((*m).clone())),
+        );
+
+        diagnostics.extend(
+            self.dimensions
+                .iter()
+                .filter(|d| !is_dimension_compatible(d))
+                .map(|d| Diagnostic::IncompatibleDimension(d.clone())),
+        );
+
+        diagnostics.extend(
+            all_measures
+                .iter()
+                .filter(|m| !self.syntax.supports_measure_type(&m.type_))
+                .map(|m| Diagnostic::UnsupportedMeasure((*m).clone())),
+        );
+
+        diagnostics.extend(
+            self.dimensions
+                .iter()
+                .filter(|d| {
+                    d.transform
+                        .as_ref()
+                        .is_some_and(|t| !self.syntax.supports_dimension_transform(t))
+                })
+                .map(|d| Diagnostic::UnsupportedDimension(d.clone())),
+        );
+
+        if !self.syntax.supports_window_functions() {
+            diagnostics.extend(
+                self.window_measures
+                    .iter()
+                    .map(|w| Diagnostic::UnsupportedWindowMeasure(w.clone())),
+            );
+        }
+
+        if !self.syntax.supports_common_table_expressions() {
+            diagnostics.extend(
+                self.ctes
+                    .iter()
+                    .map(|c| Diagnostic::UnsupportedCommonTableExpression(c.name.clone())),
+            );
+        }
+
+        // Names of all columns that we reference and output, as well as CTEs
+        let mut identifiers: Vec<&String> = self.columns.iter().map(|c| &c.pg_name).collect();
+        identifiers.extend(self.dimensions.iter().map(|d| &d.column.pg_name));
+        identifiers.extend(
+            all_measures
+                .iter()
+                .filter_map(|m| m.column.as_ref().map(|c| &c.pg_name)),
+        );
+        if let Some(filter) = &self.filter {
+            identifiers.extend(filter_columns(filter).into_iter().map(|c| &c.pg_name));
+        }
+        identifiers.extend(output_columns.iter().map(|c| &c.pg_name));
+        identifiers.extend(self.ctes.iter().map(|c| &c.name));
+
+        diagnostics.extend(
+            identifiers
+                .into_iter()
+                .unique()
+                .filter(|i| !self.syntax.can_represent_identifier(i))
+                .map(|i| Diagnostic::UnrepresentableIdentifier(i.clone())),
+        );
+
+        // Only worth parsing if we didn't find anything wrong with the AST itself
+        #[cfg(feature = "sql-check")]
+        if diagnostics.is_empty() {
+            if let Some(dialect) = self.syntax.get_sql_dialect() {
+                if let Err(error) = check_sql(&self.to_sql(), &dialect) {
+                    diagnostics.push(Diagnostic::UnparseableSql(error.to_string()));
+                }
+            }
+        }
+
+        diagnostics
+    }
+    pub fn new(
+        dataset: Dataset,
+        measures: Vec<Measure>,
+        dimensions: Vec<Dimension>,
+        order_by: Vec<OrderBy>,
+        limit: Option<usize>,
+        syntax: T,
+    ) -> Self {
+        Self {
+            dataset,
+            ctes: vec![],
+            source: QuerySource::Dataset,
+            columns: vec![],
+            measures,
+            dimensions,
+            window_measures: vec![],
+            filter: None,
+            having: vec![],
+            order_by,
+            limit,
+            syntax,
+        }
+    }
+}
+
+// Socrata derived geo columns, e.g. :@computed_region_7ccj_gre3 for the neighbourhood a
+// point is in. They're IDs of regions that we can't look up.
+pub(crate) fn is_computed_region(column: &Column) -> bool {
+    column.pg_name.contains(":@computed_region")
+}
+
+pub(crate) fn is_numeric(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Money | DataType::Number | DataType::Double
+    )
+}
+
+pub(crate) fn is_temporal(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::CalendarDate | DataType::FloatingTimestamp
+    )
+}
+
+// Legacy Location columns aren't included: they bundle the point with an address, so the
+// geometry functions can't take them directly
+pub(crate) fn is_geospatial(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Point
+            | DataType::Multipoint
+            | DataType::Line
+            | DataType::Multiline
+            | DataType::Multipolygon
+    )
+}
+
+// Single points, which can be snapped to a grid or be some distance away from something
+fn is_point(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Point)
+}
+
+/// Whether the value can go into an ORDER BY. Geometries don't have a meaningful order.
+pub(crate) fn is_orderable(item: &OrderByItem) -> bool {
+    !is_geospatial(&match item {
+        OrderByItem::Measure(m) => m.data_type(),
+        OrderByItem::Dimension(d) => d.data_type(),
+    })
+}
+
+fn is_measure_compatible(measure: &Measure) -> bool {
+    match (&measure.type_, &measure.column) {
+        (MeasureType::Count, _) => true,
+        (_, None) => false,
+        (MeasureType::CountDistinct | MeasureType::CountNonNull, Some(_)) => true,
+        // Anything that can be ordered
+        (MeasureType::Min | MeasureType::Max, Some(c)) => {
+            is_numeric(&c.data_type)
+                || is_temporal(&c.data_type)
+                || matches!(c.data_type, DataType::Text | DataType::Url)
+        }
+        (MeasureType::Percentile(p), Some(c)) => *p <= 100 && is_numeric(&c.data_type),
+        (
+            MeasureType::Sum
+            | MeasureType::Average
+            | MeasureType::Median
+            | MeasureType::StddevSamp
+            | MeasureType::VarSamp,
+            Some(c),
+        ) => is_numeric(&c.data_type),
+        (MeasureType::Extent | MeasureType::Centroid, Some(c)) => is_geospatial(&c.data_type),
+        (MeasureType::CountWithinRadius { .. }, Some(c)) => is_point(&c.data_type),
+    }
+}
+
+fn is_dimension_compatible(dimension: &Dimension) -> bool {
+    match dimension.transform {
+        None => true,
+        Some(DimensionTransform::DateTrunc(_)) | Some(DimensionTransform::Extract(_)) => {
+            is_temporal(&dimension.column.data_type)
+        }
+        Some(DimensionTransform::Bin(_)) => is_numeric(&dimension.column.data_type),
+        Some(DimensionTransform::SnapToGrid { .. }) => is_point(&dimension.column.data_type),
+    }
+}
+
+pub(crate) fn filter_columns(filter: &Filter) -> Vec<&Column> {
+    match filter {
+        Filter::Comparison { column, .. }
+        | Filter::In { column, .. }
+        | Filter::Between { column, .. }
+        | Filter::IsNull { column, .. }
+        | Filter::Like { column, .. } => vec![column],
+        Filter::And(filters) | Filter::Or(filters) => {
+            filters.iter().flat_map(filter_columns).collect()
+        }
+    }
+}
+
+// Radius of the area that CountWithinRadius counts rows in
+const NEARBY_METERS: &str = "1000";
+// Sizes (in degrees) of the grids to snap points to: roughly 1 km and 10 km
+const GRID_SIZES: [&str; 2] = ["0.01", "0.1"];
+
+/// Middle of the area that the dataset covers, as (latitude, longitude), if it has numeric
+/// latitude and longitude columns with statistics (Socrata datasets with a point often do)
+pub fn dataset_center(dataset: &Dataset) -> Option<(f64, f64)> {
+    let midpoint = |names: &[&str], limit: f64| {
+        dataset
+            .columns
+            .iter()
+            .filter(|c| is_numeric(&c.data_type))
+            .filter(|c| names.contains(&c.human_name.to_lowercase().as_str()))
+            .find_map(number_range)
+            .map(|(low, high)| (low + high) / 2.0)
+            .filter(|m| m.abs() <= limit)
+    };
+
+    Some((
+        midpoint(&["latitude", "lat"], 90.0)?,
+        midpoint(&["longitude", "long", "lon", "lng"], 180.0)?,
+    ))
+}
+
+pub fn build_measures(dataset: &Dataset) -> Vec<Measure> {
+    // COUNT(*) always exists
+    let mut measures = vec![Measure {
+        type_: MeasureType::Count,
+        column: None,
+    }];
+    let center = dataset_center(dataset);
+
+    for column in &dataset.columns {
+        // Ignore Socrata derived geo columns and columns without any values
+        if is_computed_region(column) || column.stats.is_all_null() {
+            continue;
+        };
+
+        if matches!(
+            column.data_type,
+            DataType::Money
+                | DataType::Number
+                | DataType::Double
+                | DataType::FloatingTimestamp
+                | DataType::CalendarDate
+        ) {
+            measures.push(Measure {
+                type_: MeasureType::Min,
+                column: Some(column.clone()),
+            });
+            measures.push(Measure {
+                type_: MeasureType::Max,
+                column: Some(column.clone()),
+            });
+        };
 
         if matches!(
             column.data_type,
@@ -232,107 +1513,866 @@ pub fn build_measures(dataset: &Dataset) -> Vec<Measure> {
                 type_: MeasureType::Average,
                 column: Some(column.clone()),
             });
+            measures.push(Measure {
+                type_: MeasureType::Median,
+                column: Some(column.clone()),
+            });
+            measures.push(Measure {
+                type_: MeasureType::Percentile(90),
+                column: Some(column.clone()),
+            });
+            measures.push(Measure {
+                type_: MeasureType::StddevSamp,
+                column: Some(column.clone()),
+            });
+            measures.push(Measure {
+                type_: MeasureType::VarSamp,
+                column: Some(column.clone()),
+            });
+        };
+
+        if matches!(
+            column.data_type,
+            DataType::Text | DataType::Url | DataType::Checkbox
+        ) {
+            measures.push(Measure {
+                type_: MeasureType::CountDistinct,
+                column: Some(column.clone()),
+            });
+            measures.push(Measure {
+                type_: MeasureType::CountNonNull,
+                column: Some(column.clone()),
+            });
         };
+
+        if is_geospatial(&column.data_type) {
+            measures.push(Measure {
+                type_: MeasureType::Extent,
+                column: Some(column.clone()),
+            });
+            measures.push(Measure {
+                type_: MeasureType::Centroid,
+                column: Some(column.clone()),
+            });
+        }
+
+        // We need somewhere to measure the distance from
+        if let Some((latitude, longitude)) = center.filter(|_| is_point(&column.data_type)) {
+            measures.push(Measure {
+                type_: MeasureType::CountWithinRadius {
+                    latitude: format_number(latitude),
+                    longitude: format_number(longitude),
+                    meters: NEARBY_METERS.to_string(),
+                },
+                column: Some(column.clone()),
+            });
+        }
     }
 
     measures
 }
 
-pub fn build_dimensions(dataset: &Dataset) -> Vec<Dimension> {
-    // possible dimensions:
-    //  - all text cols
-    //  - all dates (though we can't even extract a date part)
+/// Parse a statistic of a numeric column into a number we can put into a query
+fn parse_number_stat(value: &Option<String>) -> Option<f64> {
+    value
+        .as_ref()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|v| v.is_finite())
+}
 
-    dataset
-        .columns
-        .iter()
-        .filter_map(|c| match c.data_type {
-            DataType::Text | DataType::Checkbox | DataType::Url => {
-                Some(Dimension { column: c.clone() })
-            }
-            _ => None,
-        })
-        .collect()
+// Avoid -0
+fn format_number(number: f64) -> String {
+    (number + 0.0).to_string()
 }
 
-pub fn random_query<T: Syntax>(
-    dataset: &Dataset,
-    measures: &Vec<Measure>,
-    dimensions: &Vec<Dimension>,
-    no_dimensions: Range<usize>,
-    no_measures: Range<usize>,
-    no_order_bys: Range<usize>,
-    syntax: T,
-) -> Query<T> {
-    let mut rng = rand::thread_rng();
+/// Round a value between `low` and `high` to a step of a hundredth to a thousandth of that
+/// range, e.g. to 0.001 for a latitude between 53.39 and 53.71 or to 100 for a cost below 50000
+fn format_in_range(value: f64, low: f64, high: f64) -> String {
+    let exponent = (high - low).log10().floor() as i32 - 2;
+    let step = 10f64.powi(exponent);
+    let rounded = match (value / step).round() * step {
+        // Don't round past the ends of the range
+        r if r > high => (value / step).floor() * step,
+        r if r < low => (value / step).ceil() * step,
+        r => r,
+    };
+    format!("{:.*}", (-exponent).max(0) as usize, rounded + 0.0)
+}
 
-    let no_dimensions = rng.gen_range(no_dimensions);
-    let no_measures = rng.gen_range(no_measures);
-    let no_order_bys = rng.gen_range(no_order_bys).min(no_dimensions + no_measures);
+/// Two random values between `low` and `high`, in order
+fn random_range<R: Rng>(rng: &mut R, low: f64, high: f64) -> (String, String) {
+    let mut points = [rng.gen_range(low..=high), rng.gen_range(low..=high)];
+    points.sort_by(f64::total_cmp);
+    let [first, second] = points.map(|p| format_in_range(p, low, high));
+    (first, second)
+}
 
-    let chosen_measures: Vec<Measure> = measures
-        .choose_multiple(&mut rng, no_measures)
-        .cloned()
-        .collect();
-    let chosen_dimensions: Vec<Dimension> = dimensions
-        .choose_multiple(&mut rng, no_dimensions)
-        .cloned()
-        .collect();
+/// Smallest and largest values of a numeric column, if the statistics have them
+fn number_range(column: &Column) -> Option<(f64, f64)> {
+    let low = parse_number_stat(&column.stats.smallest)?;
+    let high = parse_number_stat(&column.stats.largest)?;
+    (low < high).then_some((low, high))
+}
 
-    let mut all_order_bys: Vec<OrderBy> = chosen_measures
-        .iter()
-        .map(|m| OrderBy {
-            item: OrderByItem::Measure(m.clone()),
-            direction: match rng.gen_range(0..2) {
-                0 => OrderByDirection::Asc,
-                _ => OrderByDirection::Desc,
-            },
-        })
-        .collect();
+/// Years of the smallest and largest values of a date column, if the statistics have them
+fn year_range(column: &Column) -> Option<(i32, i32)> {
+    // e.g. 2020-01-31T00:00:00.000
+    let year = |v: &Option<String>| v.as_ref()?.get(0..4)?.parse::<i32>().ok();
+    let low = year(&column.stats.smallest)?;
+    let high = year(&column.stats.largest)?;
+    (low <= high).then_some((low, high))
+}
 
-    all_order_bys.extend(chosen_dimensions.iter().map(|d| OrderBy {
-        item: OrderByItem::Dimension(d.clone()),
-        direction: match rng.gen_range(0..2) {
-            0 => OrderByDirection::Asc,
-            _ => OrderByDirection::Desc,
-        },
-    }));
+pub fn build_binnings(column: &Column) -> Vec<Binning> {
+    // Real statistics beat guessing from the format
+    if let Some((low, high)) = number_range(column) {
+        return vec![
+            Binning::Range {
+                low: format_number(low.floor()),
+                high: format_number(high.ceil()),
+                count: 10,
+            },
+            Binning::Log,
+        ];
+    }
 
-    let chosen_order_bys = all_order_bys
-        .choose_multiple(&mut rng, no_order_bys)
-        .cloned()
-        .collect();
+    // Use the column's display format as a hint to how its values are distributed
+    match column.format.precision_style.as_deref() {
+        // Percentages are mostly between 0 and 100
+        Some("percentage") => vec![
+            Binning::Fixed {
+                width: "10".to_string(),
+            },
+            Binning::Range {
+                low: "0".to_string(),
+                high: "100".to_string(),
+                count: 10,
+            },
+        ],
+        _ => {
+            let width = match (&column.data_type, column.format.precision_style.as_deref()) {
+                (DataType::Money, _) | (_, Some("currency")) | (_, Some("financial")) => "100",
+                _ => "10",
+            };
 
-    Query::new(
-        dataset.clone(),
-        chosen_measures,
-        chosen_dimensions,
-        chosen_order_bys,
-        Some(100),
-        syntax,
-    )
+            vec![
+                Binning::Fixed {
+                    width: width.to_string(),
+                },
+                Binning::Log,
+            ]
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::query_gen::query::{
-        build_dimensions, build_measures, random_query, DefaultSyntax, Dimension, Measure,
-        MeasureType, OrderBy, OrderByDirection, OrderByItem, Query,
-    };
-    use crate::query_gen::socrata::{parse_dataset, Column, DataType, Dataset, RawDatasetResource};
-    use std::fs;
-    use std::path::PathBuf;
+// Columns with at most this many distinct values make good dimensions
+pub const LOW_CARDINALITY: u64 = 20;
 
-    #[test]
-    fn test_build_measures() {
-        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        d.push("resources/test/socrata_resource.json");
-        let resource_json = fs::read_to_string(d).unwrap();
-        let resource: RawDatasetResource = serde_json::from_str(&resource_json).unwrap();
-        let dataset = parse_dataset("data.edmonton.ca", &resource);
+pub fn build_dimensions(dataset: &Dataset) -> Vec<Dimension> {
+    // possible dimensions:
+    //  - all text cols
+    //  - all dates, truncated to some unit or with a part extracted
+    //  - all numbers, binned into buckets
+    //  - all points, snapped to a grid
+
+    let mut dimensions = Vec::new();
+
+    for column in &dataset.columns {
+        if column.stats.is_all_null() {
+            continue;
+        }
+
+        match column.data_type {
+            DataType::Text | DataType::Checkbox | DataType::Url => dimensions.push(Dimension {
+                column: column.clone(),
+                transform: None,
+                alias: None,
+            }),
+            DataType::CalendarDate | DataType::FloatingTimestamp => {
+                let mut transforms = vec![
+                    DimensionTransform::DateTrunc(DateTruncUnit::Day),
+                    DimensionTransform::DateTrunc(DateTruncUnit::Week),
+                    DimensionTransform::DateTrunc(DateTruncUnit::Month),
+                    DimensionTransform::DateTrunc(DateTruncUnit::Quarter),
+                    DimensionTransform::DateTrunc(DateTruncUnit::Year),
+                    DimensionTransform::Extract(DatePart::Year),
+                    DimensionTransform::Extract(DatePart::Month),
+                    DimensionTransform::Extract(DatePart::DayOfWeek),
+                ];
+                // Dates don't have a time component
+                if column.data_type == DataType::FloatingTimestamp {
+                    transforms.push(DimensionTransform::Extract(DatePart::Hour));
+                }
+
+                dimensions.extend(transforms.into_iter().map(|t| Dimension {
+                    column: column.clone(),
+                    transform: Some(t),
+                    alias: None,
+                }));
+            }
+            DataType::Money | DataType::Number | DataType::Double => {
+                // Ignore Socrata derived geo columns
+                if is_computed_region(column) {
+                    continue;
+                };
+
+                // Numbers with only a few values (e.g. a number of floors) are categories
+                if column
+                    .stats
+                    .cardinality
+                    .is_some_and(|c| c <= LOW_CARDINALITY)
+                {
+                    dimensions.push(Dimension {
+                        column: column.clone(),
+                        transform: None,
+                        alias: None,
+                    });
+                }
+
+                dimensions.extend(build_binnings(column).into_iter().map(|b| Dimension {
+                    column: column.clone(),
+                    transform: Some(DimensionTransform::Bin(b)),
+                    alias: None,
+                }));
+            }
+            DataType::Point => {
+                dimensions.extend(GRID_SIZES.into_iter().map(|size| Dimension {
+                    column: column.clone(),
+                    transform: Some(DimensionTransform::SnapToGrid {
+                        size: size.to_string(),
+                    }),
+                    alias: None,
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    dimensions
+}
+
+pub fn build_window_measures(measures: &[Measure], dimensions: &[Dimension]) -> Vec<WindowMeasure> {
+    // Window functions run over the result of the aggregation, so they're only
+    // interesting if there are some groups to compare
+    if dimensions.is_empty() {
+        return vec![];
+    }
+
+    // With more than one dimension, we can compare groups within the first dimension
+    let partition_by: Vec<Dimension> = if dimensions.len() > 1 {
+        vec![dimensions[0].clone()]
+    } else {
+        vec![]
+    };
+
+    let mut window_measures = Vec::new();
+
+    for measure in measures {
+        // Geometries can't be ranked
+        if is_geospatial(&measure.data_type()) {
+            continue;
+        }
+
+        window_measures.push(WindowMeasure {
+            function: WindowFunction::Rank,
+            window: Window {
+                partition_by: partition_by.clone(),
+                order_by: vec![OrderBy {
+                    item: OrderByItem::Measure(measure.clone()),
+                    direction: OrderByDirection::Desc,
+                }],
+                frame: None,
+            },
+        });
+
+        // Running totals and shares only make sense for measures that can be added up
+        if !matches!(
+            measure.type_,
+            MeasureType::Count
+                | MeasureType::CountNonNull
+                | MeasureType::Sum
+                | MeasureType::CountWithinRadius { .. }
+        ) {
+            continue;
+        }
+
+        window_measures.push(WindowMeasure {
+            function: WindowFunction::ShareOfTotal(measure.clone()),
+            window: Window {
+                partition_by: partition_by.clone(),
+                order_by: vec![],
+                frame: None,
+            },
+        });
+
+        for time_dimension in dimensions
+            .iter()
+            .filter(|d| matches!(d.transform, Some(DimensionTransform::DateTrunc(_))))
+        {
+            window_measures.push(WindowMeasure {
+                function: WindowFunction::Sum(measure.clone()),
+                window: Window {
+                    // Running total within every combination of the other dimensions
+                    partition_by: dimensions
+                        .iter()
+                        .filter(|d| *d != time_dimension)
+                        .cloned()
+                        .collect(),
+                    order_by: vec![OrderBy {
+                        item: OrderByItem::Dimension(time_dimension.clone()),
+                        direction: OrderByDirection::Asc,
+                    }],
+                    frame: Some(WindowFrame {
+                        units: WindowFrameUnits::Rows,
+                        start: WindowFrameBound::UnboundedPreceding,
+                        end: WindowFrameBound::CurrentRow,
+                    }),
+                },
+            });
+        }
+    }
+
+    window_measures
+}
+
+/// Get the top `n` values of `item` for every value of `group`, ranked by `measure`:
+///
+/// WITH ranked AS (
+///   SELECT group, item, measure, ROW_NUMBER() OVER (PARTITION BY group ORDER BY measure DESC)
+///   ...
+/// )
+/// SELECT ... FROM ranked WHERE row_number <= n
+pub fn top_n_per_group_query<T: Syntax + Clone>(
+    dataset: &Dataset,
+    measure: &Measure,
+    group: &Dimension,
+    item: &Dimension,
+    n: usize,
+    syntax: T,
+) -> Query<T> {
+    let mut ranked = Query::new(
+        dataset.clone(),
+        vec![measure.clone()],
+        vec![group.clone(), item.clone()],
+        vec![],
+        None,
+        syntax.clone(),
+    );
+    ranked.window_measures = vec![WindowMeasure {
+        function: WindowFunction::RowNumber,
+        window: Window {
+            partition_by: vec![group.clone()],
+            order_by: vec![OrderBy {
+                item: OrderByItem::Measure(measure.clone()),
+                direction: OrderByDirection::Desc,
+            }],
+            frame: None,
+        },
+    }];
+
+    let columns = ranked.output_columns();
+    let group_column = columns.first().unwrap().clone();
+    let row_number_column = columns.last().unwrap().clone();
+
+    let mut query = Query::new(
+        dataset.clone(),
+        vec![],
+        vec![],
+        vec![
+            OrderBy {
+                item: OrderByItem::Dimension(Dimension {
+                    column: group_column,
+                    transform: None,
+                    alias: None,
+                }),
+                direction: OrderByDirection::Asc,
+            },
+            OrderBy {
+                item: OrderByItem::Dimension(Dimension {
+                    column: row_number_column.clone(),
+                    transform: None,
+                    alias: None,
+                }),
+                direction: OrderByDirection::Asc,
+            },
+        ],
+        Some(100),
+        syntax,
+    );
+    query.columns = columns;
+    query.filter = Some(Filter::Comparison {
+        column: row_number_column,
+        operator: ComparisonOperator::LtEq,
+        value: Literal::Number(n.to_string()),
+    });
+    query.ctes = vec![CommonTableExpression {
+        name: "ranked".to_string(),
+        query: ranked,
+    }];
+    query.source = QuerySource::CommonTableExpression("ranked".to_string());
+
+    query
+}
+
+pub fn random_top_n_per_group_query<R: Rng, T: Syntax + Clone>(
+    rng: &mut R,
+    dataset: &Dataset,
+    measures: &[Measure],
+    dimensions: &[Dimension],
+    scorer: &dyn ColumnScorer,
+    syntax: T,
+) -> Option<Query<T>> {
+    let (measures, dimensions) = supported_by_syntax(&syntax, measures, dimensions);
+    // We rank by the measure and sort by the group, so they have to be sortable
+    let sortable = |item: OrderByItem, score: f64| if is_orderable(&item) { score } else { 0.0 };
+    let measure = choose_weighted(rng, &measures, 1, |m| {
+        sortable(OrderByItem::Measure(m.clone()), scorer.score_measure(m))
+    })
+    .pop()?;
+    let group = choose_weighted(rng, &dimensions, 1, |d| {
+        sortable(OrderByItem::Dimension(d.clone()), scorer.score_dimension(d))
+    })
+    .pop()?;
+    // Ranking e.g. the months of a date within each year of that same date isn't a top N
+    let item = choose_weighted(rng, &dimensions, 1, |d| {
+        if d.column == group.column {
+            0.0
+        } else {
+            sortable(OrderByItem::Dimension(d.clone()), scorer.score_dimension(d))
+        }
+    })
+    .pop()?;
+
+    let query = top_n_per_group_query(
+        dataset,
+        &measure,
+        &group,
+        &item,
+        rng.gen_range(1..6),
+        syntax,
+    );
+
+    // e.g. if the group and the item end up with the same name
+    query.validate().is_empty().then_some(query)
+}
+
+pub fn random_filter<R: Rng>(rng: &mut R, column: &Column) -> Option<Filter> {
+    // Ignore Socrata derived geo columns and columns without any values
+    if is_computed_region(column) || column.stats.is_all_null() {
+        return None;
+    };
+
+    let column = column.clone();
+
+    match column.data_type {
+        DataType::Money | DataType::Number | DataType::Double => match number_range(&column) {
+            // Pick a range between two values that the column actually has
+            Some((smallest, largest)) => {
+                let (low, high) = random_range(rng, smallest, largest);
+                let (low, high) = (Literal::Number(low), Literal::Number(high));
+
+                Some(match rng.gen_range(0..3) {
+                    0 => Filter::Comparison {
+                        column,
+                        operator: ComparisonOperator::GtEq,
+                        value: low,
+                    },
+                    1 => Filter::Comparison {
+                        column,
+                        operator: ComparisonOperator::LtEq,
+                        value: high,
+                    },
+                    _ => Filter::Between { column, low, high },
+                })
+            }
+            // We don't know the distribution of the column, so pick an order of magnitude
+            None => {
+                let high = Literal::Number(10u64.pow(rng.gen_range(1..7)).to_string());
+                Some(match rng.gen_range(0..3) {
+                    0 => Filter::Comparison {
+                        column,
+                        operator: ComparisonOperator::Gt,
+                        value: Literal::Number("0".to_string()),
+                    },
+                    1 => Filter::Comparison {
+                        column,
+                        operator: ComparisonOperator::Lt,
+                        value: high,
+                    },
+                    _ => Filter::Between {
+                        column,
+                        low: Literal::Number("0".to_string()),
+                        high,
+                    },
+                })
+            }
+        },
+        DataType::CalendarDate | DataType::FloatingTimestamp => {
+            // Stay within the years the column has, if we know them
+            let (first_year, last_year) = year_range(&column).unwrap_or((2010, 2022));
+            let start = rng.gen_range(first_year..=last_year);
+            Some(match rng.gen_range(0..2) {
+                0 => Filter::Comparison {
+                    column,
+                    operator: ComparisonOperator::GtEq,
+                    value: Literal::Date(format!("{start}-01-01")),
+                },
+                _ => Filter::Between {
+                    column,
+                    low: Literal::Date(format!("{start}-01-01")),
+                    high: Literal::Date(format!("{:}-12-31", rng.gen_range(start..=last_year))),
+                },
+            })
+        }
+        // Filter on some of the most common values, if we know them
+        DataType::Text if !column.stats.top_values.is_empty() => {
+            let top_values = &column.stats.top_values;
+            let count = rng.gen_range(1..=top_values.len().min(3));
+            let mut values: Vec<Literal> = top_values
+                .choose_multiple(rng, count)
+                .map(|v| Literal::Text(v.clone()))
+                .collect();
+
+            Some(if values.len() == 1 {
+                Filter::Comparison {
+                    column,
+                    operator: ComparisonOperator::Eq,
+                    value: values.pop().unwrap(),
+                }
+            } else {
+                Filter::In { column, values }
+            })
+        }
+        // Otherwise, we don't know any actual values of text columns, so the best we
+        // can do is filter out empty ones
+        DataType::Text | DataType::Url => Some(match rng.gen_range(0..2) {
+            0 => Filter::IsNull {
+                column,
+                negated: true,
+            },
+            _ => Filter::Comparison {
+                column,
+                operator: ComparisonOperator::NotEq,
+                value: Literal::Text("".to_string()),
+            },
+        }),
+        DataType::Checkbox => Some(Filter::Comparison {
+            column,
+            operator: ComparisonOperator::Eq,
+            value: Literal::Boolean(rng.gen()),
+        }),
+        _ => None,
+    }
+}
+
+pub fn random_having<R: Rng>(rng: &mut R, measure: &Measure) -> Option<HavingCondition> {
+    let measure = measure.clone();
+
+    if matches!(
+        measure.type_,
+        MeasureType::Count
+            | MeasureType::CountDistinct
+            | MeasureType::CountNonNull
+            | MeasureType::CountWithinRadius { .. }
+    ) {
+        // Mostly used to get rid of the long tail of groups with a single row
+        let value = [1, 1, 5, 10, 100].choose(rng).unwrap().to_string();
+        return Some(HavingCondition::Comparison {
+            measure,
+            operator: ComparisonOperator::Gt,
+            value: Literal::Number(value),
+        });
+    }
+
+    let column = measure.column.clone()?;
+    match column.data_type {
+        DataType::Money | DataType::Number | DataType::Double => {
+            let magnitude = 10u64.pow(rng.gen_range(1..7));
+            let (low, high) = match (&measure.type_, number_range(&column)) {
+                // These end up between the smallest and the largest value of the column
+                (
+                    MeasureType::Average
+                    | MeasureType::Min
+                    | MeasureType::Max
+                    | MeasureType::Median
+                    | MeasureType::Percentile(_),
+                    Some((smallest, largest)),
+                ) => random_range(rng, smallest, largest),
+                // Adding up values that are never positive doesn't make a positive number
+                (MeasureType::Sum, Some((_, largest))) if largest <= 0.0 => {
+                    (format!("-{magnitude}"), "0".to_string())
+                }
+                // Otherwise, pick an order of magnitude
+                _ => ("0".to_string(), magnitude.to_string()),
+            };
+            Some(match rng.gen_range(0..2) {
+                0 => HavingCondition::Comparison {
+                    measure,
+                    operator: ComparisonOperator::Gt,
+                    value: Literal::Number(low),
+                },
+                _ => HavingCondition::Between {
+                    measure,
+                    low: Literal::Number(low),
+                    high: Literal::Number(high),
+                },
+            })
+        }
+        DataType::CalendarDate | DataType::FloatingTimestamp => {
+            // Stay within the years the column has, if we know them
+            let (first_year, last_year) = year_range(&column).unwrap_or((2010, 2022));
+            Some(HavingCondition::Comparison {
+                measure,
+                operator: ComparisonOperator::GtEq,
+                value: Literal::Date(format!("{:}-01-01", rng.gen_range(first_year..=last_year))),
+            })
+        }
+        _ => None,
+    }
+}
+
+pub struct RandomQueryParams {
+    pub no_dimensions: Range<usize>,
+    pub no_measures: Range<usize>,
+    pub no_order_bys: Range<usize>,
+    pub no_havings: Range<usize>,
+    pub no_window_measures: Range<usize>,
+    pub no_filters: Range<usize>,
+    // Reduce (see `reduce_cost`) or reject queries that `estimate_relative_cost` thinks are
+    // more expensive
+    pub max_cost: Option<f64>,
+    // Weights for picking measures and dimensions
+    pub scorer: Box<dyn ColumnScorer>,
+}
+
+/// Drop the measures and dimensions that a syntax can't express
+fn supported_by_syntax<T: Syntax>(
+    syntax: &T,
+    measures: &[Measure],
+    dimensions: &[Dimension],
+) -> (Vec<Measure>, Vec<Dimension>) {
+    (
+        measures
+            .iter()
+            .filter(|m| syntax.supports_measure_type(&m.type_))
+            .cloned()
+            .collect(),
+        dimensions
+            .iter()
+            .filter(|d| {
+                d.transform
+                    .as_ref()
+                    .is_none_or(|t| syntax.supports_dimension_transform(t))
+            })
+            .cloned()
+            .collect(),
+    )
+}
+
+// How many random queries to try before giving up and falling back to COUNT(*)
+const RANDOM_QUERY_ATTEMPTS: usize = 10;
+
+/// Generate a random query that passes validation
+pub fn random_query<R: Rng, T: Syntax + Clone>(
+    rng: &mut R,
+    dataset: &Dataset,
+    measures: &[Measure],
+    dimensions: &[Dimension],
+    params: &RandomQueryParams,
+    syntax: T,
+) -> Query<T> {
+    let (measures, dimensions) = supported_by_syntax(&syntax, measures, dimensions);
+
+    for _ in 0..RANDOM_QUERY_ATTEMPTS {
+        let mut query =
+            random_query_candidate(rng, dataset, &measures, &dimensions, params, syntax.clone());
+        if let Some(max_cost) = params.max_cost {
+            reduce_cost(rng, &mut query, max_cost);
+            if estimate_relative_cost(&query) > max_cost {
+                continue;
+            }
+        }
+        if query.validate().is_empty() {
+            return query;
+        }
+    }
+
+    // Only reads every row once, so it fits into any budget that isn't below 2
+    Query::new(
+        dataset.clone(),
+        vec![Measure {
+            type_: MeasureType::Count,
+            column: None,
+        }],
+        vec![],
+        vec![],
+        None,
+        syntax,
+    )
+}
+
+fn random_query_candidate<R: Rng, T: Syntax>(
+    rng: &mut R,
+    dataset: &Dataset,
+    measures: &[Measure],
+    dimensions: &[Dimension],
+    params: &RandomQueryParams,
+    syntax: T,
+) -> Query<T> {
+    let RandomQueryParams {
+        no_dimensions,
+        no_measures,
+        no_order_bys,
+        no_havings,
+        no_window_measures,
+        no_filters,
+        max_cost: _,
+        scorer,
+    } = params;
+
+    let no_dimensions = rng.gen_range(no_dimensions.clone());
+    let no_measures = rng.gen_range(no_measures.clone());
+    let no_order_bys = rng
+        .gen_range(no_order_bys.clone())
+        .min(no_dimensions + no_measures);
+    let no_havings = rng.gen_range(no_havings.clone());
+    let no_window_measures = rng.gen_range(no_window_measures.clone());
+    let no_filters = rng.gen_range(no_filters.clone());
+
+    let chosen_measures: Vec<Measure> =
+        choose_weighted(rng, measures, no_measures, |m| scorer.score_measure(m));
+    // One at a time, since grouping on two transforms of the same column (e.g. the year and the
+    // month of a date) is redundant
+    let mut chosen_dimensions: Vec<Dimension> = vec![];
+    for _ in 0..no_dimensions {
+        let dimension = choose_weighted(rng, dimensions, 1, |d| {
+            if chosen_dimensions.iter().any(|c| c.column == d.column) {
+                0.0
+            } else {
+                scorer.score_dimension(d)
+            }
+        })
+        .pop();
+        match dimension {
+            Some(dimension) => chosen_dimensions.push(dimension),
+            None => break,
+        }
+    }
+
+    let mut all_order_bys: Vec<OrderBy> = chosen_measures
+        .iter()
+        .map(|m| OrderBy {
+            item: OrderByItem::Measure(m.clone()),
+            direction: match rng.gen_range(0..2) {
+                0 => OrderByDirection::Asc,
+                _ => OrderByDirection::Desc,
+            },
+        })
+        .collect();
+
+    all_order_bys.extend(chosen_dimensions.iter().map(|d| OrderBy {
+        item: OrderByItem::Dimension(d.clone()),
+        direction: match rng.gen_range(0..2) {
+            0 => OrderByDirection::Asc,
+            _ => OrderByDirection::Desc,
+        },
+    }));
+
+    all_order_bys.retain(|o| is_orderable(&o.item));
+    let chosen_order_bys = all_order_bys
+        .choose_multiple(rng, no_order_bys)
+        .cloned()
+        .collect();
+
+    // HAVING only makes sense if we're actually grouping on something
+    let chosen_havings: Vec<HavingCondition> = if chosen_dimensions.is_empty() {
+        vec![]
+    } else {
+        let all_havings: Vec<HavingCondition> = chosen_measures
+            .iter()
+            .filter_map(|m| random_having(rng, m))
+            .collect();
+        all_havings
+            .choose_multiple(rng, no_havings)
+            .cloned()
+            .collect()
+    };
+
+    let chosen_window_measures: Vec<WindowMeasure> = if syntax.supports_window_functions() {
+        build_window_measures(&chosen_measures, &chosen_dimensions)
+            .choose_multiple(rng, no_window_measures)
+            .cloned()
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let all_filters: Vec<Filter> = dataset
+        .columns
+        .iter()
+        .filter_map(|c| random_filter(rng, c))
+        .collect();
+    let mut chosen_filters: Vec<Filter> = all_filters
+        .choose_multiple(rng, no_filters)
+        .cloned()
+        .collect();
+
+    let mut query = Query::new(
+        dataset.clone(),
+        chosen_measures,
+        chosen_dimensions,
+        chosen_order_bys,
+        Some(100),
+        syntax,
+    );
+
+    query.window_measures = chosen_window_measures;
+    query.having = chosen_havings;
+    query.filter = match chosen_filters.len() {
+        0 => None,
+        1 => chosen_filters.pop(),
+        _ => Some(match rng.gen_range(0..4) {
+            0 => Filter::Or(chosen_filters),
+            _ => Filter::And(chosen_filters),
+        }),
+    };
+
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::query::{
+        build_binnings, build_dimensions, build_measures, build_window_measures, dataset_center,
+        random_filter, random_having, random_query, random_top_n_per_group_query,
+        top_n_per_group_query, Binning, ComparisonOperator, DatePart, DateTruncUnit, DefaultSyntax,
+        Diagnostic, Dimension, DimensionTransform, Filter, HavingCondition, Literal, Measure,
+        MeasureType, OrderBy, OrderByDirection, OrderByItem, Query, RandomQueryParams, Window,
+        WindowFrame, WindowFrameBound, WindowFrameUnits, WindowFunction, WindowMeasure,
+    };
+    use crate::query_gen::scoring::DefaultScorer;
+    use crate::query_gen::socrata::{Column, ColumnFormat, ColumnStats, DataType, Dataset};
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use itertools::Itertools;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_build_measures() {
+        let dataset = edmonton_dataset();
 
         assert_eq!(
-            build_measures(&dataset),
+            build_measures(&dataset)
+                .into_iter()
+                .filter(|m| matches!(
+                    m.type_,
+                    MeasureType::Count
+                        | MeasureType::Sum
+                        | MeasureType::Average
+                        | MeasureType::Min
+                        | MeasureType::Max
+                ))
+                .collect::<Vec<Measure>>(),
             vec![
                 Measure {
                     type_: MeasureType::Count,
@@ -343,7 +2383,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Facility/Service ID".to_string(),
                         pg_name: "facility_service_id".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -351,7 +2395,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Facility/Service ID".to_string(),
                         pg_name: "facility_service_id".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -359,7 +2407,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Facility/Service ID".to_string(),
                         pg_name: "facility_service_id".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -367,7 +2419,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Facility/Service ID".to_string(),
                         pg_name: "facility_service_id".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -375,7 +2431,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notification ID".to_string(),
                         pg_name: "notification_id".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -383,7 +2443,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notification ID".to_string(),
                         pg_name: "notification_id".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -391,7 +2455,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notification ID".to_string(),
                         pg_name: "notification_id".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -399,7 +2467,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notification ID".to_string(),
                         pg_name: "notification_id".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -407,7 +2479,9 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notice Publish Start Date".to_string(),
                         pg_name: "notice_publish_start_date".to_string(),
-                        data_type: DataType::CalendarDate
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -415,7 +2489,9 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notice Publish Start Date".to_string(),
                         pg_name: "notice_publish_start_date".to_string(),
-                        data_type: DataType::CalendarDate
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -423,7 +2499,9 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notice Publish End Date".to_string(),
                         pg_name: "notice_publish_end_date".to_string(),
-                        data_type: DataType::CalendarDate
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -431,7 +2509,9 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notice Publish End Date".to_string(),
                         pg_name: "notice_publish_end_date".to_string(),
-                        data_type: DataType::CalendarDate
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -439,7 +2519,9 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notification Start Date".to_string(),
                         pg_name: "notification_start_date".to_string(),
-                        data_type: DataType::CalendarDate
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -447,7 +2529,9 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notification Start Date".to_string(),
                         pg_name: "notification_start_date".to_string(),
-                        data_type: DataType::CalendarDate
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -455,7 +2539,9 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notification End Date".to_string(),
                         pg_name: "notification_end_date".to_string(),
-                        data_type: DataType::CalendarDate
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -463,7 +2549,9 @@ mod tests {
                     column: Some(Column {
                         human_name: "Notification End Date".to_string(),
                         pg_name: "notification_end_date".to_string(),
-                        data_type: DataType::CalendarDate
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -471,7 +2559,9 @@ mod tests {
                     column: Some(Column {
                         human_name: "Last Updated".to_string(),
                         pg_name: "last_updated".to_string(),
-                        data_type: DataType::CalendarDate
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -479,7 +2569,9 @@ mod tests {
                     column: Some(Column {
                         human_name: "Last Updated".to_string(),
                         pg_name: "last_updated".to_string(),
-                        data_type: DataType::CalendarDate
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -487,7 +2579,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Latitude".to_string(),
                         pg_name: "latitude".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -495,7 +2591,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Latitude".to_string(),
                         pg_name: "latitude".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -503,7 +2603,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Latitude".to_string(),
                         pg_name: "latitude".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -511,7 +2615,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Latitude".to_string(),
                         pg_name: "latitude".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -519,7 +2627,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Longitude".to_string(),
                         pg_name: "longitude".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -527,7 +2639,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Longitude".to_string(),
                         pg_name: "longitude".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -535,7 +2651,11 @@ mod tests {
                     column: Some(Column {
                         human_name: "Longitude".to_string(),
                         pg_name: "longitude".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 },
                 Measure {
@@ -543,314 +2663,1953 @@ mod tests {
                     column: Some(Column {
                         human_name: "Longitude".to_string(),
                         pg_name: "longitude".to_string(),
-                        data_type: DataType::Number
+                        data_type: DataType::Number,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
                     })
                 }
             ],
         );
     }
 
+    #[test]
+    fn test_build_count_measures() {
+        let dataset = edmonton_dataset();
+
+        let status = Column {
+            human_name: "Status".to_string(),
+            pg_name: "status".to_string(),
+            data_type: DataType::Text,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let measures = build_measures(&dataset);
+
+        assert!(measures.contains(&Measure {
+            type_: MeasureType::CountDistinct,
+            column: Some(status.clone())
+        }));
+        assert!(measures.contains(&Measure {
+            type_: MeasureType::CountNonNull,
+            column: Some(status)
+        }));
+        // Only text-like columns get the new COUNT measures
+        assert!(measures
+            .iter()
+            .filter(|m| matches!(
+                m.type_,
+                MeasureType::CountDistinct | MeasureType::CountNonNull
+            ))
+            .all(|m| matches!(
+                m.column.as_ref().unwrap().data_type,
+                DataType::Text | DataType::Url | DataType::Checkbox
+            )));
+    }
+
+    #[test]
+    fn test_build_statistical_measures() {
+        let dataset = edmonton_dataset();
+
+        let latitude = Column {
+            human_name: "Latitude".to_string(),
+            pg_name: "latitude".to_string(),
+            data_type: DataType::Number,
+            format: ColumnFormat {
+                precision_style: Some("standard".to_string()),
+            },
+            stats: ColumnStats::default(),
+        };
+
+        assert_eq!(
+            build_measures(&dataset)
+                .into_iter()
+                .filter(|m| m.column.as_ref() == Some(&latitude))
+                .map(|m| m.type_)
+                .collect::<Vec<MeasureType>>(),
+            vec![
+                MeasureType::Min,
+                MeasureType::Max,
+                MeasureType::Sum,
+                MeasureType::Average,
+                MeasureType::Median,
+                MeasureType::Percentile(90),
+                MeasureType::StddevSamp,
+                MeasureType::VarSamp,
+            ]
+        );
+    }
+
     #[test]
     fn test_build_dimensions() {
-        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        d.push("resources/test/socrata_resource.json");
-        let resource_json = fs::read_to_string(d).unwrap();
-        let resource: RawDatasetResource = serde_json::from_str(&resource_json).unwrap();
-        let dataset = parse_dataset("data.edmonton.ca", &resource);
+        let dataset = edmonton_dataset();
 
         assert_eq!(
-            build_dimensions(&dataset),
+            build_dimensions(&dataset)
+                .into_iter()
+                .filter(|d| d.transform.is_none())
+                .collect::<Vec<Dimension>>(),
             vec![
                 Dimension {
                     column: Column {
                         human_name: "Row ID".to_string(),
                         pg_name: "row_id".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat {
+                            precision_style: Some("standard".to_string())
+                        },
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Facility/Service Type".to_string(),
                         pg_name: "facility_service_type".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Facility/Service Name".to_string(),
                         pg_name: "facility_service_name".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Facility/Service Short Name".to_string(),
                         pg_name: "facility_service_short_name".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Facility/Service Information".to_string(),
                         pg_name: "facility_service_information".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Address".to_string(),
                         pg_name: "address".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "edmonton.ca Info Page".to_string(),
                         pg_name: "edmonton_ca_info_page".to_string(),
-                        data_type: DataType::Url
-                    }
+                        data_type: DataType::Url,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Notice Date Information".to_string(),
                         pg_name: "notice_date_information".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Is a Service Notification".to_string(),
                         pg_name: "is_a_service_notification".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Notice Start Time of Day".to_string(),
                         pg_name: "notice_start_time_of_day".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Notice End Time of Day".to_string(),
                         pg_name: "notice_end_time_of_day".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Notice Has Holiday Hours".to_string(),
                         pg_name: "notice_has_holiday_hours".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Additional Information".to_string(),
                         pg_name: "additional_information".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Status".to_string(),
                         pg_name: "status".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Date Time".to_string(),
                         pg_name: "date_time".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Service".to_string(),
                         pg_name: "service".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Reason".to_string(),
                         pg_name: "reason".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Location (address)".to_string(),
                         pg_name: "location_address".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Location (city)".to_string(),
                         pg_name: "location_city".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
                 },
                 Dimension {
                     column: Column {
                         human_name: "Location (state)".to_string(),
                         pg_name: "location_state".to_string(),
-                        data_type: DataType::Text
-                    }
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
+                },
+                Dimension {
+                    column: Column {
+                        human_name: "Location (zip)".to_string(),
+                        pg_name: "location_zip".to_string(),
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default()
+                    },
+                    transform: None,
+                    alias: None
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_time_dimensions() {
+        let dataset = edmonton_dataset();
+
+        let time_dimensions: Vec<Dimension> = build_dimensions(&dataset)
+            .into_iter()
+            .filter(|d| d.column.pg_name == "last_updated")
+            .collect();
+
+        let last_updated = Column {
+            human_name: "Last Updated".to_string(),
+            pg_name: "last_updated".to_string(),
+            data_type: DataType::CalendarDate,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+
+        assert_eq!(
+            time_dimensions,
+            vec![
+                DimensionTransform::DateTrunc(DateTruncUnit::Day),
+                DimensionTransform::DateTrunc(DateTruncUnit::Week),
+                DimensionTransform::DateTrunc(DateTruncUnit::Month),
+                DimensionTransform::DateTrunc(DateTruncUnit::Quarter),
+                DimensionTransform::DateTrunc(DateTruncUnit::Year),
+                DimensionTransform::Extract(DatePart::Year),
+                DimensionTransform::Extract(DatePart::Month),
+                DimensionTransform::Extract(DatePart::DayOfWeek),
+            ]
+            .into_iter()
+            .map(|t| Dimension {
+                column: last_updated.clone(),
+                transform: Some(t),
+                alias: None
+            })
+            .collect::<Vec<Dimension>>()
+        );
+    }
+
+    #[test]
+    fn test_emit_query_time_dimensions() {
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Facility/Service Notification History".to_string(),
+            row_count: None,
+            columns: vec![],
+        };
+        let last_updated = Column {
+            human_name: "Last Updated".to_string(),
+            pg_name: "last_updated".to_string(),
+            data_type: DataType::CalendarDate,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let by_month = Dimension {
+            column: last_updated.clone(),
+            transform: Some(DimensionTransform::DateTrunc(DateTruncUnit::Month)),
+            alias: None,
+        };
+        let by_day_of_week = Dimension {
+            column: last_updated,
+            transform: Some(DimensionTransform::Extract(DatePart::DayOfWeek)),
+            alias: None,
+        };
+
+        let query = Query::new(
+            dataset,
+            vec![Measure {
+                type_: MeasureType::Count,
+                column: None,
+            }],
+            vec![by_month.clone(), by_day_of_week],
+            vec![OrderBy {
+                item: OrderByItem::Dimension(by_month),
+                direction: OrderByDirection::Asc,
+            }],
+            None,
+            DefaultSyntax {},
+        );
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  date_trunc('month', last_updated) AS month_last_updated,
+  EXTRACT(DOW FROM last_updated) AS dow_of_last_updated,
+  COUNT(*) AS count
+FROM "u7r4-acwa"
+GROUP BY
+  date_trunc('month', last_updated),
+  EXTRACT(DOW FROM last_updated)
+ORDER BY
+  date_trunc('month', last_updated) ASC"#
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_build_binnings() {
+        let column = |data_type: DataType, precision_style: Option<&str>| Column {
+            human_name: "Estimated Cost".to_string(),
+            pg_name: "estimated_cost".to_string(),
+            data_type,
+            format: ColumnFormat {
+                precision_style: precision_style.map(|s| s.to_string()),
+            },
+            stats: ColumnStats::default(),
+        };
+
+        assert_eq!(
+            build_binnings(&column(DataType::Number, None)),
+            vec![
+                Binning::Fixed {
+                    width: "10".to_string()
+                },
+                Binning::Log
+            ]
+        );
+        assert_eq!(
+            build_binnings(&column(DataType::Number, Some("currency"))),
+            vec![
+                Binning::Fixed {
+                    width: "100".to_string()
+                },
+                Binning::Log
+            ]
+        );
+        assert_eq!(
+            build_binnings(&column(DataType::Double, Some("percentage"))),
+            vec![
+                Binning::Fixed {
+                    width: "10".to_string()
+                },
+                Binning::Range {
+                    low: "0".to_string(),
+                    high: "100".to_string(),
+                    count: 10
+                }
+            ]
+        );
+
+        // Statistics win over the format
+        let mut with_stats = column(DataType::Double, Some("percentage"));
+        with_stats.stats = ColumnStats {
+            smallest: Some("-12.5".to_string()),
+            largest: Some("980".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            build_binnings(&with_stats),
+            vec![
+                Binning::Range {
+                    low: "-13".to_string(),
+                    high: "980".to_string(),
+                    count: 10
+                },
+                Binning::Log
+            ]
+        );
+    }
+
+    #[test]
+    fn test_emit_query_binned_dimensions() {
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Facility/Service Notification History".to_string(),
+            row_count: None,
+            columns: vec![],
+        };
+        let cost = Column {
+            human_name: "Estimated Cost".to_string(),
+            pg_name: "estimated_cost".to_string(),
+            data_type: DataType::Money,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+
+        let query = Query::new(
+            dataset,
+            vec![Measure {
+                type_: MeasureType::Count,
+                column: None,
+            }],
+            vec![
+                Dimension {
+                    column: cost.clone(),
+                    transform: Some(DimensionTransform::Bin(Binning::Fixed {
+                        width: "100".to_string(),
+                    })),
+                    alias: None,
+                },
+                Dimension {
+                    column: cost.clone(),
+                    transform: Some(DimensionTransform::Bin(Binning::Range {
+                        low: "0".to_string(),
+                        high: "1000".to_string(),
+                        count: 10,
+                    })),
+                    alias: None,
+                },
+                Dimension {
+                    column: cost,
+                    transform: Some(DimensionTransform::Bin(Binning::Log)),
+                    alias: None,
+                },
+            ],
+            vec![],
+            None,
+            DefaultSyntax {},
+        );
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  floor(estimated_cost / 100) * 100 AS bucket_estimated_cost,
+  width_bucket(estimated_cost, 0, 1000, 10) AS width_bucket_estimated_cost,
+  floor(log(greatest(estimated_cost, 1))) AS magnitude_estimated_cost,
+  COUNT(*) AS count
+FROM "u7r4-acwa"
+GROUP BY
+  floor(estimated_cost / 100) * 100,
+  width_bucket(estimated_cost, 0, 1000, 10),
+  floor(log(greatest(estimated_cost, 1)))"#
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_emit_query() {
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Facility/Service Notification History".to_string(),
+            row_count: None,
+            // Doesn't matter in this context
+            columns: vec![],
+        };
+
+        let query = Query::new(
+            dataset,
+            vec![
+                Measure {
+                    type_: MeasureType::Count,
+                    column: None,
+                },
+                Measure {
+                    type_: MeasureType::Min,
+                    column: Some(Column {
+                        human_name: "Notification Start Date".to_string(),
+                        pg_name: "notification_start_date".to_string(),
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default(),
+                    }),
+                },
+                Measure {
+                    type_: MeasureType::Max,
+                    column: Some(Column {
+                        human_name: "Notification End Date".to_string(),
+                        pg_name: "notification_end_date".to_string(),
+                        data_type: DataType::CalendarDate,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default(),
+                    }),
+                },
+                Measure {
+                    type_: MeasureType::Average,
+                    column: Some(Column {
+                        human_name: "Latitude".to_string(),
+                        pg_name: "latitude".to_string(),
+                        data_type: DataType::Number,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default(),
+                    }),
+                },
+                Measure {
+                    type_: MeasureType::Average,
+                    column: Some(Column {
+                        human_name: "Longitude".to_string(),
+                        pg_name: "longitude".to_string(),
+                        data_type: DataType::Number,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default(),
+                    }),
+                },
+            ],
+            vec![
+                Dimension {
+                    column: Column {
+                        human_name: "Facility/Service Type".to_string(),
+                        pg_name: "facility_service_type".to_string(),
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default(),
+                    },
+                    transform: None,
+                    alias: None,
+                },
+                Dimension {
+                    column: Column {
+                        human_name: "Status".to_string(),
+                        pg_name: "status".to_string(),
+                        data_type: DataType::Text,
+                        format: ColumnFormat::default(),
+                        stats: ColumnStats::default(),
+                    },
+                    transform: None,
+                    alias: None,
+                },
+            ],
+            vec![
+                OrderBy {
+                    item: OrderByItem::Dimension(Dimension {
+                        column: Column {
+                            human_name: "Facility/Service Type".to_string(),
+                            pg_name: "facility_service_type".to_string(),
+                            data_type: DataType::Text,
+                            format: ColumnFormat::default(),
+                            stats: ColumnStats::default(),
+                        },
+                        transform: None,
+                        alias: None,
+                    }),
+
+                    direction: OrderByDirection::Asc,
+                },
+                OrderBy {
+                    item: OrderByItem::Measure(Measure {
+                        type_: MeasureType::Min,
+                        column: Some(Column {
+                            human_name: "Notification Start Date".to_string(),
+                            pg_name: "notification_start_date".to_string(),
+                            data_type: DataType::CalendarDate,
+                            format: ColumnFormat::default(),
+                            stats: ColumnStats::default(),
+                        }),
+                    }),
+                    direction: OrderByDirection::Desc,
+                },
+            ],
+            Some(100),
+            DefaultSyntax {},
+        );
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  facility_service_type,
+  status,
+  COUNT(*) AS count,
+  MIN(notification_start_date) AS min_notification_start_date,
+  MAX(notification_end_date) AS max_notification_end_date,
+  AVG(latitude) AS avg_latitude,
+  AVG(longitude) AS avg_longitude
+FROM "u7r4-acwa"
+GROUP BY
+  facility_service_type,
+  status
+ORDER BY
+  facility_service_type ASC,
+  MIN(notification_start_date) DESC
+LIMIT 100"#
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_emit_query_count_measures() {
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Facility/Service Notification History".to_string(),
+            row_count: None,
+            columns: vec![],
+        };
+        let reason = Column {
+            human_name: "Reason".to_string(),
+            pg_name: "reason".to_string(),
+            data_type: DataType::Text,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let count_distinct = Measure {
+            type_: MeasureType::CountDistinct,
+            column: Some(reason.clone()),
+        };
+
+        let query = Query::new(
+            dataset,
+            vec![
+                count_distinct.clone(),
+                Measure {
+                    type_: MeasureType::CountNonNull,
+                    column: Some(reason),
+                },
+            ],
+            vec![],
+            vec![OrderBy {
+                item: OrderByItem::Measure(count_distinct),
+                direction: OrderByDirection::Desc,
+            }],
+            None,
+            DefaultSyntax {},
+        );
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  COUNT(DISTINCT reason) AS count_distinct_reason,
+  COUNT(reason) AS count_reason
+FROM "u7r4-acwa"
+ORDER BY
+  COUNT(DISTINCT reason) DESC"#
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_emit_query_statistical_measures() {
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Facility/Service Notification History".to_string(),
+            row_count: None,
+            columns: vec![],
+        };
+        let cost = Column {
+            human_name: "Estimated Cost".to_string(),
+            pg_name: "estimated_cost".to_string(),
+            data_type: DataType::Money,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let measure = |type_: MeasureType| Measure {
+            type_,
+            column: Some(cost.clone()),
+        };
+
+        let query = Query::new(
+            dataset,
+            vec![
+                measure(MeasureType::Median),
+                measure(MeasureType::Percentile(90)),
+                measure(MeasureType::StddevSamp),
+                measure(MeasureType::VarSamp),
+            ],
+            vec![],
+            vec![OrderBy {
+                item: OrderByItem::Measure(measure(MeasureType::Percentile(25))),
+                direction: OrderByDirection::Asc,
+            }],
+            None,
+            DefaultSyntax {},
+        );
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY estimated_cost) AS median_estimated_cost,
+  PERCENTILE_CONT(0.9) WITHIN GROUP (ORDER BY estimated_cost) AS p90_estimated_cost,
+  STDDEV_SAMP(estimated_cost) AS stddev_estimated_cost,
+  VAR_SAMP(estimated_cost) AS variance_estimated_cost
+FROM "u7r4-acwa"
+ORDER BY
+  PERCENTILE_CONT(0.25) WITHIN GROUP (ORDER BY estimated_cost) ASC"#
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_build_window_measures() {
+        let status = Dimension {
+            column: Column {
+                human_name: "Status".to_string(),
+                pg_name: "status".to_string(),
+                data_type: DataType::Text,
+                format: ColumnFormat::default(),
+                stats: ColumnStats::default(),
+            },
+            transform: None,
+            alias: None,
+        };
+        let month = Dimension {
+            column: Column {
+                human_name: "Last Updated".to_string(),
+                pg_name: "last_updated".to_string(),
+                data_type: DataType::CalendarDate,
+                format: ColumnFormat::default(),
+                stats: ColumnStats::default(),
+            },
+            transform: Some(DimensionTransform::DateTrunc(DateTruncUnit::Month)),
+            alias: None,
+        };
+        let count = Measure {
+            type_: MeasureType::Count,
+            column: None,
+        };
+        let max_latitude = Measure {
+            type_: MeasureType::Max,
+            column: Some(Column {
+                human_name: "Latitude".to_string(),
+                pg_name: "latitude".to_string(),
+                data_type: DataType::Number,
+                format: ColumnFormat::default(),
+                stats: ColumnStats::default(),
+            }),
+        };
+        let measures = vec![count.clone(), max_latitude.clone()];
+
+        assert_eq!(build_window_measures(&measures, &[]), vec![]);
+
+        assert_eq!(
+            build_window_measures(&measures, &[status.clone(), month.clone()]),
+            vec![
+                WindowMeasure {
+                    function: WindowFunction::Rank,
+                    window: Window {
+                        partition_by: vec![status.clone()],
+                        order_by: vec![OrderBy {
+                            item: OrderByItem::Measure(count.clone()),
+                            direction: OrderByDirection::Desc,
+                        }],
+                        frame: None,
+                    },
+                },
+                WindowMeasure {
+                    function: WindowFunction::ShareOfTotal(count.clone()),
+                    window: Window {
+                        partition_by: vec![status.clone()],
+                        order_by: vec![],
+                        frame: None,
+                    },
+                },
+                WindowMeasure {
+                    function: WindowFunction::Sum(count),
+                    window: Window {
+                        partition_by: vec![status.clone()],
+                        order_by: vec![OrderBy {
+                            item: OrderByItem::Dimension(month),
+                            direction: OrderByDirection::Asc,
+                        }],
+                        frame: Some(WindowFrame {
+                            units: WindowFrameUnits::Rows,
+                            start: WindowFrameBound::UnboundedPreceding,
+                            end: WindowFrameBound::CurrentRow,
+                        }),
+                    },
+                },
+                // MAX() can't be added up, so we only rank on it
+                WindowMeasure {
+                    function: WindowFunction::Rank,
+                    window: Window {
+                        partition_by: vec![status],
+                        order_by: vec![OrderBy {
+                            item: OrderByItem::Measure(max_latitude),
+                            direction: OrderByDirection::Desc,
+                        }],
+                        frame: None,
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_emit_query_window_measures() {
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Facility/Service Notification History".to_string(),
+            row_count: None,
+            columns: vec![],
+        };
+        let month = Dimension {
+            column: Column {
+                human_name: "Last Updated".to_string(),
+                pg_name: "last_updated".to_string(),
+                data_type: DataType::CalendarDate,
+                format: ColumnFormat::default(),
+                stats: ColumnStats::default(),
+            },
+            transform: Some(DimensionTransform::DateTrunc(DateTruncUnit::Month)),
+            alias: None,
+        };
+        let sum_cost = Measure {
+            type_: MeasureType::Sum,
+            column: Some(Column {
+                human_name: "Estimated Cost".to_string(),
+                pg_name: "estimated_cost".to_string(),
+                data_type: DataType::Money,
+                format: ColumnFormat::default(),
+                stats: ColumnStats::default(),
+            }),
+        };
+
+        let mut query = Query::new(
+            dataset,
+            vec![sum_cost.clone()],
+            vec![month.clone()],
+            vec![],
+            None,
+            DefaultSyntax {},
+        );
+        query.window_measures = vec![
+            WindowMeasure {
+                function: WindowFunction::Rank,
+                window: Window {
+                    partition_by: vec![],
+                    order_by: vec![OrderBy {
+                        item: OrderByItem::Measure(sum_cost.clone()),
+                        direction: OrderByDirection::Desc,
+                    }],
+                    frame: None,
+                },
+            },
+            WindowMeasure {
+                function: WindowFunction::Sum(sum_cost.clone()),
+                window: Window {
+                    partition_by: vec![],
+                    order_by: vec![OrderBy {
+                        item: OrderByItem::Dimension(month),
+                        direction: OrderByDirection::Asc,
+                    }],
+                    frame: Some(WindowFrame {
+                        units: WindowFrameUnits::Rows,
+                        start: WindowFrameBound::UnboundedPreceding,
+                        end: WindowFrameBound::CurrentRow,
+                    }),
+                },
+            },
+            WindowMeasure {
+                function: WindowFunction::ShareOfTotal(sum_cost),
+                window: Window {
+                    partition_by: vec![],
+                    order_by: vec![],
+                    frame: None,
+                },
+            },
+        ];
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  date_trunc('month', last_updated) AS month_last_updated,
+  SUM(estimated_cost) AS sum_estimated_cost,
+  RANK() OVER (ORDER BY SUM(estimated_cost) DESC) AS rank,
+  SUM(SUM(estimated_cost)) OVER (ORDER BY date_trunc('month', last_updated) ASC ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) AS running_sum_estimated_cost,
+  SUM(estimated_cost) / SUM(SUM(estimated_cost)) OVER () AS share_of_sum_estimated_cost
+FROM "u7r4-acwa"
+GROUP BY
+  date_trunc('month', last_updated)"#
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_emit_query_filter() {
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Facility/Service Notification History".to_string(),
+            row_count: None,
+            columns: vec![],
+        };
+        let status = Column {
+            human_name: "Status".to_string(),
+            pg_name: "status".to_string(),
+            data_type: DataType::Text,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let latitude = Column {
+            human_name: "Latitude".to_string(),
+            pg_name: "latitude".to_string(),
+            data_type: DataType::Number,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let last_updated = Column {
+            human_name: "Last Updated".to_string(),
+            pg_name: "last_updated".to_string(),
+            data_type: DataType::CalendarDate,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+
+        let mut query = Query::new(
+            dataset,
+            vec![Measure {
+                type_: MeasureType::Count,
+                column: None,
+            }],
+            vec![],
+            vec![],
+            None,
+            DefaultSyntax {},
+        );
+        query.filter = Some(Filter::And(vec![
+            Filter::Between {
+                column: latitude.clone(),
+                low: Literal::Number("53.4".to_string()),
+                high: Literal::Number("53.7".to_string()),
+            },
+            Filter::Or(vec![
+                Filter::In {
+                    column: status.clone(),
+                    values: vec![
+                        Literal::Text("Open".to_string()),
+                        Literal::Text("Won't fix".to_string()),
+                    ],
+                },
+                Filter::Like {
+                    column: status.clone(),
+                    pattern: "Closed%".to_string(),
+                },
+            ]),
+            Filter::Comparison {
+                column: last_updated,
+                operator: ComparisonOperator::GtEq,
+                value: Literal::Date("2020-01-01".to_string()),
+            },
+            Filter::IsNull {
+                column: latitude,
+                negated: true,
+            },
+        ]));
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  COUNT(*) AS count
+FROM "u7r4-acwa"
+WHERE
+  latitude BETWEEN 53.4 AND 53.7
+  AND (status IN ('Open', 'Won''t fix') OR status LIKE 'Closed%')
+  AND last_updated >= '2020-01-01'
+  AND latitude IS NOT NULL"#
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_random_filter() {
+        let mut rng = rand::thread_rng();
+        let column = |pg_name: &str, data_type: DataType| Column {
+            human_name: pg_name.to_string(),
+            pg_name: pg_name.to_string(),
+            data_type,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+
+        for _ in 1..10 {
+            assert!(matches!(
+                random_filter(&mut rng, &column("cost", DataType::Money)),
+                Some(Filter::Comparison {
+                    value: Literal::Number(_),
+                    ..
+                }) | Some(Filter::Between {
+                    low: Literal::Number(_),
+                    ..
+                })
+            ));
+            assert!(matches!(
+                random_filter(&mut rng, &column("opened", DataType::CalendarDate)),
+                Some(Filter::Comparison {
+                    value: Literal::Date(_),
+                    ..
+                }) | Some(Filter::Between {
+                    low: Literal::Date(_),
+                    ..
+                })
+            ));
+            assert!(matches!(
+                random_filter(&mut rng, &column("is_open", DataType::Checkbox)),
+                Some(Filter::Comparison {
+                    operator: ComparisonOperator::Eq,
+                    value: Literal::Boolean(_),
+                    ..
+                })
+            ));
+            assert!(random_filter(&mut rng, &column("status", DataType::Text)).is_some());
+            assert!(random_filter(&mut rng, &column("location", DataType::Point)).is_none());
+            assert!(random_filter(
+                &mut rng,
+                &column(":@computed_region_7ccj_gre3", DataType::Number)
+            )
+            .is_none());
+        }
+    }
+
+    #[test]
+    fn test_random_filter_stats() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let column = |pg_name: &str, data_type: DataType, stats: ColumnStats| Column {
+            human_name: pg_name.to_string(),
+            pg_name: pg_name.to_string(),
+            data_type,
+            format: ColumnFormat::default(),
+            stats,
+        };
+        let status = column(
+            "status",
+            DataType::Text,
+            ColumnStats {
+                top_values: vec!["Open".to_string(), "Closed".to_string()],
+                ..Default::default()
+            },
+        );
+        let longitude = column(
+            "longitude",
+            DataType::Double,
+            ColumnStats {
+                smallest: Some("-114.3".to_string()),
+                largest: Some("-113.2".to_string()),
+                ..Default::default()
+            },
+        );
+        let latitude = column(
+            "latitude",
+            DataType::Double,
+            ColumnStats {
+                smallest: Some("53.39".to_string()),
+                largest: Some("53.71".to_string()),
+                ..Default::default()
+            },
+        );
+        let opened = column(
+            "opened",
+            DataType::CalendarDate,
+            ColumnStats {
+                smallest: Some("2015-03-01T00:00:00.000".to_string()),
+                largest: Some("2017-11-30T00:00:00.000".to_string()),
+                ..Default::default()
+            },
+        );
+        let empty = column(
+            "empty",
+            DataType::Text,
+            ColumnStats {
+                non_null_count: Some(0),
+                null_count: Some(100),
+                ..Default::default()
+            },
+        );
+
+        let is_top_value = |v: &Literal| {
+            [
+                Literal::Text("Open".to_string()),
+                Literal::Text("Closed".to_string()),
+            ]
+            .contains(v)
+        };
+        // Rounded to a precision that fits the range, so that narrow ranges don't collapse
+        let is_in_range = |v: &Literal, low: f64, high: f64, decimals: usize| match v {
+            Literal::Number(n) => {
+                (low..=high).contains(&n.parse::<f64>().unwrap())
+                    && n.split('.').nth(1).map_or(0, |d| d.len()) <= decimals
+            }
+            _ => false,
+        };
+        let is_in_years = |v: &Literal| match v {
+            Literal::Date(d) => ["2015", "2016", "2017"].contains(&&d[0..4]),
+            _ => false,
+        };
+
+        for _ in 1..50 {
+            match random_filter(&mut rng, &status).unwrap() {
+                Filter::Comparison { value, .. } => assert!(is_top_value(&value)),
+                Filter::In { values, .. } => assert!(values.iter().all(is_top_value)),
+                f => panic!("unexpected filter {f:?}"),
+            }
+            let is_longitude = |v: &Literal| is_in_range(v, -114.3, -113.2, 2);
+            match random_filter(&mut rng, &longitude).unwrap() {
+                Filter::Comparison { value, .. } => assert!(is_longitude(&value)),
+                Filter::Between { low, high, .. } => {
+                    assert!(is_longitude(&low) && is_longitude(&high))
+                }
+                f => panic!("unexpected filter {f:?}"),
+            }
+            let is_latitude = |v: &Literal| is_in_range(v, 53.39, 53.71, 3);
+            match random_filter(&mut rng, &latitude).unwrap() {
+                Filter::Comparison { value, .. } => assert!(is_latitude(&value)),
+                Filter::Between { low, high, .. } => {
+                    assert!(is_latitude(&low) && is_latitude(&high))
+                }
+                f => panic!("unexpected filter {f:?}"),
+            }
+            match random_filter(&mut rng, &opened).unwrap() {
+                Filter::Comparison { value, .. } => assert!(is_in_years(&value)),
+                Filter::Between { low, high, .. } => {
+                    assert!(is_in_years(&low) && is_in_years(&high))
+                }
+                f => panic!("unexpected filter {f:?}"),
+            }
+            assert!(random_filter(&mut rng, &empty).is_none());
+        }
+    }
+
+    #[test]
+    fn test_build_with_stats() {
+        let column = |pg_name: &str, data_type: DataType, stats: ColumnStats| Column {
+            human_name: pg_name.to_string(),
+            pg_name: pg_name.to_string(),
+            data_type,
+            format: ColumnFormat::default(),
+            stats,
+        };
+        let empty = column(
+            "empty",
+            DataType::Number,
+            ColumnStats {
+                non_null_count: Some(0),
+                ..Default::default()
+            },
+        );
+        let floors = column(
+            "floors",
+            DataType::Number,
+            ColumnStats {
+                cardinality: Some(12),
+                ..Default::default()
+            },
+        );
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Building Permits".to_string(),
+            row_count: None,
+            columns: vec![empty, floors.clone()],
+        };
+
+        // Nothing on the empty column
+        assert!(build_measures(&dataset)
+            .iter()
+            .all(|m| m.column.as_ref().is_none_or(|c| c.pg_name != "empty")));
+        assert!(build_dimensions(&dataset)
+            .iter()
+            .all(|d| d.column.pg_name != "empty"));
+
+        // Group on the number of floors directly
+        assert!(build_dimensions(&dataset).contains(&Dimension {
+            column: floors,
+            transform: None,
+            alias: None,
+        }));
+    }
+
+    #[test]
+    fn test_build_geospatial() {
+        let column = |name: &str, data_type: DataType, range: Option<(&str, &str)>| Column {
+            human_name: name.to_string(),
+            pg_name: name.to_lowercase(),
+            data_type,
+            format: ColumnFormat::default(),
+            stats: ColumnStats {
+                smallest: range.map(|r| r.0.to_string()),
+                largest: range.map(|r| r.1.to_string()),
+                ..Default::default()
+            },
+        };
+        let location = column("Location", DataType::Point, None);
+        let boundary = column("Boundary", DataType::Multipolygon, None);
+        let mut dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Building Permits".to_string(),
+            row_count: None,
+            columns: vec![location.clone(), boundary.clone()],
+        };
+
+        let geo_measures = |dataset: &Dataset| -> Vec<MeasureType> {
+            build_measures(dataset)
+                .into_iter()
+                .filter(|m| m.type_ != MeasureType::Count)
+                .map(|m| m.type_)
+                .collect()
+        };
+
+        // Without latitude and longitude columns, there's nothing to measure a distance from
+        assert_eq!(dataset_center(&dataset), None);
+        assert_eq!(
+            geo_measures(&dataset),
+            vec![
+                MeasureType::Extent,
+                MeasureType::Centroid,
+                MeasureType::Extent,
+                MeasureType::Centroid
+            ]
+        );
+
+        dataset.columns.extend([
+            column("Latitude", DataType::Number, Some(("53", "54"))),
+            column("Longitude", DataType::Number, Some(("-114", "-113"))),
+        ]);
+        assert_eq!(dataset_center(&dataset), Some((53.5, -113.5)));
+        assert!(build_measures(&dataset).contains(&Measure {
+            type_: MeasureType::CountWithinRadius {
+                latitude: "53.5".to_string(),
+                longitude: "-113.5".to_string(),
+                meters: "1000".to_string(),
+            },
+            column: Some(location.clone()),
+        }));
+        // Only around points
+        assert!(!build_measures(&dataset)
+            .iter()
+            .any(|m| matches!(m.type_, MeasureType::CountWithinRadius { .. })
+                && m.column == Some(boundary.clone())));
+
+        // Points go on a grid, areas don't
+        let grids: Vec<Dimension> = build_dimensions(&dataset)
+            .into_iter()
+            .filter(|d| matches!(d.transform, Some(DimensionTransform::SnapToGrid { .. })))
+            .collect();
+        assert_eq!(grids.len(), 2);
+        assert!(grids.iter().all(|d| d.column == location));
+
+        // Legacy location columns aren't plain geometries
+        dataset.columns = vec![column("Address", DataType::Location, None)];
+        assert_eq!(geo_measures(&dataset), vec![]);
+        assert!(build_dimensions(&dataset)
+            .iter()
+            .all(|d| !matches!(d.transform, Some(DimensionTransform::SnapToGrid { .. }))));
+    }
+
+    #[test]
+    fn test_emit_query_geospatial() {
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Building Permits".to_string(),
+            row_count: None,
+            columns: vec![],
+        };
+        let location = Column {
+            human_name: "Location".to_string(),
+            pg_name: "location".to_string(),
+            data_type: DataType::Point,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let measure = |type_: MeasureType| Measure {
+            type_,
+            column: Some(location.clone()),
+        };
+        let nearby = measure(MeasureType::CountWithinRadius {
+            latitude: "53.5".to_string(),
+            longitude: "-113.5".to_string(),
+            meters: "1000".to_string(),
+        });
+        let grid = Dimension {
+            column: location.clone(),
+            transform: Some(DimensionTransform::SnapToGrid {
+                size: "0.01".to_string(),
+            }),
+            alias: None,
+        };
+
+        let mut query = Query::new(
+            dataset,
+            vec![
+                measure(MeasureType::Extent),
+                measure(MeasureType::Centroid),
+                nearby.clone(),
+            ],
+            vec![grid.clone()],
+            vec![OrderBy {
+                item: OrderByItem::Measure(nearby),
+                direction: OrderByDirection::Desc,
+            }],
+            Some(100),
+            DefaultSyntax {},
+        );
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  ST_SnapToGrid(location, 0.01) AS grid_location,
+  ST_Extent(location) AS extent_location,
+  ST_Centroid(ST_Collect(location)) AS centroid_location,
+  SUM(CASE WHEN ST_DWithin(location::geography, ST_MakePoint(-113.5, 53.5)::geography, 1000) THEN 1 ELSE 0 END) AS count_within_1000m_location
+FROM "u7r4-acwa"
+GROUP BY
+  ST_SnapToGrid(location, 0.01)
+ORDER BY
+  SUM(CASE WHEN ST_DWithin(location::geography, ST_MakePoint(-113.5, 53.5)::geography, 1000) THEN 1 ELSE 0 END) DESC
+LIMIT 100"#
+                .to_string()
+        );
+        assert_eq!(query.validate(), vec![]);
+
+        // Geometries don't have an order
+        let by_grid = OrderBy {
+            item: OrderByItem::Dimension(grid),
+            direction: OrderByDirection::Asc,
+        };
+        query.order_by = vec![by_grid.clone()];
+        assert_eq!(
+            query.validate(),
+            vec![Diagnostic::UnorderableOrderBy(by_grid)]
+        );
+    }
+
+    #[test]
+    fn test_emit_query_having() {
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Facility/Service Notification History".to_string(),
+            row_count: None,
+            columns: vec![],
+        };
+        let count = Measure {
+            type_: MeasureType::Count,
+            column: None,
+        };
+        let avg_latitude = Measure {
+            type_: MeasureType::Average,
+            column: Some(Column {
+                human_name: "Latitude".to_string(),
+                pg_name: "latitude".to_string(),
+                data_type: DataType::Number,
+                format: ColumnFormat::default(),
+                stats: ColumnStats::default(),
+            }),
+        };
+
+        let mut query = Query::new(
+            dataset,
+            vec![count.clone(), avg_latitude.clone()],
+            vec![Dimension {
+                column: Column {
+                    human_name: "Status".to_string(),
+                    pg_name: "status".to_string(),
+                    data_type: DataType::Text,
+                    format: ColumnFormat::default(),
+                    stats: ColumnStats::default(),
                 },
-                Dimension {
-                    column: Column {
-                        human_name: "Location (zip)".to_string(),
-                        pg_name: "location_zip".to_string(),
-                        data_type: DataType::Text
+                transform: None,
+                alias: None,
+            }],
+            vec![],
+            None,
+            DefaultSyntax {},
+        );
+        query.having = vec![
+            HavingCondition::Comparison {
+                measure: count,
+                operator: ComparisonOperator::Gt,
+                value: Literal::Number("10".to_string()),
+            },
+            HavingCondition::Between {
+                measure: avg_latitude,
+                low: Literal::Number("53".to_string()),
+                high: Literal::Number("54".to_string()),
+            },
+        ];
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT
+  status,
+  COUNT(*) AS count,
+  AVG(latitude) AS avg_latitude
+FROM "u7r4-acwa"
+GROUP BY
+  status
+HAVING
+  COUNT(*) > 10
+  AND AVG(latitude) BETWEEN 53 AND 54"#
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_random_having() {
+        let mut rng = rand::thread_rng();
+        let measure = |type_: MeasureType, data_type: DataType| Measure {
+            type_,
+            column: Some(Column {
+                human_name: "Some Column".to_string(),
+                pg_name: "some_column".to_string(),
+                data_type,
+                format: ColumnFormat::default(),
+                stats: ColumnStats::default(),
+            }),
+        };
+
+        for _ in 1..10 {
+            assert!(matches!(
+                random_having(
+                    &mut rng,
+                    &Measure {
+                        type_: MeasureType::Count,
+                        column: None
                     }
-                }
-            ]
+                ),
+                Some(HavingCondition::Comparison {
+                    operator: ComparisonOperator::Gt,
+                    ..
+                })
+            ));
+            assert!(random_having(&mut rng, &measure(MeasureType::Sum, DataType::Money)).is_some());
+            assert!(matches!(
+                random_having(&mut rng, &measure(MeasureType::Max, DataType::CalendarDate)),
+                Some(HavingCondition::Comparison {
+                    value: Literal::Date(_),
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_random_having_stats() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let measure =
+            |type_: MeasureType, data_type: DataType, smallest: &str, largest: &str| Measure {
+                type_,
+                column: Some(Column {
+                    human_name: "Some Column".to_string(),
+                    pg_name: "some_column".to_string(),
+                    data_type,
+                    format: ColumnFormat::default(),
+                    stats: ColumnStats {
+                        smallest: Some(smallest.to_string()),
+                        largest: Some(largest.to_string()),
+                        ..Default::default()
+                    },
+                }),
+            };
+        let values = |condition: HavingCondition| match condition {
+            HavingCondition::Comparison { value, .. } => vec![value],
+            HavingCondition::Between { low, high, .. } => vec![low, high],
+        };
+        let number = |v: &Literal| match v {
+            Literal::Number(n) => n.parse::<f64>().unwrap(),
+            v => panic!("unexpected value {v:?}"),
+        };
+
+        let temperature = measure(MeasureType::Average, DataType::Double, "-40.5", "-2.25");
+        let refunds = measure(MeasureType::Sum, DataType::Money, "-500", "-1");
+        let opened = measure(
+            MeasureType::Min,
+            DataType::CalendarDate,
+            "2015-03-01T00:00:00.000",
+            "2017-11-30T00:00:00.000",
         );
+
+        for _ in 1..50 {
+            for value in values(random_having(&mut rng, &temperature).unwrap()) {
+                assert!((-40.5..=-2.25).contains(&number(&value)), "{value:?}");
+            }
+            for value in values(random_having(&mut rng, &refunds).unwrap()) {
+                assert!(number(&value) <= 0.0, "{value:?}");
+            }
+            match &values(random_having(&mut rng, &opened).unwrap())[..] {
+                [Literal::Date(d)] => assert!(["2015", "2016", "2017"].contains(&&d[0..4])),
+                v => panic!("unexpected values {v:?}"),
+            }
+        }
     }
 
     #[test]
-    fn test_emit_query() {
+    fn test_emit_top_n_per_group_query() {
         let dataset = Dataset {
             domain: "data.edmonton.ca".to_string(),
             socrata_id: "u7r4-acwa".to_string(),
             name: "Facility/Service Notification History".to_string(),
-            // Doesn't matter in this context
+            row_count: None,
             columns: vec![],
         };
+        let dimension = |human_name: &str, pg_name: &str| Dimension {
+            column: Column {
+                human_name: human_name.to_string(),
+                pg_name: pg_name.to_string(),
+                data_type: DataType::Text,
+                format: ColumnFormat::default(),
+                stats: ColumnStats::default(),
+            },
+            transform: None,
+            alias: None,
+        };
+
+        let query = top_n_per_group_query(
+            &dataset,
+            &Measure {
+                type_: MeasureType::Count,
+                column: None,
+            },
+            &dimension("Facility/Service Type", "facility_service_type"),
+            &dimension("Reason", "reason"),
+            3,
+            DefaultSyntax {},
+        );
+
+        assert_eq!(
+            query.to_sql(),
+            r#"WITH ranked AS (
+  SELECT
+    facility_service_type,
+    reason,
+    COUNT(*) AS count,
+    ROW_NUMBER() OVER (PARTITION BY facility_service_type ORDER BY COUNT(*) DESC) AS row_number
+  FROM "u7r4-acwa"
+  GROUP BY
+    facility_service_type,
+    reason
+)
+SELECT
+  facility_service_type,
+  reason,
+  count,
+  row_number
+FROM ranked
+WHERE
+  row_number <= 3
+ORDER BY
+  facility_service_type ASC,
+  row_number ASC
+LIMIT 100"#
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_emit_query_aliases() {
+        let column = |pg_name: &str, data_type: DataType| Column {
+            human_name: pg_name.to_string(),
+            pg_name: pg_name.to_string(),
+            data_type,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let status = column("status", DataType::Text);
+        let region = column(":@computed_region_7ccj_gre3", DataType::Number);
+        let region_sum = column("sum_computed_region_7ccj_gre3", DataType::Number);
+
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Facility/Service Notification History".to_string(),
+            row_count: None,
+            columns: vec![status.clone(), region.clone(), region_sum.clone()],
+        };
 
         let query = Query::new(
             dataset,
             vec![
                 Measure {
-                    type_: MeasureType::Count,
-                    column: None,
-                },
-                Measure {
-                    type_: MeasureType::Min,
-                    column: Some(Column {
-                        human_name: "Notification Start Date".to_string(),
-                        pg_name: "notification_start_date".to_string(),
-                        data_type: DataType::CalendarDate,
-                    }),
+                    type_: MeasureType::Sum,
+                    column: Some(region.clone()),
                 },
                 Measure {
                     type_: MeasureType::Max,
-                    column: Some(Column {
-                        human_name: "Notification End Date".to_string(),
-                        pg_name: "notification_end_date".to_string(),
-                        data_type: DataType::CalendarDate,
-                    }),
-                },
-                Measure {
-                    type_: MeasureType::Average,
-                    column: Some(Column {
-                        human_name: "Latitude".to_string(),
-                        pg_name: "latitude".to_string(),
-                        data_type: DataType::Number,
-                    }),
-                },
-                Measure {
-                    type_: MeasureType::Average,
-                    column: Some(Column {
-                        human_name: "Longitude".to_string(),
-                        pg_name: "longitude".to_string(),
-                        data_type: DataType::Number,
-                    }),
+                    column: Some(region_sum),
                 },
             ],
             vec![
                 Dimension {
-                    column: Column {
-                        human_name: "Facility/Service Type".to_string(),
-                        pg_name: "facility_service_type".to_string(),
-                        data_type: DataType::Text,
-                    },
+                    column: status,
+                    transform: None,
+                    alias: Some("State".to_string()),
                 },
                 Dimension {
-                    column: Column {
-                        human_name: "Status".to_string(),
-                        pg_name: "status".to_string(),
-                        data_type: DataType::Text,
-                    },
-                },
-            ],
-            vec![
-                OrderBy {
-                    item: OrderByItem::Dimension(Dimension {
-                        column: Column {
-                            human_name: "Facility/Service Type".to_string(),
-                            pg_name: "facility_service_type".to_string(),
-                            data_type: DataType::Text,
-                        },
-                    }),
-
-                    direction: OrderByDirection::Asc,
-                },
-                OrderBy {
-                    item: OrderByItem::Measure(Measure {
-                        type_: MeasureType::Min,
-                        column: Some(Column {
-                            human_name: "Notification Start Date".to_string(),
-                            pg_name: "notification_start_date".to_string(),
-                            data_type: DataType::CalendarDate,
-                        }),
-                    }),
-                    direction: OrderByDirection::Desc,
+                    column: region,
+                    transform: Some(DimensionTransform::Bin(Binning::Log)),
+                    alias: None,
                 },
             ],
-            Some(100),
+            vec![],
+            None,
             DefaultSyntax {},
         );
 
         assert_eq!(
             query.to_sql(),
             r#"SELECT
-  facility_service_type,
-  status,
-  COUNT(*),
-  MIN(notification_start_date) AS min_notification_start_date,
-  MAX(notification_end_date) AS max_notification_end_date,
-  AVG(latitude) AS avg_latitude,
-  AVG(longitude) AS avg_longitude
-FROM u7r4-acwa
+  status AS state,
+  floor(log(greatest(:@computed_region_7ccj_gre3, 1))) AS magnitude_computed_region_7ccj_gre3,
+  SUM(:@computed_region_7ccj_gre3) AS sum_computed_region_7ccj_gre3,
+  MAX(sum_computed_region_7ccj_gre3) AS max_sum_computed_region_7ccj_gre3
+FROM "u7r4-acwa"
 GROUP BY
-  facility_service_type,
-  status
-ORDER BY
-  facility_service_type ASC,
-  MIN(notification_start_date) DESC
-LIMIT 100"#
+  status,
+  floor(log(greatest(:@computed_region_7ccj_gre3, 1)))"#
                 .to_string()
         );
     }
 
+    #[test]
+    fn test_validate_query() {
+        let column = |pg_name: &str, data_type: DataType| Column {
+            human_name: pg_name.to_string(),
+            pg_name: pg_name.to_string(),
+            data_type,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let status = column("status", DataType::Text);
+        let cost = column("cost", DataType::Number);
+        let weird = column(":@computed_region_7ccj_gre3", DataType::Number);
+
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Facility/Service Notification History".to_string(),
+            row_count: None,
+            columns: vec![status.clone(), cost.clone(), weird.clone()],
+        };
+        let dimension = |column: &Column| Dimension {
+            column: column.clone(),
+            transform: None,
+            alias: None,
+        };
+        let measure = |type_: MeasureType, column: &Column| Measure {
+            type_,
+            column: Some(column.clone()),
+        };
+        let rank = |measure: Measure| WindowMeasure {
+            function: WindowFunction::Rank,
+            window: Window {
+                partition_by: vec![],
+                order_by: vec![OrderBy {
+                    item: OrderByItem::Measure(measure),
+                    direction: OrderByDirection::Desc,
+                }],
+                frame: None,
+            },
+        };
+
+        let mut query = Query::new(
+            dataset.clone(),
+            vec![measure(MeasureType::Sum, &cost)],
+            vec![dimension(&status)],
+            vec![OrderBy {
+                item: OrderByItem::Measure(measure(MeasureType::Sum, &cost)),
+                direction: OrderByDirection::Desc,
+            }],
+            Some(100),
+            DefaultSyntax {},
+        );
+        assert_eq!(query.validate(), vec![]);
+
+        // Two RANK() columns get different aliases
+        query.window_measures = vec![
+            rank(measure(MeasureType::Sum, &cost)),
+            rank(Measure {
+                type_: MeasureType::Count,
+                column: None,
+            }),
+        ];
+        assert_eq!(query.validate(), vec![]);
+        assert_eq!(
+            query.output_aliases(),
+            vec![
+                "status".to_string(),
+                "sum_cost".to_string(),
+                "rank".to_string(),
+                "rank_2".to_string()
+            ]
+        );
+        query.window_measures = vec![];
+
+        // Ordering by something that we're not grouping by
+        let order_by_cost = OrderBy {
+            item: OrderByItem::Dimension(dimension(&cost)),
+            direction: OrderByDirection::Asc,
+        };
+        query.order_by = vec![order_by_cost.clone()];
+        assert_eq!(
+            query.validate(),
+            vec![Diagnostic::OrderByNotSelectable(order_by_cost)]
+        );
+        query.order_by = vec![];
+
+        // Aggregating text and truncating numbers to months
+        let month_of_cost = Dimension {
+            column: cost.clone(),
+            transform: Some(DimensionTransform::DateTrunc(DateTruncUnit::Month)),
+            alias: None,
+        };
+        query.measures = vec![measure(MeasureType::Average, &status)];
+        query.dimensions = vec![month_of_cost.clone()];
+        assert_eq!(
+            query.validate(),
+            vec![
+                Diagnostic::IncompatibleMeasure(measure(MeasureType::Average, &status)),
+                Diagnostic::IncompatibleDimension(month_of_cost)
+            ]
+        );
+
+        // Columns that DefaultSyntax doesn't quote
+        query.measures = vec![measure(MeasureType::Sum, &weird)];
+        query.dimensions = vec![dimension(&status)];
+        assert_eq!(
+            query.validate(),
+            vec![Diagnostic::UnrepresentableIdentifier(
+                ":@computed_region_7ccj_gre3".to_string()
+            )]
+        );
+
+        // Passing the same column through twice
+        let mut query = Query::new(dataset, vec![], vec![], vec![], None, DefaultSyntax {});
+        query.columns = vec![status.clone(), status];
+        assert_eq!(
+            query.validate(),
+            vec![Diagnostic::DuplicateAlias("status".to_string())]
+        );
+
+        // Nothing to select
+        query.columns = vec![];
+        assert_eq!(query.validate(), vec![Diagnostic::EmptySelect]);
+    }
+
     #[test]
     fn test_random_query() {
-        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        d.push("resources/test/socrata_resource.json");
-        let resource_json = fs::read_to_string(d).unwrap();
-        let resource: RawDatasetResource = serde_json::from_str(&resource_json).unwrap();
-        let dataset = parse_dataset("data.edmonton.ca", &resource);
+        let dataset = edmonton_dataset();
 
         let measures = build_measures(&dataset);
         let dimensions = build_dimensions(&dataset);
+        let mut rng = rand::thread_rng();
 
         // Smoke test the random query generation
         for _ in 1..10 {
             let query = random_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &default_params(),
+                DefaultSyntax {},
+            );
+            assert_eq!(query.validate(), vec![]);
+
+            let query = random_top_n_per_group_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &DefaultScorer {},
+                DefaultSyntax {},
+            );
+            if let Some(query) = query {
+                assert_eq!(query.validate(), vec![]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_query_seeded() {
+        let dataset = edmonton_dataset();
+
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        let generate = |seed: u64| {
+            random_query(
+                &mut StdRng::seed_from_u64(seed),
+                &dataset,
+                &measures,
+                &dimensions,
+                &default_params(),
+                DefaultSyntax {},
+            )
+            .to_sql()
+        };
+
+        // The same seed always gives us the same query
+        for seed in 0..10 {
+            assert_eq!(generate(seed), generate(seed));
+        }
+        assert_ne!(
+            (0..10).map(generate).collect::<Vec<String>>(),
+            (10..20).map(generate).collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn test_random_query_distinct_dimension_columns() {
+        let dataset = edmonton_dataset();
+
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        for seed in 0..100 {
+            let query = random_query(
+                &mut StdRng::seed_from_u64(seed),
                 &dataset,
                 &measures,
                 &dimensions,
-                1..3,
-                1..4,
-                0..3,
+                &RandomQueryParams {
+                    no_dimensions: 2..5,
+                    ..default_params()
+                },
                 DefaultSyntax {},
             );
-            let _ = query.to_sql();
+            let columns: Vec<&str> = query
+                .dimensions
+                .iter()
+                .map(|d| d.column.pg_name.as_str())
+                .collect();
+            assert_eq!(
+                columns.iter().unique().count(),
+                columns.len(),
+                "{:?}",
+                columns
+            );
+
+            let top_n = random_top_n_per_group_query(
+                &mut StdRng::seed_from_u64(seed),
+                &dataset,
+                &measures,
+                &dimensions,
+                &DefaultScorer {},
+                DefaultSyntax {},
+            )
+            .unwrap();
+            let ranked = &top_n.ctes[0].query;
+            assert_ne!(ranked.dimensions[0].column, ranked.dimensions[1].column);
         }
     }
 }
@@ -0,0 +1,295 @@
+use crate::query_gen::query::{
+    is_computed_region, Dimension, DimensionTransform, Measure, MeasureType, LOW_CARDINALITY,
+};
+use crate::query_gen::socrata::{Column, DataType};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// Decides how likely the generator is to pick a column. Scores are relative weights:
+/// 0 means never, 1 is an unremarkable column, higher is more interesting.
+pub trait ColumnScorer {
+    /// How interesting a column is, regardless of how it's used
+    fn score_column(&self, column: &Column) -> f64;
+    /// How interesting it is to group on a dimension
+    fn score_dimension(&self, dimension: &Dimension) -> f64 {
+        self.score_column(&dimension.column)
+    }
+    /// How interesting an aggregate is
+    fn score_measure(&self, measure: &Measure) -> f64 {
+        measure
+            .column
+            .as_ref()
+            .map(|c| self.score_column(c))
+            .unwrap_or(1.0)
+    }
+}
+
+/// Every column is as likely as any other
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct UniformScorer {}
+
+impl ColumnScorer for UniformScorer {
+    fn score_column(&self, _column: &Column) -> f64 {
+        1.0
+    }
+}
+
+/// Prefers categories (status, type, district...) and time trends, and stays away from
+/// identifiers and free text, which are usually different for every row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultScorer {}
+
+// Words in a column name that make it a category worth grouping on
+const CATEGORY_WORDS: &[&str] = &[
+    "agency",
+    "borough",
+    "category",
+    "class",
+    "department",
+    "district",
+    "gender",
+    "kind",
+    "neighborhood",
+    "neighbourhood",
+    "priority",
+    "sector",
+    "status",
+    "type",
+    "ward",
+    "zone",
+];
+
+// Words in a column name that make it (most likely) long free text
+const FREE_TEXT_WORDS: &[&str] = &[
+    "comment",
+    "comments",
+    "description",
+    "details",
+    "info",
+    "information",
+    "narrative",
+    "note",
+    "notes",
+    "remarks",
+    "summary",
+];
+
+fn name_words(column: &Column) -> Vec<String> {
+    // Use both names, since either of them can be uninformative
+    format!("{:} {:}", column.human_name, column.pg_name)
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_string())
+        .collect()
+}
+
+fn is_identifier(column: &Column) -> bool {
+    let human_words: Vec<String> = column
+        .human_name
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_string())
+        .collect();
+
+    name_words(column)
+        .iter()
+        .any(|w| ["id", "uuid", "guid", "objectid", "fid"].contains(&w.as_str()))
+        // e.g. "Permit Number" (but not "Number of Units")
+        || human_words
+            .last()
+            .is_some_and(|w| ["number", "no", "num"].contains(&w.as_str()))
+}
+
+fn is_free_text(column: &Column) -> bool {
+    match column.data_type {
+        DataType::Url => true,
+        DataType::Text => name_words(column)
+            .iter()
+            .any(|w| FREE_TEXT_WORDS.contains(&w.as_str())),
+        _ => false,
+    }
+}
+
+pub(crate) fn is_category(column: &Column) -> bool {
+    column.data_type == DataType::Checkbox
+        || name_words(column)
+            .iter()
+            .any(|w| CATEGORY_WORDS.contains(&w.as_str()))
+}
+
+impl ColumnScorer for DefaultScorer {
+    fn score_column(&self, column: &Column) -> f64 {
+        if column.stats.is_all_null() {
+            0.0
+        } else if is_identifier(column) {
+            0.1
+        } else if is_free_text(column) {
+            0.2
+        } else if is_computed_region(column) {
+            // Socrata-generated geo regions: only meaningful with a lookup table
+            0.3
+        } else if is_category(column) {
+            2.0
+        } else {
+            1.0
+        }
+    }
+
+    fn score_dimension(&self, dimension: &Dimension) -> f64 {
+        let column = &dimension.column;
+        let score = self.score_column(column);
+
+        match &dimension.transform {
+            None => match (column.stats.cardinality, column.stats.row_count()) {
+                // Only one group, nothing to compare
+                (Some(cardinality), _) if cardinality <= 1 => 0.0,
+                // A different value for (almost) every row
+                (Some(cardinality), Some(rows)) if cardinality * 10 >= rows * 9 => score.min(0.1),
+                (Some(cardinality), _) if cardinality <= LOW_CARDINALITY => score.max(2.0),
+                _ => score,
+            },
+            // Trends over time are always nice
+            Some(DimensionTransform::DateTrunc(_)) | Some(DimensionTransform::Extract(_)) => {
+                score.max(1.5)
+            }
+            // Bucketing numbers only makes sense when they're quantities
+            Some(DimensionTransform::Bin(_)) if is_identifier(&dimension.column) => 0.0,
+            _ => score,
+        }
+    }
+
+    fn score_measure(&self, measure: &Measure) -> f64 {
+        let column = match &measure.column {
+            Some(c) => c,
+            None => return 1.0,
+        };
+
+        if is_identifier(column) {
+            match measure.type_ {
+                // The number of distinct IDs is fine, but summing them up isn't
+                MeasureType::CountDistinct | MeasureType::CountNonNull => 0.5,
+                _ => 0.0,
+            }
+        } else if is_free_text(column) {
+            0.2
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Pick up to `amount` distinct items, with probabilities proportional to their weights.
+/// Items with a weight of 0 never get picked.
+pub(crate) fn choose_weighted<R: Rng + ?Sized, T: Clone>(
+    rng: &mut R,
+    items: &[T],
+    amount: usize,
+    weight: impl Fn(&T) -> f64,
+) -> Vec<T> {
+    let candidates: Vec<(T, f64)> = items
+        .iter()
+        .map(|i| (i.clone(), weight(i)))
+        .filter(|(_, w)| w.is_finite() && *w > 0.0)
+        .collect();
+
+    candidates
+        .choose_multiple_weighted(rng, amount, |(_, w)| *w)
+        // Can't happen, since we filtered out the invalid weights
+        .map(|chosen| chosen.map(|(i, _)| i.clone()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, Dimension, Measure, MeasureType,
+    };
+    use crate::query_gen::scoring::{choose_weighted, ColumnScorer, DefaultScorer};
+    use crate::query_gen::test_util::edmonton_dataset;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_default_scorer() {
+        let dataset = edmonton_dataset();
+        let scorer = DefaultScorer {};
+        let score = |pg_name: &str| {
+            scorer.score_column(
+                dataset
+                    .columns
+                    .iter()
+                    .find(|c| c.pg_name == pg_name)
+                    .unwrap(),
+            )
+        };
+
+        assert_eq!(score("row_id"), 0.1);
+        assert_eq!(score("facility_service_id"), 0.1);
+        assert_eq!(score("additional_information"), 0.2);
+        assert_eq!(score("edmonton_ca_info_page"), 0.2);
+        assert_eq!(score("status"), 2.0);
+        assert_eq!(score("facility_service_type"), 2.0);
+        assert_eq!(score("reason"), 1.0);
+
+        let notification_id = dataset
+            .columns
+            .iter()
+            .find(|c| c.pg_name == "notification_id")
+            .unwrap();
+        let measure = |type_| Measure {
+            type_,
+            column: Some(notification_id.clone()),
+        };
+        assert_eq!(scorer.score_measure(&measure(MeasureType::Sum)), 0.0);
+        assert_eq!(
+            scorer.score_measure(&measure(MeasureType::CountDistinct)),
+            0.5
+        );
+        assert_eq!(
+            scorer.score_measure(&Measure {
+                type_: MeasureType::Count,
+                column: None
+            }),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_choose_weighted() {
+        let dataset = edmonton_dataset();
+        let dimensions = build_dimensions(&dataset);
+        let measures = build_measures(&dataset);
+        let scorer = DefaultScorer {};
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut status_count = 0;
+        let mut row_id_count = 0;
+        for _ in 0..1000 {
+            let chosen: Vec<Dimension> =
+                choose_weighted(&mut rng, &dimensions, 1, |d| scorer.score_dimension(d));
+            match chosen[0].column.pg_name.as_str() {
+                "status" => status_count += 1,
+                "row_id" => row_id_count += 1,
+                _ => {}
+            }
+
+            // Never sum up IDs
+            let chosen: Vec<Measure> =
+                choose_weighted(&mut rng, &measures, 3, |m| scorer.score_measure(m));
+            assert!(!chosen.iter().any(|m| m.type_ == MeasureType::Sum
+                && m.column.as_ref().unwrap().pg_name == "notification_id"));
+        }
+
+        assert!(status_count > row_id_count * 5);
+
+        // Can't pick more than there is
+        assert_eq!(
+            choose_weighted(&mut rng, &dimensions[..2], 5, |_| 1.0).len(),
+            2
+        );
+        assert_eq!(choose_weighted(&mut rng, &dimensions, 5, |_| 0.0).len(), 0);
+    }
+}
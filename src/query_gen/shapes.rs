@@ -0,0 +1,548 @@
+use crate::query_gen::query::{
+    is_computed_region, is_numeric, is_orderable, is_temporal, Dimension, DimensionTransform,
+    Measure, MeasureType, OrderBy, OrderByDirection, OrderByItem, Query, Syntax, LOW_CARDINALITY,
+};
+use crate::query_gen::scoring::{choose_weighted, is_category, ColumnScorer};
+use crate::query_gen::socrata::{Column, DataType, Dataset};
+use rand::seq::SliceRandom;
+use rand::RngCore;
+
+/// What a shape needs a dataset column for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnRole {
+    // A date or a timestamp, to follow something over time
+    Time,
+    // Something with a few distinct values to group on, e.g. a status or a type
+    Category,
+    // A number to aggregate or to bucket
+    Quantity,
+    // Anything at all
+    Any,
+}
+
+impl ColumnRole {
+    pub fn accepts(&self, column: &Column) -> bool {
+        if column.stats.is_all_null() || is_computed_region(column) {
+            return false;
+        }
+
+        match self {
+            ColumnRole::Time => is_temporal(&column.data_type),
+            ColumnRole::Category => match column.stats.cardinality {
+                // More than one group, but few enough to compare them
+                Some(c) => {
+                    (matches!(column.data_type, DataType::Text | DataType::Checkbox)
+                        || is_numeric(&column.data_type))
+                        && c > 1
+                        && c <= LOW_CARDINALITY
+                }
+                // Without the stats, go by the name (e.g. a status or a type)
+                None => {
+                    matches!(column.data_type, DataType::Text | DataType::Checkbox)
+                        && is_category(column)
+                }
+            },
+            ColumnRole::Quantity => is_numeric(&column.data_type),
+            ColumnRole::Any => true,
+        }
+    }
+}
+
+/// Whether every role can be given a different column of the dataset
+pub fn can_assign_roles(dataset: &Dataset, roles: &[ColumnRole]) -> bool {
+    fn assign(columns: &[&Column], roles: &[ColumnRole], used: &mut Vec<usize>) -> bool {
+        let (role, rest) = match roles.split_first() {
+            Some(r) => r,
+            None => return true,
+        };
+
+        for (i, column) in columns.iter().enumerate() {
+            if used.contains(&i) || !role.accepts(column) {
+                continue;
+            }
+            used.push(i);
+            if assign(columns, rest, used) {
+                return true;
+            }
+            used.pop();
+        }
+        false
+    }
+
+    let columns: Vec<&Column> = dataset.columns.iter().collect();
+    assign(&columns, roles, &mut vec![])
+}
+
+/// Everything a shape can build its query out of, usually the output of `build_measures`
+/// and `build_dimensions`
+pub struct ShapeContext<'a> {
+    pub dataset: &'a Dataset,
+    pub measures: &'a [Measure],
+    pub dimensions: &'a [Dimension],
+    pub scorer: &'a dyn ColumnScorer,
+}
+
+/// A kind of query that makes sense on its own, e.g. a trend over time or a histogram
+pub trait QueryShape<T: Syntax + Clone> {
+    #[allow(dead_code)]
+    fn name(&self) -> &'static str;
+    /// Columns the shape needs, each one a different column of the dataset
+    fn roles(&self) -> Vec<ColumnRole>;
+    /// Build a random query of this shape. Can still give up (e.g. if the syntax doesn't
+    /// support the dimension it wants), even if the dataset has all the roles.
+    fn fill(&self, rng: &mut dyn RngCore, context: &ShapeContext, syntax: T) -> Option<Query<T>>;
+
+    fn can_fill(&self, dataset: &Dataset) -> bool {
+        can_assign_roles(dataset, &self.roles())
+    }
+}
+
+fn count() -> Measure {
+    Measure {
+        type_: MeasureType::Count,
+        column: None,
+    }
+}
+
+fn choose_dimension(
+    rng: &mut dyn RngCore,
+    context: &ShapeContext,
+    role: ColumnRole,
+    filter: impl Fn(&Dimension) -> bool,
+) -> Option<Dimension> {
+    let candidates: Vec<Dimension> = context
+        .dimensions
+        .iter()
+        .filter(|d| role.accepts(&d.column) && filter(d))
+        .cloned()
+        .collect();
+    choose_weighted(rng, &candidates, 1, |d| context.scorer.score_dimension(d)).pop()
+}
+
+/// Some aggregate over time, newest first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrendShape {}
+
+impl<T: Syntax + Clone> QueryShape<T> for TrendShape {
+    fn name(&self) -> &'static str {
+        "trend over time"
+    }
+
+    fn roles(&self) -> Vec<ColumnRole> {
+        vec![ColumnRole::Time]
+    }
+
+    fn fill(&self, rng: &mut dyn RngCore, context: &ShapeContext, syntax: T) -> Option<Query<T>> {
+        let period = choose_dimension(rng, context, ColumnRole::Time, |d| {
+            matches!(d.transform, Some(DimensionTransform::DateTrunc(_)))
+        })?;
+
+        // Count the records and maybe follow some quantity too
+        let quantities: Vec<Measure> = context
+            .measures
+            .iter()
+            .filter(|m| {
+                m.column
+                    .as_ref()
+                    .is_some_and(|c| ColumnRole::Quantity.accepts(c))
+            })
+            .cloned()
+            .collect();
+        let mut measures = vec![count()];
+        measures.extend(choose_weighted(rng, &quantities, 1, |m| {
+            context.scorer.score_measure(m)
+        }));
+
+        Some(Query::new(
+            context.dataset.clone(),
+            measures,
+            vec![period.clone()],
+            vec![OrderBy {
+                item: OrderByItem::Dimension(period),
+                direction: OrderByDirection::Desc,
+            }],
+            Some(100),
+            syntax,
+        ))
+    }
+}
+
+/// The few categories with the highest (or lowest) value of some aggregate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopNShape {
+    pub n: usize,
+}
+
+impl<T: Syntax + Clone> QueryShape<T> for TopNShape {
+    fn name(&self) -> &'static str {
+        "top N"
+    }
+
+    fn roles(&self) -> Vec<ColumnRole> {
+        vec![ColumnRole::Category]
+    }
+
+    fn fill(&self, rng: &mut dyn RngCore, context: &ShapeContext, syntax: T) -> Option<Query<T>> {
+        let category = choose_dimension(rng, context, ColumnRole::Category, |d| {
+            d.transform.is_none()
+        })?;
+        let measure = choose_weighted(rng, context.measures, 1, |m| {
+            // Aggregating the column we group on isn't very interesting, and we can't
+            // order by geometries
+            if m.column.as_ref() == Some(&category.column)
+                || !is_orderable(&OrderByItem::Measure(m.clone()))
+            {
+                0.0
+            } else {
+                context.scorer.score_measure(m)
+            }
+        })
+        .pop()?;
+
+        Some(Query::new(
+            context.dataset.clone(),
+            vec![measure.clone()],
+            vec![category],
+            vec![OrderBy {
+                item: OrderByItem::Measure(measure),
+                direction: OrderByDirection::Desc,
+            }],
+            Some(self.n),
+            syntax,
+        ))
+    }
+}
+
+/// How the values of a number are distributed, in buckets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistogramShape {}
+
+impl<T: Syntax + Clone> QueryShape<T> for HistogramShape {
+    fn name(&self) -> &'static str {
+        "distribution"
+    }
+
+    fn roles(&self) -> Vec<ColumnRole> {
+        vec![ColumnRole::Quantity]
+    }
+
+    fn fill(&self, rng: &mut dyn RngCore, context: &ShapeContext, syntax: T) -> Option<Query<T>> {
+        let bucket = choose_dimension(rng, context, ColumnRole::Quantity, |d| {
+            matches!(d.transform, Some(DimensionTransform::Bin(_)))
+        })?;
+
+        Some(Query::new(
+            context.dataset.clone(),
+            vec![count()],
+            vec![bucket.clone()],
+            vec![OrderBy {
+                item: OrderByItem::Dimension(bucket),
+                direction: OrderByDirection::Asc,
+            }],
+            Some(100),
+            syntax,
+        ))
+    }
+}
+
+/// All the different values of a category
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistinctValuesShape {}
+
+impl<T: Syntax + Clone> QueryShape<T> for DistinctValuesShape {
+    fn name(&self) -> &'static str {
+        "distinct values"
+    }
+
+    fn roles(&self) -> Vec<ColumnRole> {
+        vec![ColumnRole::Category]
+    }
+
+    fn fill(&self, rng: &mut dyn RngCore, context: &ShapeContext, syntax: T) -> Option<Query<T>> {
+        let category = choose_dimension(rng, context, ColumnRole::Category, |d| {
+            d.transform.is_none()
+        })?;
+
+        Some(Query::new(
+            context.dataset.clone(),
+            vec![],
+            vec![category.clone()],
+            vec![OrderBy {
+                item: OrderByItem::Dimension(category),
+                direction: OrderByDirection::Asc,
+            }],
+            Some(100),
+            syntax,
+        ))
+    }
+}
+
+/// A few raw rows, with some of the more interesting columns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleRowsShape {
+    pub max_columns: usize,
+    pub rows: usize,
+}
+
+impl<T: Syntax + Clone> QueryShape<T> for SampleRowsShape {
+    fn name(&self) -> &'static str {
+        "sample rows"
+    }
+
+    fn roles(&self) -> Vec<ColumnRole> {
+        vec![ColumnRole::Any]
+    }
+
+    fn fill(&self, rng: &mut dyn RngCore, context: &ShapeContext, syntax: T) -> Option<Query<T>> {
+        let candidates: Vec<Column> = context
+            .dataset
+            .columns
+            .iter()
+            .filter(|c| ColumnRole::Any.accepts(c))
+            .cloned()
+            .collect();
+        let chosen = choose_weighted(rng, &candidates, self.max_columns, |c| {
+            context.scorer.score_column(c)
+        });
+        if chosen.is_empty() {
+            return None;
+        }
+
+        let mut query = Query::new(
+            context.dataset.clone(),
+            vec![],
+            vec![],
+            vec![],
+            Some(self.rows),
+            syntax,
+        );
+        // Keep the dataset's column order
+        query.columns = candidates
+            .into_iter()
+            .filter(|c| chosen.contains(c))
+            .collect();
+        Some(query)
+    }
+}
+
+/// Number of records for every combination of two categories
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwoWayBreakdownShape {}
+
+impl<T: Syntax + Clone> QueryShape<T> for TwoWayBreakdownShape {
+    fn name(&self) -> &'static str {
+        "two-way breakdown"
+    }
+
+    fn roles(&self) -> Vec<ColumnRole> {
+        vec![ColumnRole::Category, ColumnRole::Category]
+    }
+
+    fn fill(&self, rng: &mut dyn RngCore, context: &ShapeContext, syntax: T) -> Option<Query<T>> {
+        let first = choose_dimension(rng, context, ColumnRole::Category, |d| {
+            d.transform.is_none()
+        })?;
+        let second = choose_dimension(rng, context, ColumnRole::Category, |d| {
+            d.transform.is_none() && d.column != first.column
+        })?;
+
+        Some(Query::new(
+            context.dataset.clone(),
+            vec![count()],
+            vec![first.clone(), second.clone()],
+            vec![
+                OrderBy {
+                    item: OrderByItem::Dimension(first),
+                    direction: OrderByDirection::Asc,
+                },
+                OrderBy {
+                    item: OrderByItem::Dimension(second),
+                    direction: OrderByDirection::Asc,
+                },
+            ],
+            Some(100),
+            syntax,
+        ))
+    }
+}
+
+/// All the shapes we know about
+pub fn default_shapes<T: Syntax + Clone>() -> Vec<Box<dyn QueryShape<T>>> {
+    vec![
+        Box::new(TrendShape {}),
+        Box::new(TopNShape { n: 10 }),
+        Box::new(HistogramShape {}),
+        Box::new(DistinctValuesShape {}),
+        Box::new(SampleRowsShape {
+            max_columns: 5,
+            rows: 10,
+        }),
+        Box::new(TwoWayBreakdownShape {}),
+    ]
+}
+
+/// Pick a random shape that the dataset has the columns for and fill it in. Returns None
+/// if none of the shapes could produce a valid query.
+pub fn random_shaped_query<R: RngCore, T: Syntax + Clone>(
+    rng: &mut R,
+    shapes: &[Box<dyn QueryShape<T>>],
+    context: &ShapeContext,
+    syntax: T,
+) -> Option<Query<T>> {
+    let mut candidates: Vec<&Box<dyn QueryShape<T>>> = shapes
+        .iter()
+        .filter(|s| s.can_fill(context.dataset))
+        .collect();
+    candidates.shuffle(rng);
+
+    // If the first shape doesn't work out, try the other ones
+    candidates.into_iter().find_map(|shape| {
+        shape
+            .fill(rng, context, syntax.clone())
+            .filter(|q| q.validate().is_empty())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, DefaultSyntax, DimensionTransform,
+    };
+    use crate::query_gen::scoring::DefaultScorer;
+    use crate::query_gen::shapes::{
+        can_assign_roles, default_shapes, random_shaped_query, ColumnRole, HistogramShape,
+        QueryShape, ShapeContext, TrendShape, TwoWayBreakdownShape,
+    };
+    use crate::query_gen::socrata::{Column, ColumnFormat, ColumnStats, DataType, Dataset};
+    use crate::query_gen::test_util::edmonton_dataset;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_can_assign_roles() {
+        let column = |name: &str, data_type| Column {
+            human_name: name.to_string(),
+            pg_name: name.to_lowercase(),
+            data_type,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Building Permits".to_string(),
+            row_count: None,
+            columns: vec![
+                column("Status", DataType::Text),
+                column("Cost", DataType::Number),
+            ],
+        };
+
+        assert!(can_assign_roles(&dataset, &[ColumnRole::Category]));
+        assert!(can_assign_roles(
+            &dataset,
+            &[ColumnRole::Any, ColumnRole::Category]
+        ));
+        assert!(!can_assign_roles(
+            &dataset,
+            &[ColumnRole::Category, ColumnRole::Category]
+        ));
+        assert!(!can_assign_roles(&dataset, &[ColumnRole::Time]));
+
+        // Free text isn't a category, unless the stats say it only has a few values
+        let mut comments = column("Comments", DataType::Text);
+        assert!(!ColumnRole::Category.accepts(&comments));
+        comments.stats.cardinality = Some(5);
+        assert!(ColumnRole::Category.accepts(&comments));
+        let mut status = column("Status", DataType::Text);
+        status.stats.cardinality = Some(5000);
+        assert!(!ColumnRole::Category.accepts(&status));
+        status.stats.cardinality = Some(1);
+        assert!(!ColumnRole::Category.accepts(&status));
+        let mut region = column(":@computed_region_7ccj_gre3", DataType::Number);
+        region.stats.cardinality = Some(5);
+        assert!(!ColumnRole::Category.accepts(&region));
+
+        let trend: &dyn QueryShape<DefaultSyntax> = &TrendShape {};
+        let two_way: &dyn QueryShape<DefaultSyntax> = &TwoWayBreakdownShape {};
+        let histogram: &dyn QueryShape<DefaultSyntax> = &HistogramShape {};
+        assert!(!trend.can_fill(&dataset));
+        assert!(!two_way.can_fill(&dataset));
+        assert!(histogram.can_fill(&dataset));
+    }
+
+    #[test]
+    fn test_fill_shapes() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+        let context = ShapeContext {
+            dataset: &dataset,
+            measures: &measures,
+            dimensions: &dimensions,
+            scorer: &DefaultScorer {},
+        };
+
+        for shape in default_shapes::<DefaultSyntax>() {
+            assert!(shape.can_fill(&dataset), "{:}", shape.name());
+
+            for seed in 0..20 {
+                let query = shape
+                    .fill(&mut StdRng::seed_from_u64(seed), &context, DefaultSyntax {})
+                    .unwrap();
+                assert_eq!(query.validate(), vec![], "{:}", shape.name());
+            }
+        }
+
+        let query = TrendShape {}
+            .fill(&mut StdRng::seed_from_u64(0), &context, DefaultSyntax {})
+            .unwrap();
+        assert!(matches!(
+            query.dimensions[0].transform,
+            Some(DimensionTransform::DateTrunc(_))
+        ));
+    }
+
+    #[test]
+    fn test_random_shaped_query() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+        let context = ShapeContext {
+            dataset: &dataset,
+            measures: &measures,
+            dimensions: &dimensions,
+            scorer: &DefaultScorer {},
+        };
+        let shapes = default_shapes();
+
+        for seed in 0..20 {
+            let query = random_shaped_query(
+                &mut StdRng::seed_from_u64(seed),
+                &shapes,
+                &context,
+                DefaultSyntax {},
+            )
+            .unwrap();
+            assert_eq!(query.validate(), vec![]);
+        }
+
+        // Nothing to fill the shapes with
+        let empty = Dataset {
+            columns: vec![],
+            ..dataset.clone()
+        };
+        assert!(random_shaped_query(
+            &mut StdRng::seed_from_u64(0),
+            &shapes,
+            &ShapeContext {
+                dataset: &empty,
+                measures: &[],
+                dimensions: &[],
+                scorer: &DefaultScorer {},
+            },
+            DefaultSyntax {},
+        )
+        .is_none());
+    }
+}
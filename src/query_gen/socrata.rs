@@ -1,6 +1,8 @@
 use itertools::izip;
+use serde::{Deserialize as _, Deserializer};
 use serde_derive::Deserialize;
 use serde_enum_str::Deserialize_enum_str;
+use serde_json::Value;
 
 #[derive(Deserialize_enum_str, PartialEq, Eq, Debug, Clone)]
 pub enum DataType {
@@ -30,11 +32,47 @@ pub enum DataType {
     Other(String),
 }
 
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnFormat {
+    // standard, scientific, percentage, currency, financial
+    pub precision_style: Option<String>,
+}
+
+/// Statistics that Socrata keeps about a column's values. Everything is optional, since
+/// not all resources have them.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct ColumnStats {
+    pub non_null_count: Option<u64>,
+    pub null_count: Option<u64>,
+    // Number of distinct values
+    pub cardinality: Option<u64>,
+    // Most common values first
+    pub top_values: Vec<String>,
+    // As Socrata renders them, e.g. 2020-01-31T00:00:00.000 for timestamps
+    pub smallest: Option<String>,
+    pub largest: Option<String>,
+}
+
+impl ColumnStats {
+    /// Whether we know that the column doesn't have any values
+    pub fn is_all_null(&self) -> bool {
+        self.non_null_count == Some(0)
+    }
+
+    /// Total number of rows, if we know both counts
+    pub fn row_count(&self) -> Option<u64> {
+        Some(self.non_null_count? + self.null_count?)
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Column {
     pub human_name: String,
     pub pg_name: String,
     pub data_type: DataType,
+    pub format: ColumnFormat,
+    pub stats: ColumnStats,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -42,9 +80,68 @@ pub struct Dataset {
     pub domain: String,
     pub socrata_id: String,
     pub name: String,
+    // Number of rows, if the catalog tells us
+    pub row_count: Option<u64>,
     pub columns: Vec<Column>,
 }
 
+// Socrata returns counts either as numbers or as strings
+fn deserialize_count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.as_u64().or_else(|| n.as_f64().map(|f| f as u64)),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    })
+}
+
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RawTopValue {
+    item: Value,
+}
+
+// Socrata's `cachedContents`
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RawColumnStats {
+    #[serde(default, deserialize_with = "deserialize_count")]
+    non_null: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_count")]
+    null: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_count")]
+    cardinality: Option<u64>,
+    #[serde(default)]
+    top: Vec<RawTopValue>,
+    #[serde(default)]
+    smallest: Option<Value>,
+    #[serde(default)]
+    largest: Option<Value>,
+}
+
+impl RawColumnStats {
+    fn parse(&self) -> ColumnStats {
+        ColumnStats {
+            non_null_count: self.non_null,
+            null_count: self.null,
+            cardinality: self.cardinality,
+            top_values: self
+                .top
+                .iter()
+                .filter_map(|t| value_to_string(&t.item))
+                .collect(),
+            smallest: self.smallest.as_ref().and_then(value_to_string),
+            largest: self.largest.as_ref().and_then(value_to_string),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct RawDatasetResource {
     name: String,
@@ -52,20 +149,34 @@ pub struct RawDatasetResource {
     columns_name: Vec<String>,
     columns_field_name: Vec<String>,
     columns_datatype: Vec<DataType>,
+    #[serde(default)]
+    columns_format: Vec<ColumnFormat>,
+    #[serde(default)]
+    columns_cached_contents: Vec<Option<RawColumnStats>>,
 }
 
 pub fn parse_dataset(domain: &str, resource: &RawDatasetResource) -> Dataset {
     let mut columns: Vec<Column> = Vec::new();
 
-    for (human_name, pg_name, data_type) in izip!(
+    for (i, (human_name, pg_name, data_type)) in izip!(
         resource.columns_name.clone(),
         resource.columns_field_name.clone(),
         resource.columns_datatype.clone()
-    ) {
+    )
+    .enumerate()
+    {
         columns.push(Column {
             human_name,
             pg_name,
             data_type,
+            // Not all resources have the format metadata
+            format: resource.columns_format.get(i).cloned().unwrap_or_default(),
+            // Same for the statistics
+            stats: resource
+                .columns_cached_contents
+                .get(i)
+                .and_then(|s| s.as_ref().map(|s| s.parse()))
+                .unwrap_or_default(),
         })
     }
 
@@ -73,24 +184,22 @@ pub fn parse_dataset(domain: &str, resource: &RawDatasetResource) -> Dataset {
         domain: domain.to_string(),
         socrata_id: resource.id.clone(),
         name: resource.name.clone(),
+        // All columns count the same rows
+        row_count: columns.iter().filter_map(|c| c.stats.row_count()).max(),
         columns,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::query_gen::socrata::{parse_dataset, Column, DataType, Dataset, RawDatasetResource};
-    use std::fs;
-    use std::path::PathBuf;
+    use crate::query_gen::socrata::{
+        parse_dataset, Column, ColumnFormat, ColumnStats, DataType, Dataset, RawDatasetResource,
+    };
+    use crate::query_gen::test_util::edmonton_dataset;
 
     #[test]
     fn test_parse_dataset() {
-        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        d.push("resources/test/socrata_resource.json");
-
-        let resource_json = fs::read_to_string(d).unwrap();
-        let resource: RawDatasetResource = serde_json::from_str(&resource_json).unwrap();
-        let dataset = parse_dataset("data.edmonton.ca", &resource);
+        let dataset = edmonton_dataset();
 
         assert_eq!(
             dataset,
@@ -98,46 +207,101 @@ mod tests {
                 domain: "data.edmonton.ca".to_string(),
                 socrata_id: "u7r4-acwa".to_string(),
                 name: "Facility/Service Notification History".to_string(),
+                row_count: None,
                 columns: vec![
-                    Column { human_name: "Row ID".to_string(), pg_name: "row_id".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Facility/Service Type".to_string(), pg_name: "facility_service_type".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Facility/Service ID".to_string(), pg_name: "facility_service_id".to_string(), data_type: DataType::Number },
-                    Column { human_name: "Facility/Service Name".to_string(), pg_name: "facility_service_name".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Facility/Service Short Name".to_string(), pg_name: "facility_service_short_name".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Facility/Service Information".to_string(), pg_name: "facility_service_information".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Address".to_string(), pg_name: "address".to_string(), data_type: DataType::Text },
-                    Column { human_name: "edmonton.ca Info Page".to_string(), pg_name: "edmonton_ca_info_page".to_string(), data_type: DataType::Url },
-                    Column { human_name: "Notification ID".to_string(), pg_name: "notification_id".to_string(), data_type: DataType::Number },
-                    Column { human_name: "Notice Publish Start Date".to_string(), pg_name: "notice_publish_start_date".to_string(), data_type: DataType::CalendarDate },
-                    Column { human_name: "Notice Publish End Date".to_string(), pg_name: "notice_publish_end_date".to_string(), data_type: DataType::CalendarDate },
-                    Column { human_name: "Notice Date Information".to_string(), pg_name: "notice_date_information".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Notification Start Date".to_string(), pg_name: "notification_start_date".to_string(), data_type: DataType::CalendarDate },
-                    Column { human_name: "Notification End Date".to_string(), pg_name: "notification_end_date".to_string(), data_type: DataType::CalendarDate },
-                    Column { human_name: "Is a Service Notification".to_string(), pg_name: "is_a_service_notification".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Notice Start Time of Day".to_string(), pg_name: "notice_start_time_of_day".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Notice End Time of Day".to_string(), pg_name: "notice_end_time_of_day".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Notice Has Holiday Hours".to_string(), pg_name: "notice_has_holiday_hours".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Additional Information".to_string(), pg_name: "additional_information".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Status".to_string(), pg_name: "status".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Date Time".to_string(), pg_name: "date_time".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Service".to_string(), pg_name: "service".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Reason".to_string(), pg_name: "reason".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Last Updated".to_string(), pg_name: "last_updated".to_string(), data_type: DataType::CalendarDate },
-                    Column { human_name: "Latitude".to_string(), pg_name: "latitude".to_string(), data_type: DataType::Number },
-                    Column { human_name: "Longitude".to_string(), pg_name: "longitude".to_string(), data_type: DataType::Number },
-                    Column { human_name: "Location".to_string(), pg_name: "location".to_string(), data_type: DataType::Point },
-                    Column { human_name: "Location (address)".to_string(), pg_name: "location_address".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Geometry Point".to_string(), pg_name: "geometry_point".to_string(), data_type: DataType::Point },
-                    Column { human_name: "Location (city)".to_string(), pg_name: "location_city".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Location (state)".to_string(), pg_name: "location_state".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Location (zip)".to_string(), pg_name: "location_zip".to_string(), data_type: DataType::Text },
-                    Column { human_name: "Neighbourhood Boundaries : 2019".to_string(), pg_name: ":@computed_region_7ccj_gre3".to_string(), data_type: DataType::Number },
-                    Column { human_name: "Roadway Maintenance Area Polygon".to_string(), pg_name: ":@computed_region_ecxu_fw7u".to_string(), data_type: DataType::Number },
-                    Column { human_name: "Edmonton Public School Board (EPSB) Ward Boundaries (effective at 12:00 AM on Oct 16, 2017)".to_string(), pg_name: ":@computed_region_izdr_ja4x".to_string(), data_type: DataType::Number },
-                    Column { human_name: "Edmonton Catholic School District Ward Boundaries (effective at 12:00 AM on Oct 16, 2017)".to_string(), pg_name: ":@computed_region_5jki_au6x".to_string(), data_type: DataType::Number },
-                    Column { human_name: "City of Edmonton - Ward Boundaries (effective at 12:00 AM on Oct 16, 2017)".to_string(), pg_name: ":@computed_region_mnf4_kaez".to_string(), data_type: DataType::Number },
-                    Column { human_name: "City of Edmonton : Neighbourhood Boundaries".to_string(), pg_name: ":@computed_region_eq8d_jmrp".to_string(), data_type: DataType::Number }],
+                    Column { human_name: "Row ID".to_string(), pg_name: "row_id".to_string(), data_type: DataType::Text, format: ColumnFormat { precision_style: Some("standard".to_string()) }, stats: ColumnStats::default() },
+                    Column { human_name: "Facility/Service Type".to_string(), pg_name: "facility_service_type".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Facility/Service ID".to_string(), pg_name: "facility_service_id".to_string(), data_type: DataType::Number, format: ColumnFormat { precision_style: Some("standard".to_string()) }, stats: ColumnStats::default() },
+                    Column { human_name: "Facility/Service Name".to_string(), pg_name: "facility_service_name".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Facility/Service Short Name".to_string(), pg_name: "facility_service_short_name".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Facility/Service Information".to_string(), pg_name: "facility_service_information".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Address".to_string(), pg_name: "address".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "edmonton.ca Info Page".to_string(), pg_name: "edmonton_ca_info_page".to_string(), data_type: DataType::Url, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Notification ID".to_string(), pg_name: "notification_id".to_string(), data_type: DataType::Number, format: ColumnFormat { precision_style: Some("standard".to_string()) }, stats: ColumnStats::default() },
+                    Column { human_name: "Notice Publish Start Date".to_string(), pg_name: "notice_publish_start_date".to_string(), data_type: DataType::CalendarDate, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Notice Publish End Date".to_string(), pg_name: "notice_publish_end_date".to_string(), data_type: DataType::CalendarDate, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Notice Date Information".to_string(), pg_name: "notice_date_information".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Notification Start Date".to_string(), pg_name: "notification_start_date".to_string(), data_type: DataType::CalendarDate, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Notification End Date".to_string(), pg_name: "notification_end_date".to_string(), data_type: DataType::CalendarDate, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Is a Service Notification".to_string(), pg_name: "is_a_service_notification".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Notice Start Time of Day".to_string(), pg_name: "notice_start_time_of_day".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Notice End Time of Day".to_string(), pg_name: "notice_end_time_of_day".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Notice Has Holiday Hours".to_string(), pg_name: "notice_has_holiday_hours".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Additional Information".to_string(), pg_name: "additional_information".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Status".to_string(), pg_name: "status".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Date Time".to_string(), pg_name: "date_time".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Service".to_string(), pg_name: "service".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Reason".to_string(), pg_name: "reason".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Last Updated".to_string(), pg_name: "last_updated".to_string(), data_type: DataType::CalendarDate, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Latitude".to_string(), pg_name: "latitude".to_string(), data_type: DataType::Number, format: ColumnFormat { precision_style: Some("standard".to_string()) }, stats: ColumnStats::default() },
+                    Column { human_name: "Longitude".to_string(), pg_name: "longitude".to_string(), data_type: DataType::Number, format: ColumnFormat { precision_style: Some("standard".to_string()) }, stats: ColumnStats::default() },
+                    Column { human_name: "Location".to_string(), pg_name: "location".to_string(), data_type: DataType::Point, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Location (address)".to_string(), pg_name: "location_address".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Geometry Point".to_string(), pg_name: "geometry_point".to_string(), data_type: DataType::Point, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Location (city)".to_string(), pg_name: "location_city".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Location (state)".to_string(), pg_name: "location_state".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Location (zip)".to_string(), pg_name: "location_zip".to_string(), data_type: DataType::Text, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Neighbourhood Boundaries : 2019".to_string(), pg_name: ":@computed_region_7ccj_gre3".to_string(), data_type: DataType::Number, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Roadway Maintenance Area Polygon".to_string(), pg_name: ":@computed_region_ecxu_fw7u".to_string(), data_type: DataType::Number, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Edmonton Public School Board (EPSB) Ward Boundaries (effective at 12:00 AM on Oct 16, 2017)".to_string(), pg_name: ":@computed_region_izdr_ja4x".to_string(), data_type: DataType::Number, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "Edmonton Catholic School District Ward Boundaries (effective at 12:00 AM on Oct 16, 2017)".to_string(), pg_name: ":@computed_region_5jki_au6x".to_string(), data_type: DataType::Number, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "City of Edmonton - Ward Boundaries (effective at 12:00 AM on Oct 16, 2017)".to_string(), pg_name: ":@computed_region_mnf4_kaez".to_string(), data_type: DataType::Number, format: ColumnFormat::default(), stats: ColumnStats::default() },
+                    Column { human_name: "City of Edmonton : Neighbourhood Boundaries".to_string(), pg_name: ":@computed_region_eq8d_jmrp".to_string(), data_type: DataType::Number, format: ColumnFormat::default(), stats: ColumnStats::default() }],
             }
         )
     }
+
+    #[test]
+    fn test_parse_dataset_stats() {
+        let resource: RawDatasetResource = serde_json::from_str(
+            r#"{
+                "name": "Building Permits",
+                "id": "24uj-dj8v",
+                "columns_name": ["Status", "Floors", "Issued", "Notes"],
+                "columns_field_name": ["status", "floors", "issued", "notes"],
+                "columns_datatype": ["Text", "Number", "Calendar date", "Text"],
+                "columns_cached_contents": [
+                    {
+                        "non_null": "990",
+                        "null": "10",
+                        "cardinality": "3",
+                        "top": [{"item": "Issued", "count": "600"}, {"item": "Pending", "count": 300}],
+                        "smallest": "Issued",
+                        "largest": "Withdrawn"
+                    },
+                    {"non_null": 1000, "null": 0, "smallest": "1", "largest": 54},
+                    null
+                ]
+            }"#,
+        )
+        .unwrap();
+        let dataset = parse_dataset("data.edmonton.ca", &resource);
+
+        assert_eq!(dataset.row_count, Some(1000));
+        assert_eq!(
+            dataset.columns[0].stats,
+            ColumnStats {
+                non_null_count: Some(990),
+                null_count: Some(10),
+                cardinality: Some(3),
+                top_values: vec!["Issued".to_string(), "Pending".to_string()],
+                smallest: Some("Issued".to_string()),
+                largest: Some("Withdrawn".to_string()),
+            }
+        );
+        assert_eq!(
+            dataset.columns[1].stats,
+            ColumnStats {
+                non_null_count: Some(1000),
+                null_count: Some(0),
+                cardinality: None,
+                top_values: vec![],
+                smallest: Some("1".to_string()),
+                largest: Some("54".to_string()),
+            }
+        );
+        // Either explicitly or implicitly missing
+        assert_eq!(dataset.columns[2].stats, ColumnStats::default());
+        assert_eq!(dataset.columns[3].stats, ColumnStats::default());
+    }
 }
@@ -0,0 +1,545 @@
+use crate::query_gen::query::{
+    DatePart, DateTruncUnit, DimensionTransform, Literal, MeasureType, Query, SqlDialect, Syntax,
+};
+use crate::query_gen::socrata::{Column, Dataset};
+use itertools::Itertools;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+// Everything apart from the unreserved characters (RFC 3986)
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// SoQL, the query language of the Socrata API, run directly against a dataset's
+/// resource endpoint. Columns are referenced by their Socrata field names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoqlSyntax {}
+
+// Words that SoQL would read as part of the query instead of as a field name
+const KEYWORDS: &[&str] = &[
+    "and", "as", "asc", "avg", "between", "by", "case", "count", "desc", "distinct", "else", "end",
+    "false", "first", "from", "full", "group", "having", "in", "inner", "is", "join", "last",
+    "left", "like", "limit", "max", "min", "not", "null", "nulls", "offset", "on", "or", "order",
+    "outer", "right", "search", "select", "sum", "then", "true", "when", "where",
+];
+
+fn is_field_name(identifier: &str) -> bool {
+    // System fields and computed regions, e.g. :id or :@computed_region_7ccj_gre3
+    let name = identifier
+        .strip_prefix(":@")
+        .or_else(|| identifier.strip_prefix(':'))
+        .unwrap_or(identifier);
+
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&identifier.to_lowercase().as_str())
+}
+
+impl Syntax for SoqlSyntax {
+    fn get_sql_dialect(&self) -> Option<SqlDialect> {
+        // Close to SQL, but not close enough for a SQL parser
+        None
+    }
+
+    fn get_dataset_sql(&self, dataset: &Dataset) -> String {
+        // SoQL queries don't have a FROM: the dataset is in the URL
+        dataset.socrata_id.clone()
+    }
+
+    fn get_column_sql(&self, column: &Column) -> String {
+        self.get_identifier_sql(&column.pg_name)
+    }
+
+    fn get_identifier_sql(&self, identifier: &str) -> String {
+        if is_field_name(identifier) {
+            identifier.to_string()
+        } else {
+            format!("`{identifier}`")
+        }
+    }
+
+    fn can_represent_identifier(&self, identifier: &str) -> bool {
+        !identifier.is_empty() && !identifier.contains('`')
+    }
+
+    fn get_literal_sql(&self, literal: &Literal) -> String {
+        match literal {
+            Literal::Number(n) => n.clone(),
+            Literal::Text(s) => format!("'{:}'", s.replace('\'', "''")),
+            // Floating timestamps need the time part
+            Literal::Date(d) => format!("'{d}T00:00:00'"),
+            Literal::Boolean(true) => "true".to_string(),
+            Literal::Boolean(false) => "false".to_string(),
+        }
+    }
+
+    fn get_date_trunc_sql(&self, unit: &DateTruncUnit, expression: &str) -> String {
+        match unit {
+            DateTruncUnit::Day => format!("date_trunc_ymd({expression})"),
+            DateTruncUnit::Month => format!("date_trunc_ym({expression})"),
+            DateTruncUnit::Year => format!("date_trunc_y({expression})"),
+            // Not supported, see supports_dimension_transform
+            DateTruncUnit::Week | DateTruncUnit::Quarter => expression.to_string(),
+        }
+    }
+
+    fn get_extract_sql(&self, part: &DatePart, expression: &str) -> String {
+        let part = match part {
+            DatePart::Year => "y",
+            DatePart::Month => "m",
+            DatePart::DayOfWeek => "dow",
+            DatePart::Hour => "hh",
+        };
+        format!("date_extract_{part}({expression})")
+    }
+
+    fn supports_measure_type(&self, measure_type: &MeasureType) -> bool {
+        // There's no aggregate that collects geometries to take the centroid of
+        !matches!(
+            measure_type,
+            MeasureType::Percentile(_) | MeasureType::VarSamp | MeasureType::Centroid
+        )
+    }
+
+    fn supports_dimension_transform(&self, transform: &DimensionTransform) -> bool {
+        match transform {
+            DimensionTransform::DateTrunc(unit) => {
+                !matches!(unit, DateTruncUnit::Week | DateTruncUnit::Quarter)
+            }
+            DimensionTransform::Extract(_) => true,
+            DimensionTransform::Bin(_) => false,
+            DimensionTransform::SnapToGrid { .. } => true,
+        }
+    }
+
+    fn supports_window_functions(&self) -> bool {
+        false
+    }
+
+    fn supports_common_table_expressions(&self) -> bool {
+        false
+    }
+
+    fn get_median_sql(&self, expression: &str) -> String {
+        format!("median({expression})")
+    }
+
+    fn get_extent_sql(&self, expression: &str) -> String {
+        format!("extent({expression})")
+    }
+
+    fn get_count_within_radius_sql(
+        &self,
+        latitude: &str,
+        longitude: &str,
+        meters: &str,
+        expression: &str,
+    ) -> String {
+        format!(
+            "sum(case(within_circle({expression}, {latitude}, {longitude}, {meters}), 1, true, 0))"
+        )
+    }
+
+    fn get_snap_to_grid_sql(&self, size: &str, expression: &str) -> String {
+        format!("snap_to_grid({expression}, {size})")
+    }
+}
+
+/// The SoQL clauses of a query as SODA parameters ($select, $where, $group, $having,
+/// $order, $limit), skipping the ones it doesn't have. Assumes the query passes `validate`.
+pub fn to_soql_params(query: &Query<SoqlSyntax>) -> Vec<(&'static str, String)> {
+    let mut params = vec![("$select", query.emit_select_items().join(", "))];
+
+    if let Some(filter) = &query.filter {
+        params.push(("$where", query.emit_filter(filter)));
+    }
+
+    if !query.dimensions.is_empty() {
+        params.push((
+            "$group",
+            query
+                .dimensions
+                .iter()
+                .map(|d| query.emit_dimension(d))
+                .join(", "),
+        ));
+    }
+
+    if !query.having.is_empty() {
+        params.push((
+            "$having",
+            query
+                .having
+                .iter()
+                .map(|h| query.emit_having(h))
+                .join(" AND "),
+        ));
+    }
+
+    if !query.order_by.is_empty() {
+        params.push((
+            "$order",
+            query
+                .order_by
+                .iter()
+                .map(|o| query.emit_order_by(o))
+                .join(", "),
+        ));
+    }
+
+    if let Some(l) = query.limit {
+        params.push(("$limit", l.to_string()));
+    }
+
+    params
+}
+
+/// The whole query as one SoQL statement, for the $query parameter
+pub fn to_soql_query(query: &Query<SoqlSyntax>) -> String {
+    to_soql_params(query)
+        .into_iter()
+        .map(|(param, value)| {
+            let keyword = match param {
+                "$select" => "SELECT",
+                "$where" => "WHERE",
+                "$group" => "GROUP BY",
+                "$having" => "HAVING",
+                "$order" => "ORDER BY",
+                _ => "LIMIT",
+            };
+            format!("{keyword} {value}")
+        })
+        .join(" ")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum SodaUrlForm {
+    // ?$select=...&$group=...
+    Params,
+    // ?$query=SELECT ...
+    Query,
+}
+
+/// Full SODA API URL that runs the query against the dataset's JSON endpoint
+#[allow(dead_code)]
+pub fn to_soda_url(query: &Query<SoqlSyntax>, form: SodaUrlForm) -> String {
+    let params = match form {
+        SodaUrlForm::Params => to_soql_params(query),
+        SodaUrlForm::Query => vec![("$query", to_soql_query(query))],
+    };
+
+    format!(
+        "https://{:}/resource/{:}.json?{:}",
+        query.dataset.domain,
+        query.dataset.socrata_id,
+        params
+            .iter()
+            .map(|(param, value)| format!("{param}={:}", utf8_percent_encode(value, QUERY_VALUE)))
+            .join("&")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, random_query, random_top_n_per_group_query,
+        ComparisonOperator, DateTruncUnit, Diagnostic, Dimension, DimensionTransform, Filter,
+        HavingCondition, Literal, Measure, MeasureType, OrderBy, OrderByDirection, OrderByItem,
+        Query,
+    };
+    use crate::query_gen::scoring::DefaultScorer;
+    use crate::query_gen::socrata::{Column, ColumnFormat, ColumnStats, DataType, Dataset};
+    use crate::query_gen::soql::{
+        to_soda_url, to_soql_params, to_soql_query, SodaUrlForm, SoqlSyntax,
+    };
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn get_query() -> Query<SoqlSyntax> {
+        let column = |pg_name: &str, data_type: DataType| Column {
+            human_name: pg_name.to_string(),
+            pg_name: pg_name.to_string(),
+            data_type,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        let issued = column("issued", DataType::FloatingTimestamp);
+        let cost = column("cost", DataType::Number);
+        let region = column(":@computed_region_7ccj_gre3", DataType::Number);
+        let status = column("status", DataType::Text);
+
+        let dataset = Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Building Permits".to_string(),
+            row_count: None,
+            columns: vec![issued.clone(), cost.clone(), region.clone(), status.clone()],
+        };
+
+        let average_cost = Measure {
+            type_: MeasureType::Average,
+            column: Some(cost),
+        };
+        let month = Dimension {
+            column: issued.clone(),
+            transform: Some(DimensionTransform::DateTrunc(DateTruncUnit::Month)),
+            alias: None,
+        };
+
+        let mut query = Query::new(
+            dataset,
+            vec![
+                Measure {
+                    type_: MeasureType::Count,
+                    column: None,
+                },
+                average_cost.clone(),
+            ],
+            vec![
+                month.clone(),
+                Dimension {
+                    column: region,
+                    transform: None,
+                    alias: None,
+                },
+            ],
+            vec![
+                OrderBy {
+                    item: OrderByItem::Dimension(month),
+                    direction: OrderByDirection::Asc,
+                },
+                OrderBy {
+                    item: OrderByItem::Measure(average_cost.clone()),
+                    direction: OrderByDirection::Desc,
+                },
+            ],
+            Some(100),
+            SoqlSyntax {},
+        );
+        query.filter = Some(Filter::And(vec![
+            Filter::In {
+                column: status,
+                values: vec![
+                    Literal::Text("Issued".to_string()),
+                    Literal::Text("Inspector's review".to_string()),
+                ],
+            },
+            Filter::Comparison {
+                column: issued,
+                operator: ComparisonOperator::GtEq,
+                value: Literal::Date("2020-01-01".to_string()),
+            },
+        ]));
+        query.having = vec![HavingCondition::Comparison {
+            measure: average_cost,
+            operator: ComparisonOperator::Gt,
+            value: Literal::Number("1000".to_string()),
+        }];
+
+        query
+    }
+
+    #[test]
+    fn test_emit_soql_params() {
+        assert_eq!(
+            to_soql_params(&get_query()),
+            vec![
+                (
+                    "$select",
+                    "date_trunc_ym(issued) AS month_issued, :@computed_region_7ccj_gre3 AS computed_region_7ccj_gre3, COUNT(*) AS `count`, AVG(cost) AS avg_cost".to_string()
+                ),
+                (
+                    "$where",
+                    "status IN ('Issued', 'Inspector''s review') AND issued >= '2020-01-01T00:00:00'".to_string()
+                ),
+                (
+                    "$group",
+                    "date_trunc_ym(issued), :@computed_region_7ccj_gre3".to_string()
+                ),
+                ("$having", "AVG(cost) > 1000".to_string()),
+                (
+                    "$order",
+                    "date_trunc_ym(issued) ASC, AVG(cost) DESC".to_string()
+                ),
+                ("$limit", "100".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_emit_soql_query() {
+        assert_eq!(
+            to_soql_query(&get_query()),
+            "SELECT date_trunc_ym(issued) AS month_issued, :@computed_region_7ccj_gre3 AS computed_region_7ccj_gre3, COUNT(*) AS `count`, AVG(cost) AS avg_cost \
+             WHERE status IN ('Issued', 'Inspector''s review') AND issued >= '2020-01-01T00:00:00' \
+             GROUP BY date_trunc_ym(issued), :@computed_region_7ccj_gre3 \
+             HAVING AVG(cost) > 1000 \
+             ORDER BY date_trunc_ym(issued) ASC, AVG(cost) DESC \
+             LIMIT 100"
+        );
+    }
+
+    #[test]
+    fn test_emit_soda_url() {
+        let mut query = get_query();
+        query.filter = None;
+        query.having = vec![];
+        query.order_by = vec![];
+
+        assert_eq!(
+            to_soda_url(&query, SodaUrlForm::Params),
+            "https://data.edmonton.ca/resource/u7r4-acwa.json\
+             ?$select=date_trunc_ym%28issued%29%20AS%20month_issued%2C%20%3A%40computed_region_7ccj_gre3%20AS%20computed_region_7ccj_gre3%2C%20COUNT%28%2A%29%20AS%20%60count%60%2C%20AVG%28cost%29%20AS%20avg_cost\
+             &$group=date_trunc_ym%28issued%29%2C%20%3A%40computed_region_7ccj_gre3\
+             &$limit=100"
+        );
+
+        query.measures.truncate(1);
+        query.dimensions.truncate(1);
+        assert_eq!(
+            to_soda_url(&query, SodaUrlForm::Query),
+            "https://data.edmonton.ca/resource/u7r4-acwa.json\
+             ?$query=SELECT%20date_trunc_ym%28issued%29%20AS%20month_issued%2C%20COUNT%28%2A%29%20AS%20%60count%60%20GROUP%20BY%20date_trunc_ym%28issued%29%20LIMIT%20100"
+        );
+    }
+
+    #[test]
+    fn test_emit_soql_keyword_identifiers() {
+        let mut query = get_query();
+        let column = |pg_name: &str| Column {
+            human_name: pg_name.to_string(),
+            pg_name: pg_name.to_string(),
+            data_type: DataType::Text,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        query.measures.truncate(1);
+        query.dimensions = vec![Dimension {
+            column: column("group"),
+            transform: None,
+            alias: None,
+        }];
+        query.filter = Some(Filter::IsNull {
+            column: column("order"),
+            negated: true,
+        });
+        query.order_by = vec![];
+        query.having = vec![];
+
+        assert_eq!(
+            to_soql_query(&query),
+            "SELECT `group`, COUNT(*) AS `count` WHERE `order` IS NOT NULL GROUP BY `group` LIMIT 100"
+        );
+    }
+
+    #[test]
+    fn test_validate_soql() {
+        let mut query = get_query();
+        query.measures[1].type_ = MeasureType::Percentile(90);
+        query.dimensions[0].transform = Some(DimensionTransform::DateTrunc(DateTruncUnit::Quarter));
+        query.order_by = vec![];
+        query.having = vec![];
+
+        assert_eq!(
+            query.validate(),
+            vec![
+                Diagnostic::UnsupportedMeasure(query.measures[1].clone()),
+                Diagnostic::UnsupportedDimension(query.dimensions[0].clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_emit_soql_geospatial() {
+        let mut query = get_query();
+        let location = Column {
+            human_name: "Location".to_string(),
+            pg_name: "location".to_string(),
+            data_type: DataType::Point,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        };
+        query.measures = vec![
+            Measure {
+                type_: MeasureType::Extent,
+                column: Some(location.clone()),
+            },
+            Measure {
+                type_: MeasureType::CountWithinRadius {
+                    latitude: "53.5".to_string(),
+                    longitude: "-113.5".to_string(),
+                    meters: "1000".to_string(),
+                },
+                column: Some(location.clone()),
+            },
+        ];
+        query.dimensions = vec![Dimension {
+            column: location.clone(),
+            transform: Some(DimensionTransform::SnapToGrid {
+                size: "0.01".to_string(),
+            }),
+            alias: None,
+        }];
+        query.filter = None;
+        query.order_by = vec![];
+        query.having = vec![];
+
+        assert_eq!(
+            to_soql_params(&query),
+            vec![
+                (
+                    "$select",
+                    "snap_to_grid(location, 0.01) AS grid_location, extent(location) AS extent_location, \
+                     sum(case(within_circle(location, 53.5, -113.5, 1000), 1, true, 0)) AS count_within_1000m_location"
+                        .to_string()
+                ),
+                ("$group", "snap_to_grid(location, 0.01)".to_string()),
+                ("$limit", "100".to_string()),
+            ]
+        );
+        assert_eq!(query.validate(), vec![]);
+
+        // No aggregate centroid in SoQL
+        query.measures[0].type_ = MeasureType::Centroid;
+        assert_eq!(
+            query.validate(),
+            vec![Diagnostic::UnsupportedMeasure(query.measures[0].clone())]
+        );
+    }
+
+    #[test]
+    fn test_random_query_soql() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        for seed in 0..100 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let query = random_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &default_params(),
+                SoqlSyntax {},
+            );
+            assert_eq!(query.validate(), vec![]);
+            assert!(query.window_measures.is_empty());
+
+            // Top N per group needs a CTE and a window function
+            assert!(random_top_n_per_group_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &DefaultScorer {},
+                SoqlSyntax {}
+            )
+            .is_none());
+        }
+    }
+}
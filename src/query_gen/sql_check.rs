@@ -0,0 +1,132 @@
+use crate::query_gen::query::SqlDialect;
+use sqlparser::dialect::{
+    Dialect, DuckDbDialect, GenericDialect, PostgreSqlDialect, SQLiteDialect,
+};
+use sqlparser::parser::{Parser, ParserError};
+
+fn get_parser_dialect(dialect: &SqlDialect) -> Box<dyn Dialect> {
+    match dialect {
+        SqlDialect::Generic => Box::new(GenericDialect {}),
+        SqlDialect::PostgreSql => Box::new(PostgreSqlDialect {}),
+        SqlDialect::SQLite => Box::new(SQLiteDialect {}),
+        SqlDialect::DuckDb => Box::new(DuckDbDialect {}),
+    }
+}
+
+/// Make sure that some SQL is a single statement that sqlparser can parse in the dialect
+pub fn check_sql(sql: &str, dialect: &SqlDialect) -> Result<(), ParserError> {
+    let statements = Parser::parse_sql(get_parser_dialect(dialect).as_ref(), sql)?;
+    match statements.len() {
+        1 => Ok(()),
+        n => Err(ParserError::ParserError(format!(
+            "expected one statement, got {n}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ddn::{SplitgraphDDNSyntax, SplitgraphInfo};
+    use crate::query_gen::dialect::{DuckDbSyntax, PostgresSyntax, SqliteSyntax};
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, random_query, random_top_n_per_group_query,
+        DefaultSyntax, Diagnostic, Measure, MeasureType, Query, SqlDialect, Syntax,
+    };
+    use crate::query_gen::scoring::DefaultScorer;
+    use crate::query_gen::socrata::{Column, Dataset};
+    use crate::query_gen::sql_check::check_sql;
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_check_sql() {
+        assert!(check_sql("SELECT COUNT(*) FROM \"u7r4-acwa\"", &SqlDialect::Generic).is_ok());
+        assert!(check_sql("SELECT COUNT(*) FROM u7r4-acwa", &SqlDialect::Generic).is_err());
+        assert!(check_sql("SELECT 1; SELECT 2", &SqlDialect::PostgreSql).is_err());
+    }
+
+    // A Syntax that doesn't quote anything, like DefaultSyntax used to
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct UnquotedSyntax {}
+
+    impl Syntax for UnquotedSyntax {
+        fn get_dataset_sql(&self, dataset: &Dataset) -> String {
+            dataset.socrata_id.clone()
+        }
+
+        fn get_column_sql(&self, column: &Column) -> String {
+            column.pg_name.clone()
+        }
+
+        fn get_identifier_sql(&self, identifier: &str) -> String {
+            identifier.to_string()
+        }
+    }
+
+    #[test]
+    fn test_validate_unparseable() {
+        let query = Query::new(
+            edmonton_dataset(),
+            vec![Measure {
+                type_: MeasureType::Count,
+                column: None,
+            }],
+            vec![],
+            vec![],
+            Some(10),
+            UnquotedSyntax {},
+        );
+        assert!(matches!(
+            &query.validate()[..],
+            [Diagnostic::UnparseableSql(_)]
+        ));
+    }
+
+    fn check_random_queries<T: Syntax + Clone>(syntax: T) {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let query = random_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &default_params(),
+                syntax.clone(),
+            );
+            let dialect = syntax.get_sql_dialect().unwrap();
+            check_sql(&query.to_sql(), &dialect)
+                .unwrap_or_else(|e| panic!("{:}\n{:}", e, query.to_sql()));
+
+            if let Some(top_n) = random_top_n_per_group_query(
+                &mut rng,
+                &dataset,
+                &measures,
+                &dimensions,
+                &DefaultScorer {},
+                syntax.clone(),
+            ) {
+                check_sql(&top_n.to_sql(), &dialect)
+                    .unwrap_or_else(|e| panic!("{:}\n{:}", e, top_n.to_sql()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_queries_parse() {
+        check_random_queries(DefaultSyntax {});
+        check_random_queries(PostgresSyntax {});
+        check_random_queries(SqliteSyntax {});
+        check_random_queries(DuckDbSyntax {});
+        check_random_queries(SplitgraphDDNSyntax {
+            repository: SplitgraphInfo {
+                namespace: "edmonton".to_string(),
+                repository: "u7r4-acwa".to_string(),
+            },
+        });
+    }
+}
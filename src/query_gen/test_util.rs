@@ -0,0 +1,29 @@
+use crate::query_gen::query::RandomQueryParams;
+use crate::query_gen::scoring::DefaultScorer;
+use crate::query_gen::socrata::{parse_dataset, Dataset, RawDatasetResource};
+use std::fs;
+use std::path::PathBuf;
+
+/// The dataset in resources/test/socrata_resource.json (Edmonton's facility and service
+/// notifications)
+pub fn edmonton_dataset() -> Dataset {
+    let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("resources/test/socrata_resource.json");
+    let resource_json = fs::read_to_string(d).unwrap();
+    let resource: RawDatasetResource = serde_json::from_str(&resource_json).unwrap();
+    parse_dataset("data.edmonton.ca", &resource)
+}
+
+/// What most tests generate random queries with: a couple of everything and no cost budget
+pub fn default_params() -> RandomQueryParams {
+    RandomQueryParams {
+        no_dimensions: 1..3,
+        no_measures: 1..4,
+        no_order_bys: 0..3,
+        no_havings: 0..2,
+        no_window_measures: 0..2,
+        no_filters: 0..3,
+        max_cost: None,
+        scorer: Box::new(DefaultScorer {}),
+    }
+}
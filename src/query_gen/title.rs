@@ -0,0 +1,476 @@
+use crate::query_gen::query::{
+    is_temporal, Binning, ComparisonOperator, DatePart, DateTruncUnit, Dimension,
+    DimensionTransform, Filter, Literal, Measure, MeasureType, OrderBy, OrderByDirection,
+    OrderByItem, Query, QuerySource, Syntax, WindowFunction, WindowMeasure,
+};
+use crate::query_gen::socrata::DataType;
+use itertools::Itertools;
+
+// 1st, 2nd, 3rd, 4th, ..., 11th, 12th, 13th, ..., 21st
+pub(crate) fn ordinal(number: u8) -> String {
+    let suffix = match (number % 10, number % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{number}{suffix}")
+}
+
+/// English name of an aggregate, e.g. "Average Latitude"
+pub fn measure_title(measure: &Measure) -> String {
+    let column = match &measure.column {
+        Some(c) => c,
+        None => return "Number of Records".to_string(),
+    };
+    let name = &column.human_name;
+    let is_time = is_temporal(&column.data_type);
+
+    match &measure.type_ {
+        MeasureType::Count => "Number of Records".to_string(),
+        MeasureType::CountDistinct => format!("Number of Distinct {name}"),
+        MeasureType::CountNonNull => format!("Number of Records with {name}"),
+        MeasureType::Sum => format!("Total {name}"),
+        MeasureType::Average => format!("Average {name}"),
+        MeasureType::Min if is_time => format!("Earliest {name}"),
+        MeasureType::Min => format!("Lowest {name}"),
+        MeasureType::Max if is_time => format!("Latest {name}"),
+        MeasureType::Max => format!("Highest {name}"),
+        MeasureType::Median => format!("Median {name}"),
+        MeasureType::Percentile(p) => format!("{:} Percentile of {name}", ordinal(*p)),
+        MeasureType::StddevSamp => format!("Standard Deviation of {name}"),
+        MeasureType::VarSamp => format!("Variance of {name}"),
+        MeasureType::Extent => format!("Extent of {name}"),
+        MeasureType::Centroid => format!("Center of {name}"),
+        MeasureType::CountWithinRadius {
+            latitude,
+            longitude,
+            meters,
+        } => {
+            format!("Number of Records with {name} within {meters} m of ({latitude}, {longitude})")
+        }
+    }
+}
+
+/// English name of a dimension, e.g. "Month of Issue Date"
+pub fn dimension_title(dimension: &Dimension) -> String {
+    let name = &dimension.column.human_name;
+
+    match &dimension.transform {
+        None => name.clone(),
+        Some(DimensionTransform::DateTrunc(unit)) => format!(
+            "{:} of {name}",
+            match unit {
+                DateTruncUnit::Day => "Day",
+                DateTruncUnit::Week => "Week",
+                DateTruncUnit::Month => "Month",
+                DateTruncUnit::Quarter => "Quarter",
+                DateTruncUnit::Year => "Year",
+            }
+        ),
+        Some(DimensionTransform::Extract(part)) => format!(
+            "{:} of {name}",
+            match part {
+                DatePart::Year => "Year",
+                DatePart::Month => "Calendar Month",
+                DatePart::DayOfWeek => "Day of the Week",
+                DatePart::Hour => "Hour of the Day",
+            }
+        ),
+        Some(DimensionTransform::Bin(binning)) => match binning {
+            Binning::Fixed { width } => format!("{name} (in Steps of {width})"),
+            Binning::Range { low, high, count } => {
+                format!("{name} ({count} Buckets from {low} to {high})")
+            }
+            Binning::Log => format!("{name} (Order of Magnitude)"),
+        },
+        Some(DimensionTransform::SnapToGrid { size }) => format!("{name} (on a {size}° Grid)"),
+    }
+}
+
+fn window_measure_title(window_measure: &WindowMeasure) -> String {
+    match &window_measure.function {
+        WindowFunction::Rank => "Rank".to_string(),
+        WindowFunction::DenseRank => "Dense Rank".to_string(),
+        WindowFunction::RowNumber => "Row Number".to_string(),
+        WindowFunction::Sum(m) => format!("Running {:}", measure_title(m)),
+        WindowFunction::ShareOfTotal(m) => format!("Share of {:}", measure_title(m)),
+    }
+}
+
+// "A", "A and B", "A, B and C"
+fn english_list(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [init @ .., last] => format!("{:} and {last}", init.join(", ")),
+    }
+}
+
+fn order_by_title(order_by: &OrderBy, measures: &[Measure]) -> String {
+    let ascending = order_by.direction == OrderByDirection::Asc;
+
+    match &order_by.item {
+        OrderByItem::Measure(m) => {
+            let direction = if ascending { "lowest" } else { "highest" };
+            // No need to repeat the name if it's the main measure
+            if measures.first() == Some(m) {
+                format!("{direction} first")
+            } else {
+                format!("{direction} {:} first", measure_title(m))
+            }
+        }
+        OrderByItem::Dimension(d) => {
+            let direction = match (&d.transform, &d.column.data_type) {
+                (None, t) | (Some(DimensionTransform::DateTrunc(_)), t) if is_temporal(t) => {
+                    if ascending {
+                        "oldest first"
+                    } else {
+                        "newest first"
+                    }
+                }
+                (None, DataType::Text | DataType::Url) => {
+                    if ascending {
+                        "A to Z"
+                    } else {
+                        "Z to A"
+                    }
+                }
+                _ => {
+                    if ascending {
+                        "ascending"
+                    } else {
+                        "descending"
+                    }
+                }
+            };
+            format!("{:} {direction}", dimension_title(d))
+        }
+    }
+}
+
+fn limit_title<T: Syntax>(query: &Query<T>) -> Option<String> {
+    let limit = query.limit?;
+    Some(if query.order_by.is_empty() {
+        format!("first {limit} rows")
+    } else {
+        format!("top {limit}")
+    })
+}
+
+// "Top 3 Service by Number of Records for Each Status", for `top_n_per_group_query`
+fn top_n_per_group_title<T: Syntax>(query: &Query<T>) -> Option<String> {
+    let cte_name = match &query.source {
+        QuerySource::CommonTableExpression(name) => name,
+        QuerySource::Dataset => return None,
+    };
+    let ranked = &query.ctes.iter().find(|c| &c.name == cte_name)?.query;
+
+    let (group, measure) = match &ranked.window_measures[..] {
+        [WindowMeasure {
+            function: WindowFunction::RowNumber,
+            window,
+        }] => match (&window.partition_by[..], &window.order_by[..]) {
+            (
+                [group],
+                [OrderBy {
+                    item: OrderByItem::Measure(measure),
+                    ..
+                }],
+            ) => (group, measure),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let item = ranked.dimensions.iter().find(|d| *d != group)?;
+    let n = match &query.filter {
+        Some(Filter::Comparison {
+            operator: ComparisonOperator::LtEq,
+            value: Literal::Number(n),
+            ..
+        }) => n,
+        _ => return None,
+    };
+
+    Some(format!(
+        "Top {n} {:} by {:} for Each {:}",
+        dimension_title(item),
+        measure_title(measure),
+        dimension_title(group)
+    ))
+}
+
+/// A short English title for a query, e.g.
+/// "Average Latitude and Number of Records by Status, highest first (top 100)"
+pub fn query_title<T: Syntax>(query: &Query<T>) -> String {
+    if let Some(title) = top_n_per_group_title(query) {
+        return title;
+    }
+
+    let mut what: Vec<String> = query.measures.iter().map(measure_title).collect();
+    what.extend(query.window_measures.iter().map(window_measure_title));
+
+    let dimensions: Vec<String> = query.dimensions.iter().map(dimension_title).collect();
+
+    let mut title = match (what.is_empty(), dimensions.is_empty()) {
+        (false, false) => format!("{:} by {:}", english_list(&what), english_list(&dimensions)),
+        (false, true) => english_list(&what),
+        (true, false) => format!("Distinct {:}", english_list(&dimensions)),
+        // Plain columns
+        (true, true) => english_list(
+            &query
+                .columns
+                .iter()
+                .map(|c| c.human_name.clone())
+                .collect_vec(),
+        ),
+    };
+
+    if !query.order_by.is_empty() {
+        title.push_str(", ");
+        title.push_str(
+            &query
+                .order_by
+                .iter()
+                .map(|o| order_by_title(o, &query.measures))
+                .join(", then "),
+        );
+    }
+
+    if let Some(limit) = limit_title(query) {
+        title.push_str(&format!(" ({limit})"));
+    }
+
+    title
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_gen::query::{
+        build_dimensions, build_measures, random_query, top_n_per_group_query, Binning,
+        DateTruncUnit, DefaultSyntax, Dimension, DimensionTransform, Measure, MeasureType, OrderBy,
+        OrderByDirection, OrderByItem, Query,
+    };
+    use crate::query_gen::socrata::{Column, ColumnFormat, ColumnStats, DataType, Dataset};
+    use crate::query_gen::test_util::{default_params, edmonton_dataset};
+    use crate::query_gen::title::{dimension_title, measure_title, query_title};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn column(human_name: &str, data_type: DataType) -> Column {
+        Column {
+            human_name: human_name.to_string(),
+            pg_name: human_name.to_lowercase().replace(' ', "_"),
+            data_type,
+            format: ColumnFormat::default(),
+            stats: ColumnStats::default(),
+        }
+    }
+
+    fn get_dataset() -> Dataset {
+        Dataset {
+            domain: "data.edmonton.ca".to_string(),
+            socrata_id: "u7r4-acwa".to_string(),
+            name: "Building Permits".to_string(),
+            row_count: None,
+            columns: vec![
+                column("Status", DataType::Text),
+                column("Latitude", DataType::Double),
+                column("Issue Date", DataType::CalendarDate),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_measure_title() {
+        let latitude = column("Latitude", DataType::Double);
+        let issued = column("Issue Date", DataType::CalendarDate);
+        let measure = |type_, column: &Column| {
+            measure_title(&Measure {
+                type_,
+                column: Some(column.clone()),
+            })
+        };
+
+        assert_eq!(
+            measure_title(&Measure {
+                type_: MeasureType::Count,
+                column: None
+            }),
+            "Number of Records"
+        );
+        assert_eq!(
+            measure(MeasureType::CountDistinct, &latitude),
+            "Number of Distinct Latitude"
+        );
+        assert_eq!(
+            measure(MeasureType::CountNonNull, &latitude),
+            "Number of Records with Latitude"
+        );
+        assert_eq!(measure(MeasureType::Sum, &latitude), "Total Latitude");
+        assert_eq!(measure(MeasureType::Average, &latitude), "Average Latitude");
+        assert_eq!(measure(MeasureType::Min, &latitude), "Lowest Latitude");
+        assert_eq!(measure(MeasureType::Max, &latitude), "Highest Latitude");
+        assert_eq!(measure(MeasureType::Min, &issued), "Earliest Issue Date");
+        assert_eq!(measure(MeasureType::Max, &issued), "Latest Issue Date");
+        assert_eq!(measure(MeasureType::Median, &latitude), "Median Latitude");
+        assert_eq!(
+            measure(MeasureType::Percentile(90), &latitude),
+            "90th Percentile of Latitude"
+        );
+        assert_eq!(
+            measure(MeasureType::Percentile(1), &latitude),
+            "1st Percentile of Latitude"
+        );
+        assert_eq!(
+            measure(MeasureType::Percentile(12), &latitude),
+            "12th Percentile of Latitude"
+        );
+        assert_eq!(
+            measure(MeasureType::StddevSamp, &latitude),
+            "Standard Deviation of Latitude"
+        );
+        assert_eq!(
+            measure(MeasureType::VarSamp, &latitude),
+            "Variance of Latitude"
+        );
+    }
+
+    #[test]
+    fn test_dimension_title() {
+        let dimension = |column: Column, transform| {
+            dimension_title(&Dimension {
+                column,
+                transform,
+                alias: None,
+            })
+        };
+
+        assert_eq!(dimension(column("Status", DataType::Text), None), "Status");
+        assert_eq!(
+            dimension(
+                column("Issue Date", DataType::CalendarDate),
+                Some(DimensionTransform::DateTrunc(DateTruncUnit::Month))
+            ),
+            "Month of Issue Date"
+        );
+        assert_eq!(
+            dimension(
+                column("Latitude", DataType::Double),
+                Some(DimensionTransform::Bin(Binning::Fixed {
+                    width: "10".to_string()
+                }))
+            ),
+            "Latitude (in Steps of 10)"
+        );
+    }
+
+    #[test]
+    fn test_query_title() {
+        let dataset = get_dataset();
+        let status = Dimension {
+            column: dataset.columns[0].clone(),
+            transform: None,
+            alias: None,
+        };
+        let average_latitude = Measure {
+            type_: MeasureType::Average,
+            column: Some(dataset.columns[1].clone()),
+        };
+        let count = Measure {
+            type_: MeasureType::Count,
+            column: None,
+        };
+        let month = Dimension {
+            column: dataset.columns[2].clone(),
+            transform: Some(DimensionTransform::DateTrunc(DateTruncUnit::Month)),
+            alias: None,
+        };
+
+        let query = Query::new(
+            dataset.clone(),
+            vec![average_latitude.clone(), count.clone()],
+            vec![status.clone()],
+            vec![OrderBy {
+                item: OrderByItem::Measure(average_latitude),
+                direction: OrderByDirection::Desc,
+            }],
+            Some(100),
+            DefaultSyntax {},
+        );
+        assert_eq!(
+            query_title(&query),
+            "Average Latitude and Number of Records by Status, highest first (top 100)"
+        );
+
+        let query = Query::new(
+            dataset.clone(),
+            vec![count.clone()],
+            vec![month.clone(), status.clone()],
+            vec![
+                OrderBy {
+                    item: OrderByItem::Dimension(month),
+                    direction: OrderByDirection::Asc,
+                },
+                OrderBy {
+                    item: OrderByItem::Dimension(status.clone()),
+                    direction: OrderByDirection::Asc,
+                },
+            ],
+            None,
+            DefaultSyntax {},
+        );
+        assert_eq!(
+            query_title(&query),
+            "Number of Records by Month of Issue Date and Status, \
+             Month of Issue Date oldest first, then Status A to Z"
+        );
+
+        let query = Query::new(
+            dataset.clone(),
+            vec![],
+            vec![status.clone()],
+            vec![],
+            Some(100),
+            DefaultSyntax {},
+        );
+        assert_eq!(query_title(&query), "Distinct Status (first 100 rows)");
+
+        let item = Dimension {
+            column: dataset.columns[1].clone(),
+            transform: None,
+            alias: None,
+        };
+        let query = top_n_per_group_query(&dataset, &count, &status, &item, 3, DefaultSyntax {});
+        assert_eq!(
+            query_title(&query),
+            "Top 3 Latitude by Number of Records for Each Status"
+        );
+    }
+
+    #[test]
+    fn test_query_title_random() {
+        let dataset = edmonton_dataset();
+        let measures = build_measures(&dataset);
+        let dimensions = build_dimensions(&dataset);
+
+        for seed in 0..50 {
+            let query = random_query(
+                &mut StdRng::seed_from_u64(seed),
+                &dataset,
+                &measures,
+                &dimensions,
+                &default_params(),
+                DefaultSyntax {},
+            );
+
+            let title = query_title(&query);
+            assert!(!title.is_empty());
+            // Every measure gets mentioned
+            for measure in &query.measures {
+                assert!(title.contains(&measure_title(measure)));
+            }
+        }
+    }
+}